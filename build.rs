@@ -20,6 +20,14 @@ fn main() -> anyhow::Result<()> {
     embuild::build::CfgArgs::output_propagated("ESP_IDF")?;
     embuild::build::LinkArgs::output_propagated("ESP_IDF")?;
     build_static_assets(&PathBuf::from(env::var("OUT_DIR")?))?;
+    build_pin_map(&PathBuf::from(env::var("OUT_DIR")?))?;
+    // Read with env!() by the config_encrypt feature. A missing secret would leave a key that
+    // anyone can derive from the MAC, so that build fails here instead.
+    println!("cargo:rerun-if-env-changed=CONFIG_SECRET");
+    let secret = env::var("CONFIG_SECRET").unwrap_or_default();
+    if env::var_os("CARGO_FEATURE_CONFIG_ENCRYPT").is_some() && secret.is_empty() {
+        anyhow::bail!("The config_encrypt feature needs a non-empty CONFIG_SECRET at build time");
+    }
 
     Ok(())
 }
@@ -35,6 +43,70 @@ fn build_static_assets(out_dir: &Path) -> anyhow::Result<()> {
     Ok(())
 }
 
+// Board pin map in the order main() expects it. The chip feature picks the defaults,
+// e.g. PIN_SCK=2 overrides a single pin at build time.
+const PINS_ESP32_C3: [(&str, u8); 7] = [
+    ("BUTTON", 9),
+    ("SCK", 4),
+    ("MOSI", 6),
+    ("MISO", 5),
+    ("CS", 7),
+    ("GDO0", 10),
+    ("LED", 8),
+];
+const PINS_ESP_WROOM_32: [(&str, u8); 7] = [
+    ("BUTTON", 0),
+    ("SCK", 18),
+    ("MOSI", 23),
+    ("MISO", 19),
+    ("CS", 5),
+    ("GDO0", 4),
+    ("LED", 2),
+];
+
+fn build_pin_map(out_dir: &Path) -> anyhow::Result<()> {
+    let defaults = if env::var_os("CARGO_FEATURE_ESP32_C3").is_some() {
+        PINS_ESP32_C3
+    } else {
+        PINS_ESP_WROOM_32
+    };
+
+    let mut fields = String::new();
+    let mut summary = Vec::new();
+    let mut gpios = Vec::new();
+    for (name, default) in defaults {
+        let var = format!("PIN_{name}");
+        println!("cargo:rerun-if-env-changed={var}");
+        let gpio = match env::var(&var) {
+            Ok(v) => v
+                .trim()
+                .parse::<u8>()
+                .map_err(|e| anyhow::anyhow!("{var}={v} is not a GPIO number: {e}"))?,
+            Err(_) => default,
+        };
+        fields.push_str(&format!("            $pins.gpio{gpio}, // {name}\n"));
+        summary.push(format!("{name}=GPIO{gpio}"));
+        gpios.push(format!("(\"{name}\", {gpio})"));
+    }
+
+    // An unknown or duplicate GPIO fails to compile in main(), no runtime checks needed
+    let src = format!(
+        "pub const BOARD_PINS: &str = \"{}\";\n\n\
+         macro_rules! board_pins {{\n    ($pins:ident) => {{\n        (\n{fields}        )\n    }};\n}}\n",
+        summary.join(" ")
+    );
+    fs::write(out_dir.join("board_pins.rs"), src)?;
+
+    // The library side only needs the numbers, e.g. to keep status_led_gpio off the board pins
+    let src = format!(
+        "pub const BOARD_GPIOS: [(&str, u8); {}] = [{}];\n",
+        gpios.len(),
+        gpios.join(", ")
+    );
+    fs::write(out_dir.join("board_gpios.rs"), src)?;
+    Ok(())
+}
+
 fn compress_asset(src: &str, dst: PathBuf) -> anyhow::Result<()> {
     println!("cargo:rerun-if-changed={src}");
 
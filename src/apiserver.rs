@@ -5,16 +5,29 @@ use std::sync::atomic::Ordering;
 use axum::{
     Json, Router,
     body::Body,
-    extract::{Form, State, rejection::JsonRejection},
-    http::{Response, StatusCode, header},
+    extract::{
+        Form, Query, Request, State,
+        rejection::{FormRejection, JsonRejection},
+    },
+    http::{HeaderValue, Method, Response, StatusCode, header},
+    middleware::{self, Next},
     response::{Html, IntoResponse},
     routing::*,
 };
 pub use axum_macros::debug_handler;
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
 use embedded_svc::http::client::Client as HttpClient;
+use esp_idf_svc::{
+    http::client::Configuration as HttpConfiguration,
+    io::{Read, Write},
+};
 
 use crate::*;
 
+// The HTTP client and TLS handshake need far more than the default pthread stack
+const OTA_THREAD_STACK_SIZE: usize = 16 * 1024;
+const OTA_REBOOT_DELAY_SECS: u64 = 2;
+
 macro_rules! static_handler {
     ($fn_name:ident, $path:literal, $content_type:literal, $bytes:expr) => {
         async fn $fn_name(State(state): State<Arc<Pin<Box<MyState>>>>) -> Response<Body> {
@@ -53,6 +66,33 @@ static_handler!(
     include_bytes!(concat!(env!("OUT_DIR"), "/index.css.gz"))
 );
 
+/// Error reply of every API endpoint: `{"ok": false, "error": "<text>", "code": <HTTP status>}`.
+pub fn error_response(status: StatusCode, msg: impl std::fmt::Display) -> Response<Body> {
+    (
+        status,
+        Json(serde_json::json!({"ok": false, "error": msg.to_string(), "code": status.as_u16()})),
+    )
+        .into_response()
+}
+
+impl AppError {
+    /// HTTP status of this error as an API reply.
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            Self::BadRequest(_) | Self::AddrParse(_) | Self::Json(_) => StatusCode::BAD_REQUEST,
+            Self::NotReady(_) => StatusCode::SERVICE_UNAVAILABLE,
+            Self::Conflict(_) => StatusCode::CONFLICT,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response<Body> {
+        error_response(self.status_code(), self)
+    }
+}
+
 pub async fn run_api_server(state: Arc<Pin<Box<MyState>>>) -> AppResult<()> {
     loop {
         if *state.net_up.read().await {
@@ -66,14 +106,34 @@ pub async fn run_api_server(state: Arc<Pin<Box<MyState>>>) -> AppResult<()> {
 
     let app = Router::new()
         .route("/", get(get_index))
+        .route("/status", get(get_status))
         .route("/favicon.ico", get(get_favicon))
         .route("/form.js", get(get_form_js))
         .route("/index.css", get(get_index_css))
         .route("/uptime", get(get_uptime))
+        .route("/healthz", get(get_healthz))
+        .route("/readyz", get(get_readyz))
         .route("/conf", get(get_conf).post(set_conf).options(options))
         .route("/meter", get(get_meter))
+        .route("/api/reading", get(get_reading).options(options))
+        .route("/api/radio", get(get_radio).options(options))
+        .route("/api/history", get(get_history).options(options))
+        .route("/api/consumption", get(get_consumption).options(options))
+        .route("/api/lastframe", get(get_lastframe).options(options))
+        .route("/api/capture", get(get_capture).options(options))
+        .route("/api/stats", get(get_stats).options(options))
+        .route("/api/status", get(get_api_status).options(options))
+        .route("/api/inject", post(post_inject).options(options))
+        .route("/api/meterkey", post(post_meterkey).options(options))
+        .route("/api/config", get(get_api_config).post(set_conf).options(options))
         .route("/reset_conf", get(reset_conf))
+        .route("/api/reboot", post(post_reboot).options(options))
+        .route("/api/factory-reset", post(reset_conf).options(options))
         .route("/fw", post(update_fw).options(options))
+        .route("/api/ota", post(post_ota).options(options))
+        .layer(middleware::from_fn_with_state(state.clone(), http_auth))
+        // Outside auth, so a browser dashboard can also read a 401
+        .layer(middleware::from_fn_with_state(state.clone(), cors))
         .with_state(state);
     // .layer(TraceLayer::new_for_http());
 
@@ -82,16 +142,97 @@ pub async fn run_api_server(state: Arc<Pin<Box<MyState>>>) -> AppResult<()> {
     Ok(axum::serve(listener, app.into_make_service()).await?)
 }
 
+/// HTTP basic auth in front of every route, active only when `http_user` is set.
+async fn http_auth(State(state): State<Arc<Pin<Box<MyState>>>>, request: Request, next: Next) -> Response<Body> {
+    let (http_user, http_pass) = {
+        let config = state.config.read().await;
+        (config.http_user.clone(), config.http_pass.clone())
+    };
+    // CORS preflight requests never carry credentials
+    if http_user.is_empty() || request.method() == Method::OPTIONS {
+        return next.run(request).await;
+    }
+
+    let authorized = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(parse_basic_auth)
+        .is_some_and(|(user, pass)| {
+            // evaluate both so the timing does not tell which one was wrong
+            ct_eq(user.as_bytes(), http_user.as_bytes()) & ct_eq(pass.as_bytes(), http_pass.as_bytes())
+        });
+    if authorized {
+        return next.run(request).await;
+    }
+
+    let cnt = state.api_cnt.fetch_add(1, Ordering::Relaxed);
+    warn!(
+        "#{cnt} http_auth(): unauthorized {} {}",
+        request.method(),
+        request.uri().path()
+    );
+    (
+        [(
+            header::WWW_AUTHENTICATE,
+            "Basic realm=\"esp32multical21\", charset=\"UTF-8\"",
+        )],
+        error_response(StatusCode::UNAUTHORIZED, "Authentication required"),
+    )
+        .into_response()
+}
+
+/// CORS headers on the JSON API (`/api/*`) and on preflight responses, as configured by `cors_origin`.
+/// The HTML pages get none, and an empty `cors_origin` turns CORS off.
+async fn cors(State(state): State<Arc<Pin<Box<MyState>>>>, request: Request, next: Next) -> Response<Body> {
+    let applies = request.uri().path().starts_with("/api/") || request.method() == Method::OPTIONS;
+    let origin = state.config.read().await.cors_origin.clone();
+    let mut response = next.run(request).await;
+    if !applies || origin.is_empty() {
+        return response;
+    }
+
+    let Ok(value) = HeaderValue::from_str(&origin) else {
+        return response;
+    };
+    let headers = response.headers_mut();
+    headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, value);
+    // Browsers send basic auth cross-origin only to a named origin, never with "*"
+    if origin != "*" {
+        headers.insert(
+            header::ACCESS_CONTROL_ALLOW_CREDENTIALS,
+            HeaderValue::from_static("true"),
+        );
+    }
+    response
+}
+
+/// Decode an `Authorization: Basic ...` header value into (user, password).
+fn parse_basic_auth(value: &str) -> Option<(String, String)> {
+    let (scheme, encoded) = value.trim().split_once(' ')?;
+    if !scheme.eq_ignore_ascii_case("basic") {
+        return None;
+    }
+    let decoded = String::from_utf8(BASE64.decode(encoded.trim()).ok()?).ok()?;
+    let (user, pass) = decoded.split_once(':')?;
+    Some((user.to_string(), pass.to_string()))
+}
+
+/// Compare without bailing out at the first differing byte.
+fn ct_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
 pub async fn options(State(state): State<Arc<Pin<Box<MyState>>>>) -> Response<Body> {
     let cnt = state.api_cnt.fetch_add(1, Ordering::Relaxed);
     info!("#{cnt} options()");
 
+    // Access-Control-Allow-Origin is added by the cors layer
     (
         StatusCode::OK,
         [
-            (header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"),
-            (header::ACCESS_CONTROL_ALLOW_METHODS, "get,post"),
-            (header::ACCESS_CONTROL_ALLOW_HEADERS, "content-type"),
+            (header::ACCESS_CONTROL_ALLOW_METHODS, "GET, POST, OPTIONS"),
+            (header::ACCESS_CONTROL_ALLOW_HEADERS, "content-type, authorization"),
         ],
     )
         .into_response()
@@ -102,9 +243,23 @@ pub async fn get_index(State(state): State<Arc<Pin<Box<MyState>>>>) -> Response<
     info!("#{cnt} get_index()");
 
     let ota_slot = state.ota_slot.clone();
-    let config = state.config.read().await.clone();
-    let value_tuple: (&str, &dyn Any) = ("ota_slot", &ota_slot);
-    let index = match config.render_with_values(&value_tuple) {
+    let meter_key_status = match meter_key_bytes(&*state.nvs.read().await) {
+        Some(_) => "set",
+        None => "unset",
+    }
+    .to_string();
+    let config = state.config.read().await.redacted();
+    let config_status = if state.config_incomplete.load(Ordering::Relaxed) {
+        format!("Meter not configured: {CONFIG_INCOMPLETE_MSG} below. The radio is idle until then.")
+    } else {
+        String::new()
+    };
+    let values: [(&str, &dyn Any); 3] = [
+        ("ota_slot", &ota_slot),
+        ("meter_key_status", &meter_key_status),
+        ("config_status", &config_status),
+    ];
+    let index = match config.render_with_values(&values) {
         Err(e) => {
             let err_msg = format!("Index template error: {e:?}\n");
             error!("{err_msg}");
@@ -115,12 +270,152 @@ pub async fn get_index(State(state): State<Arc<Pin<Box<MyState>>>>) -> Response<
     (StatusCode::OK, Html(index)).into_response()
 }
 
+/// Read-only health overview, rendered from what is already in `MyState`.
+#[derive(Template)]
+#[template(path = "status.html.ask", escape = "html")]
+pub struct StatusPage {
+    /// (section title, rows of (label, value))
+    pub sections: Vec<(&'static str, Vec<(&'static str, String)>)>,
+}
+
+fn opt_to_string<T: ToString>(value: Option<T>) -> String {
+    value.map(|v| v.to_string()).unwrap_or_else(|| "-".into())
+}
+
+pub async fn get_status(State(state): State<Arc<Pin<Box<MyState>>>>) -> Response<Body> {
+    let cnt = state.api_cnt.fetch_add(1, Ordering::Relaxed);
+    info!("#{cnt} get_status()");
+
+    let last_reading = state.latest_data.borrow().as_ref().map(|r| r.timestamp_s.clone());
+    let last_header = *state.last_header.read().await;
+    let stats = &state.rx_stats;
+    let device = vec![
+        ("Firmware version", FW_VERSION.into()),
+        ("Hardware", HW_TARGET.into()),
+        ("OTA slot", state.ota_slot.clone()),
+        ("Uptime (s)", state.uptime.read().await.to_string()),
+        ("Reset reason", state.reset_reason.into()),
+        ("Previous reset reason", opt_to_string(state.prev_reset_reason)),
+        (
+            "Meter configured",
+            (!state.config_incomplete.load(Ordering::Relaxed)).to_string(),
+        ),
+        ("Hostname", state.hostname.read().await.clone()),
+        ("MAC address", state.my_mac_s.read().await.clone()),
+        ("IPv4 address", state.ip_addr.read().await.to_string()),
+        ("Network up", state.net_up.read().await.to_string()),
+        ("NTP synced", state.ntp_synced.read().await.to_string()),
+        ("Ping OK", opt_to_string(*state.ping_ok.read().await)),
+        ("Last counter reset", opt_to_string(state.last_reset_s().await)),
+    ];
+    let radio = vec![
+        ("Radio ok", opt_to_string(*state.radio_ok.read().await)),
+        (
+            "Antenna suspect",
+            state.rx_stats.antenna_suspect.load(Ordering::Relaxed).to_string(),
+        ),
+        (
+            "Meter quiet",
+            state.rx_stats.meter_quiet.load(Ordering::Relaxed).to_string(),
+        ),
+        (
+            "Calibration failed",
+            state.rx_stats.radio_cal_failed.load(Ordering::Relaxed).to_string(),
+        ),
+        ("Last RSSI (dBm)", opt_to_string(*state.last_rssi.read().await)),
+        ("Last LQI", opt_to_string(*state.last_lqi.read().await)),
+        ("Last reading", opt_to_string(last_reading)),
+        (
+            "Meter manufacturer",
+            opt_to_string(last_header.map(|h| h.manufacturer_code())),
+        ),
+        (
+            "Meter device type",
+            opt_to_string(last_header.map(|h| h.device_type_s())),
+        ),
+        (
+            "Meter security",
+            opt_to_string(state.last_security.read().await.map(|m| m.label())),
+        ),
+    ];
+    let reception = vec![
+        ("Frames", stats.frames.load(Ordering::Relaxed).to_string()),
+        (
+            "Frames (lifetime)",
+            stats.frames_lifetime.load(Ordering::Relaxed).to_string(),
+        ),
+        ("Decoded", stats.decoded.load(Ordering::Relaxed).to_string()),
+        (
+            "Preamble errors",
+            stats.preamble_errors.load(Ordering::Relaxed).to_string(),
+        ),
+        ("CRC errors", stats.crc_errors.load(Ordering::Relaxed).to_string()),
+        (
+            "Decrypt suspect (wrong key?)",
+            stats.decrypt_suspect.load(Ordering::Relaxed).to_string(),
+        ),
+        (
+            "Other meters",
+            stats.meter_id_mismatches.load(Ordering::Relaxed).to_string(),
+        ),
+        (
+            "Success ratio (%)",
+            opt_to_string(stats.success_ratio().map(|r| format!("{r:.0}"))),
+        ),
+        ("SPI errors", stats.spi_errors.load(Ordering::Relaxed).to_string()),
+        ("SPI resets", stats.spi_resets.load(Ordering::Relaxed).to_string()),
+    ];
+    let page = StatusPage {
+        sections: vec![("Device", device), ("Radio", radio), ("Reception", reception)],
+    };
+
+    match page.render() {
+        Ok(s) => (StatusCode::OK, Html(s)).into_response(),
+        Err(e) => {
+            let err_msg = format!("Status template error: {e:?}\n");
+            error!("{err_msg}");
+            (StatusCode::INTERNAL_SERVER_ERROR, err_msg).into_response()
+        }
+    }
+}
+
 pub async fn get_uptime(State(state): State<Arc<Pin<Box<MyState>>>>) -> (StatusCode, Json<Uptime>) {
     let cnt = state.api_cnt.fetch_add(1, Ordering::Relaxed);
     info!("#{cnt} get_uptime()");
 
     let uptime = *state.uptime.read().await;
-    (StatusCode::OK, Json(Uptime { uptime }))
+    let radio_ok = *state.radio_ok.read().await;
+    (StatusCode::OK, Json(Uptime { uptime, radio_ok }))
+}
+
+// Monitors poll these often, so they only log at debug level
+pub async fn get_healthz(State(state): State<Arc<Pin<Box<MyState>>>>) -> Response<Body> {
+    let cnt = state.api_cnt.fetch_add(1, Ordering::Relaxed);
+    debug!("#{cnt} get_healthz()");
+
+    (StatusCode::OK, Json(serde_json::json!({"ok": true}))).into_response()
+}
+
+pub async fn get_readyz(State(state): State<Arc<Pin<Box<MyState>>>>) -> Response<Body> {
+    let cnt = state.api_cnt.fetch_add(1, Ordering::Relaxed);
+    debug!("#{cnt} get_readyz()");
+
+    let mut reasons = Vec::new();
+    if !*state.net_up.read().await {
+        reasons.push("network down");
+    }
+    if state.config.read().await.require_ntp && !*state.ntp_synced.read().await {
+        reasons.push("NTP not synced");
+    }
+    if state.last_decoded.read().await.is_none() {
+        reasons.push("no reading received since boot");
+    }
+
+    if reasons.is_empty() {
+        (StatusCode::OK, Json(serde_json::json!({"ok": true}))).into_response()
+    } else {
+        AppError::NotReady(reasons.join(", ")).into_response()
+    }
 }
 
 pub async fn get_conf(State(state): State<Arc<Pin<Box<MyState>>>>) -> Response<Body> {
@@ -131,7 +426,7 @@ pub async fn get_conf(State(state): State<Arc<Pin<Box<MyState>>>>) -> Response<B
         StatusCode::OK,
         Json(serde_json::json!({
             "ok": true,
-            "config": state.config.read().await.clone(),
+            "config": state.config.read().await.redacted(),
         })),
     )
         .into_response()
@@ -141,12 +436,379 @@ pub async fn get_meter(State(state): State<Arc<Pin<Box<MyState>>>>) -> Response<
     let cnt = state.api_cnt.fetch_add(1, Ordering::Relaxed);
     info!("#{cnt} get_meter()");
 
-    match &*state.latest_data.read().await {
-        Some(reading) => (StatusCode::OK, Json(reading.clone())).into_response(),
+    let m3_decimals = state.config.read().await.m3_decimals;
+    match state.latest_data.borrow().clone() {
+        Some(reading) => (StatusCode::OK, Json(reading_value(&reading, m3_decimals))).into_response(),
         None => (StatusCode::OK, Json(serde_json::json!({"status": "no reading"}))).into_response(),
     }
 }
 
+pub async fn get_reading(State(state): State<Arc<Pin<Box<MyState>>>>) -> Response<Body> {
+    let cnt = state.api_cnt.fetch_add(1, Ordering::Relaxed);
+    info!("#{cnt} get_reading()");
+
+    let uptime = *state.uptime.read().await;
+    let ntp_synced = *state.ntp_synced.read().await;
+    let radio_ok = *state.radio_ok.read().await;
+    let m3_decimals = state.config.read().await.m3_decimals;
+    let reading = match &*state.latest_data.borrow() {
+        Some(reading) => reading_value(reading, m3_decimals),
+        None => {
+            return AppError::NotReady("no data yet".into()).into_response();
+        }
+    };
+
+    match reading {
+        serde_json::Value::Object(mut map) => {
+            map.insert("uptime".into(), uptime.into());
+            map.insert("fw_version".into(), FW_VERSION.into());
+            map.insert("ntp_synced".into(), ntp_synced.into());
+            map.insert("radio_ok".into(), radio_ok.into());
+            map.insert(
+                "antenna_suspect".into(),
+                state.rx_stats.antenna_suspect.load(Ordering::Relaxed).into(),
+            );
+            (StatusCode::OK, Json(serde_json::Value::Object(map))).into_response()
+        }
+        _ => {
+            let msg = "Cannot serialize meter reading";
+            error!("{msg}");
+            AppError::Message(msg.into()).into_response()
+        }
+    }
+}
+
+pub async fn get_history(
+    State(state): State<Arc<Pin<Box<MyState>>>>,
+    Query(query): Query<HistoryQuery>,
+) -> Response<Body> {
+    let cnt = state.api_cnt.fetch_add(1, Ordering::Relaxed);
+    info!("#{cnt} get_history()");
+
+    let limit = query.limit.unwrap_or(HISTORY_LEN);
+    let history = state
+        .history
+        .read()
+        .await
+        .iter()
+        .rev()
+        .take(limit)
+        .cloned()
+        .collect::<Vec<_>>();
+    (StatusCode::OK, Json(history)).into_response()
+}
+
+pub async fn get_consumption(State(state): State<Arc<Pin<Box<MyState>>>>) -> Response<Body> {
+    let cnt = state.api_cnt.fetch_add(1, Ordering::Relaxed);
+    info!("#{cnt} get_consumption()");
+
+    (StatusCode::OK, Json(state.consumption.read().await.to_json())).into_response()
+}
+
+pub async fn get_radio(State(state): State<Arc<Pin<Box<MyState>>>>) -> Response<Body> {
+    let cnt = state.api_cnt.fetch_add(1, Ordering::Relaxed);
+    info!("#{cnt} get_radio()");
+
+    let (reply_tx, reply_rx) = oneshot::channel();
+    if state.radio_cmd.try_send(RadioCommand::DumpRegisters(reply_tx)).is_err() {
+        return AppError::NotReady("radio command queue full".into()).into_response();
+    }
+
+    let snapshot = match timeout(Duration::from_secs(5), reply_rx).await {
+        Ok(Ok(Ok(snapshot))) => snapshot,
+        Ok(Ok(Err(e))) => {
+            let msg = format!("Radio register read failed: {e}");
+            error!("{msg}");
+            return AppError::Message(msg).into_response();
+        }
+        Ok(Err(_)) | Err(_) => {
+            return AppError::NotReady("radio not active".into()).into_response();
+        }
+    };
+
+    let registers = snapshot
+        .registers
+        .iter()
+        .map(|(addr, value)| (format!("0x{addr:02X}"), format!("0x{value:02X}").into()))
+        .collect::<serde_json::Map<String, serde_json::Value>>();
+    (
+        StatusCode::OK,
+        Json(serde_json::json!({
+            "registers": registers,
+            "rssi_dbm": snapshot.rssi_dbm,
+            "lqi": snapshot.lqi,
+        })),
+    )
+        .into_response()
+}
+
+pub async fn get_lastframe(State(state): State<Arc<Pin<Box<MyState>>>>) -> Response<Body> {
+    let cnt = state.api_cnt.fetch_add(1, Ordering::Relaxed);
+    info!("#{cnt} get_lastframe()");
+
+    let Some(frame) = state.last_frame.read().await.clone() else {
+        return AppError::NotReady("no frame yet".into()).into_response();
+    };
+
+    let hex = frame.raw.iter().map(|b| format!("{b:02X}")).collect::<String>();
+    (
+        StatusCode::OK,
+        Json(serde_json::json!({
+            "timestamp": frame.timestamp,
+            "len": frame.raw.len(),
+            "hex": hex,
+            "ci": frame_ci(&frame.raw).map(|ci| format!("0x{ci:02X}")),
+            "parsed": frame.parsed,
+            "rssi_dbm": frame.rssi_dbm,
+            "lqi": frame.lqi,
+        })),
+    )
+        .into_response()
+}
+
+/// The capture ring as text, one frame per line, oldest first.
+pub async fn get_capture(State(state): State<Arc<Pin<Box<MyState>>>>) -> Response<Body> {
+    let cnt = state.api_cnt.fetch_add(1, Ordering::Relaxed);
+    info!("#{cnt} get_capture()");
+
+    let mut out = String::from("# esp32multical21 capture: timestamp rssi_dbm lqi parsed hex\n");
+    for frame in state.capture.read().await.iter() {
+        let hex = frame.raw.iter().map(|b| format!("{b:02X}")).collect::<String>();
+        out.push_str(&format!(
+            "{} {} {} {} {hex}\n",
+            frame.timestamp,
+            opt_to_string(frame.rssi_dbm),
+            opt_to_string(frame.lqi),
+            frame.parsed
+        ));
+    }
+    (
+        StatusCode::OK,
+        [
+            (header::CONTENT_TYPE, "text/plain; charset=utf-8"),
+            (header::CONTENT_DISPOSITION, "attachment; filename=\"capture.txt\""),
+        ],
+        out,
+    )
+        .into_response()
+}
+
+pub async fn get_stats(State(state): State<Arc<Pin<Box<MyState>>>>) -> Response<Body> {
+    let cnt = state.api_cnt.fetch_add(1, Ordering::Relaxed);
+    info!("#{cnt} get_stats()");
+
+    (StatusCode::OK, Json(state.rx_stats.to_json())).into_response()
+}
+
+pub async fn get_api_status(State(state): State<Arc<Pin<Box<MyState>>>>) -> Response<Body> {
+    let cnt = state.api_cnt.fetch_add(1, Ordering::Relaxed);
+    info!("#{cnt} get_api_status()");
+
+    let last_header = *state.last_header.read().await;
+    (
+        StatusCode::OK,
+        Json(serde_json::json!({
+            "fw_version": FW_VERSION,
+            "uptime": *state.uptime.read().await,
+            "reset_reason": state.reset_reason,
+            "prev_reset_reason": state.prev_reset_reason,
+            "config_incomplete": state.config_incomplete.load(Ordering::Relaxed),
+            "ping_ok": *state.ping_ok.read().await,
+            "last_reset": state.last_reset_s().await,
+            "meter_manufacturer": last_header.map(|h| h.manufacturer_code()),
+            "meter_device_type": last_header.map(|h| h.device_type_s()),
+            "meter_security": state.last_security.read().await.map(|m| m.label()),
+            "ota": state.ota.read().await.clone(),
+        })),
+    )
+        .into_response()
+}
+
+pub async fn post_inject(
+    State(state): State<Arc<Pin<Box<MyState>>>>,
+    inject_payload: Result<Json<InjectFrame>, JsonRejection>,
+) -> Response<Body> {
+    let cnt = state.api_cnt.fetch_add(1, Ordering::Relaxed);
+    info!("#{cnt} post_inject()");
+
+    let (enabled, credentials) = {
+        let config = state.config.read().await;
+        (
+            config.debug_inject,
+            config.meter_id_bytes().is_some() && meter_key_bytes(&*state.nvs.read().await).is_some(),
+        )
+    };
+    if !enabled {
+        let msg = "Frame injection is disabled, set debug_inject to enable it";
+        error!("{msg}");
+        return error_response(StatusCode::FORBIDDEN, msg);
+    }
+
+    let Json(inject) = match inject_payload {
+        Ok(inject) => inject,
+        Err(e) => {
+            let msg = format!("Invalid inject JSON: {e}");
+            error!("{msg}");
+            return AppError::BadRequest(msg).into_response();
+        }
+    };
+
+    let hex: String = inject.hex.split_whitespace().collect();
+    let Some(payload) = parse_hex(&hex).filter(|b| !b.is_empty()) else {
+        let msg = "Invalid frame: hex must be a non-empty, even number of hex digits";
+        error!("{msg}");
+        return AppError::BadRequest(msg.into()).into_response();
+    };
+
+    if !credentials {
+        let msg = "No valid meter_id and/or meter_key configured";
+        error!("{msg}");
+        return AppError::BadRequest(msg.into()).into_response();
+    }
+
+    // The radio task owns the decoding pipeline, hand the frame over and wait for the outcome
+    let (reply_tx, reply_rx) = oneshot::channel();
+    if state
+        .radio_cmd
+        .try_send(RadioCommand::Inject(payload, reply_tx))
+        .is_err()
+    {
+        let msg = "Radio command queue full";
+        error!("{msg}");
+        return AppError::NotReady(msg.into()).into_response();
+    }
+
+    match timeout(Duration::from_secs(5), reply_rx).await {
+        Ok(Ok(Ok(reading))) => (
+            StatusCode::OK,
+            Json(serde_json::json!({"ok": true, "reading": reading})),
+        )
+            .into_response(),
+        Ok(Ok(Err(e))) => {
+            let msg = format!("Frame did not yield a valid reading: {e:?}");
+            error!("{msg}");
+            error_response(StatusCode::UNPROCESSABLE_ENTITY, msg)
+        }
+        Ok(Err(_)) | Err(_) => {
+            let msg = "Radio task not active";
+            error!("{msg}");
+            AppError::NotReady(msg.into()).into_response()
+        }
+    }
+}
+
+/// Replace the meter key, and optionally the meter ID, without touching the rest of the config or rebooting.
+pub async fn post_meterkey(
+    State(state): State<Arc<Pin<Box<MyState>>>>,
+    update_payload: Result<Json<MeterKeyUpdate>, JsonRejection>,
+) -> Response<Body> {
+    let cnt = state.api_cnt.fetch_add(1, Ordering::Relaxed);
+    info!("#{cnt} post_meterkey()");
+
+    let Json(update) = match update_payload {
+        Ok(update) => update,
+        Err(e) => {
+            let msg = format!("Invalid meter key JSON: {e}");
+            error!("{msg}");
+            return AppError::BadRequest(msg).into_response();
+        }
+    };
+
+    let meter_id = clean_hex(&update.meter_id);
+    if !meter_id.is_empty()
+        && let Some(problem) = hex_problem(&meter_id, 8)
+    {
+        let msg = format!("Meter ID error: {problem} (spaces and dashes are ignored)");
+        error!("{}", msg);
+        return AppError::BadRequest(msg).into_response();
+    }
+
+    if let Some(problem) = hex_problem(&update.meter_key, 32) {
+        let msg = format!("Meter key error: {problem} (spaces and dashes are ignored)");
+        error!("{}", msg);
+        return AppError::BadRequest(msg).into_response();
+    }
+    let Some(key) = parse_meter_key(&update.meter_key) else {
+        let msg = "Meter key error: cannot decode";
+        error!("{}", msg);
+        return AppError::BadRequest(msg.into()).into_response();
+    };
+
+    // Only a changed meter_id needs the config blob rewritten
+    let mut config = state.config.read().await.clone();
+    let new_config = !meter_id.is_empty() && meter_id != config.meter_id;
+    if !meter_id.is_empty() {
+        config.meter_id = meter_id;
+    }
+    let Some(id) = config.meter_id_bytes() else {
+        let msg = "Meter ID error: none configured, include meter_id";
+        error!("{}", msg);
+        return AppError::BadRequest(msg.into()).into_response();
+    };
+
+    {
+        let mut nvs = state.nvs.write().await;
+        let mut saved = save_meter_key(&mut nvs, &key);
+        if saved.is_ok() && new_config {
+            saved = config.to_nvs(&mut nvs);
+        }
+        if let Err(e) = saved {
+            let msg = format!("{e:?}");
+            error!("{msg}");
+            return AppError::Message(msg).into_response();
+        }
+    }
+    if new_config {
+        state.config.write().await.meter_id = config.meter_id;
+    }
+
+    // The radio task holds the credentials in use, hand the new ones over
+    let (reply_tx, reply_rx) = oneshot::channel();
+    if state
+        .radio_cmd
+        .try_send(RadioCommand::Rekey(id, key, reply_tx))
+        .is_err()
+    {
+        let msg = "Meter key saved, but the radio command queue is full; reboot to apply";
+        error!("{msg}");
+        return AppError::NotReady(msg.into()).into_response();
+    }
+
+    match timeout(Duration::from_secs(5), reply_rx).await {
+        Ok(Ok(Ok(()))) => (
+            StatusCode::OK,
+            Json(serde_json::json!({"ok": true, "message": "Meter key saved, radio restarted"})),
+        )
+            .into_response(),
+        Ok(Ok(Err(e))) => {
+            let msg = format!("Meter key saved, but the radio restart failed: {e}");
+            error!("{msg}");
+            AppError::Message(msg).into_response()
+        }
+        Ok(Err(_)) | Err(_) => {
+            let msg = "Meter key saved, but the radio task is not active; reboot to apply";
+            error!("{msg}");
+            AppError::NotReady(msg.into()).into_response()
+        }
+    }
+}
+
+pub async fn get_api_config(
+    State(state): State<Arc<Pin<Box<MyState>>>>,
+    Query(query): Query<ConfigQuery>,
+) -> Response<Body> {
+    let cnt = state.api_cnt.fetch_add(1, Ordering::Relaxed);
+    info!("#{cnt} get_api_config()");
+
+    let config = state.config.read().await.clone();
+    let config = if query.secrets.unwrap_or(false) {
+        config.without_http_pass()
+    } else {
+        config.redacted()
+    };
+    (StatusCode::OK, Json(config)).into_response()
+}
+
 pub async fn set_conf(
     State(state): State<Arc<Pin<Box<MyState>>>>,
     config_payload: Result<Json<MyConfig>, JsonRejection>,
@@ -159,22 +821,173 @@ pub async fn set_conf(
         Err(e) => {
             let msg = format!("Invalid config JSON: {e}");
             error!("{msg}");
-            return (
-                StatusCode::BAD_REQUEST,
-                Json(serde_json::json!({"ok": false, "message": msg})),
-            )
-                .into_response();
+            return AppError::BadRequest(msg).into_response();
         }
     };
 
-    if config.v4mask > 30 {
-        let msg = "IPv4 mask error: bits must be between 0..30";
+    if !config.v4dhcp && !config.static_ipv4_valid() {
+        let msg = "Static IPv4 error: address must be set and mask bits must be between 1..32";
         error!("{}", msg);
-        return (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(serde_json::json!({"ok": false, "message": msg})),
-        )
-            .into_response();
+        return AppError::BadRequest(msg.into()).into_response();
+    }
+
+    if !config.hostname.is_empty() && !valid_hostname(&config.hostname) {
+        let msg = "Hostname error: 1..32 chars of a-z, 0-9 and '-', not starting or ending with '-'";
+        error!("{}", msg);
+        return AppError::BadRequest(msg.into()).into_response();
+    }
+
+    // Check the template with the longest possible expansions, the real values are known at connect time
+    let sample_topic = expand_mqtt_topic(
+        &config.mqtt_topic,
+        "esp32multical21_000000000000",
+        "000000000000",
+        &"x".repeat(32),
+    );
+    if config.mqtt_enable && !valid_mqtt_topic(&sample_topic) {
+        let msg = format!(
+            "MQTT topic error: up to {MQTT_TOPIC_MAX_LEN} chars after expanding {{id}}, {{mac}} and {{hostname}}, no '+', '#' or other placeholders"
+        );
+        error!("{msg}");
+        return AppError::BadRequest(msg).into_response();
+    }
+
+    if config.esphome_overrides_map().is_none() {
+        let msg = format!(
+            "ESPHome overrides error: entries field=accuracy,icon separated by ';', accuracy 0..{ESPHOME_ACCURACY_MAX}, icon like mdi:water"
+        );
+        error!("{msg}");
+        return AppError::BadRequest(msg).into_response();
+    }
+
+    if !(TZ_OFFSET_MIN..=TZ_OFFSET_MAX).contains(&config.tz_offset_minutes) {
+        let msg = format!("Timezone offset error: minutes must be between {TZ_OFFSET_MIN}..{TZ_OFFSET_MAX}");
+        error!("{}", msg);
+        return AppError::BadRequest(msg).into_response();
+    }
+
+    if !(radio::WMBUS_FREQ_MIN_HZ..=radio::WMBUS_FREQ_MAX_HZ).contains(&(config.radio_freq_hz as u64)) {
+        let msg = format!(
+            "Radio frequency error: Hz must be between {}..{}",
+            radio::WMBUS_FREQ_MIN_HZ,
+            radio::WMBUS_FREQ_MAX_HZ
+        );
+        error!("{}", msg);
+        return AppError::BadRequest(msg).into_response();
+    }
+
+    if radio::parse_register_overrides(&config.radio_registers).is_none() {
+        let msg = "Radio register error: must be comma separated hex reg=val pairs, reg 00..2E";
+        error!("{}", msg);
+        return AppError::BadRequest(msg.into()).into_response();
+    }
+
+    if !(radio::SPI_BAUD_MIN_KHZ..=radio::SPI_BAUD_MAX_KHZ).contains(&config.spi_baud_khz) {
+        let msg = format!(
+            "SPI clock error: kHz must be between {}..{}",
+            radio::SPI_BAUD_MIN_KHZ,
+            radio::SPI_BAUD_MAX_KHZ
+        );
+        error!("{}", msg);
+        return AppError::BadRequest(msg).into_response();
+    }
+
+    if config.mqtt_qos > MQTT_QOS_MAX {
+        let msg = format!("MQTT QoS error: must be 0..{MQTT_QOS_MAX}");
+        error!("{}", msg);
+        return AppError::BadRequest(msg).into_response();
+    }
+
+    if config.m3_decimals > M3_DECIMALS_MAX {
+        let msg = format!("m³ decimals error: must be 0..{M3_DECIMALS_MAX}");
+        error!("{}", msg);
+        return AppError::BadRequest(msg).into_response();
+    }
+
+    config.meter_id = clean_hex(&config.meter_id);
+    if !config.meter_id.is_empty()
+        && let Some(problem) = hex_problem(&config.meter_id, 8)
+    {
+        let msg = format!("Meter ID error: {problem} (spaces and dashes are ignored)");
+        error!("{}", msg);
+        return AppError::BadRequest(msg).into_response();
+    }
+
+    config.meter_key = clean_hex(&config.meter_key);
+    if !config.meter_key.is_empty()
+        && let Some(problem) = hex_problem(&config.meter_key, 32)
+    {
+        let msg = format!("Meter key error: {problem} (spaces and dashes are ignored)");
+        error!("{}", msg);
+        return AppError::BadRequest(msg).into_response();
+    }
+
+    if !config.syslog_host.is_empty() && config.syslog_port == 0 {
+        let msg = "Syslog port error: must not be 0";
+        error!("{}", msg);
+        return AppError::BadRequest(msg.into()).into_response();
+    }
+
+    if config.capture_depth > CAPTURE_DEPTH_MAX {
+        let msg = format!("Capture depth error: must be between 0..{CAPTURE_DEPTH_MAX}");
+        error!("{}", msg);
+        return AppError::BadRequest(msg).into_response();
+    }
+
+    if config.radio_watchdog_secs < RADIO_WATCHDOG_MIN_SECS {
+        let msg = format!("Radio watchdog error: must be at least {RADIO_WATCHDOG_MIN_SECS} s");
+        error!("{}", msg);
+        return AppError::BadRequest(msg).into_response();
+    }
+
+    if !(RESET_HOLD_SECS_MIN..=RESET_HOLD_SECS_MAX).contains(&config.reset_hold_secs) {
+        let msg = format!("Reset hold error: must be between {RESET_HOLD_SECS_MIN}..{RESET_HOLD_SECS_MAX} s");
+        error!("{}", msg);
+        return AppError::BadRequest(msg).into_response();
+    }
+
+    if config.status_led_gpio >= 0 && config.status_led_gpio as i32 >= esp_idf_sys::gpio_num_t_GPIO_NUM_MAX {
+        let msg = format!(
+            "Status LED error: GPIO must be -1 (none) or 0..{}",
+            esp_idf_sys::gpio_num_t_GPIO_NUM_MAX - 1
+        );
+        error!("{}", msg);
+        return AppError::BadRequest(msg).into_response();
+    }
+
+    if let Some((name, _)) = BOARD_GPIOS.iter().find(|(_, g)| *g as i8 == config.status_led_gpio) {
+        let msg = format!(
+            "Status LED error: GPIO{} is the board's {name} pin",
+            config.status_led_gpio
+        );
+        error!("{}", msg);
+        return AppError::BadRequest(msg).into_response();
+    }
+
+    if config.esphome_port == 0 || config.esphome_port == HTTP_API_PORT {
+        let msg = format!("ESPHome port error: must not be 0 or the HTTP port {HTTP_API_PORT}");
+        error!("{}", msg);
+        return AppError::BadRequest(msg).into_response();
+    }
+
+    config.keep_secrets(&*state.config.read().await);
+
+    if !config.esphome_psk.is_empty() && parse_noise_psk(&config.esphome_psk).is_none() {
+        let msg = "ESPHome encryption key error: must be 32 bytes encoded as base64";
+        error!("{}", msg);
+        return AppError::BadRequest(msg.into()).into_response();
+    }
+
+    if config.http_user.contains(':') || (!config.http_user.is_empty() && config.http_pass.is_empty()) {
+        let msg = "HTTP auth error: username must not contain ':' and needs a password";
+        error!("{}", msg);
+        return AppError::BadRequest(msg.into()).into_response();
+    }
+
+    if !config.cors_origin.is_empty() && !valid_cors_origin(&config.cors_origin) {
+        let msg = "CORS origin error: use *, or scheme and host like https://dash.example.com";
+        error!("{}", msg);
+        return AppError::BadRequest(msg.into()).into_response();
     }
 
     if !config.wifi_wpa2ent {
@@ -195,10 +1008,29 @@ pub async fn set_conf(
     Box::pin(save_conf(state, config)).await
 }
 
+pub async fn post_reboot(State(state): State<Arc<Pin<Box<MyState>>>>) -> Response<Body> {
+    let cnt = state.api_cnt.fetch_add(1, Ordering::Relaxed);
+    info!("#{cnt} post_reboot()");
+
+    info!("Reboot requested over HTTP. Resetting soon...");
+    *state.reset.write().await = true;
+    (
+        StatusCode::OK,
+        Json(serde_json::json!({"ok": true, "message": "Rebooting"})),
+    )
+        .into_response()
+}
+
 pub async fn reset_conf(State(state): State<Arc<Pin<Box<MyState>>>>) -> Response<Body> {
     let cnt = state.api_cnt.fetch_add(1, Ordering::Relaxed);
     info!("#{cnt} reset_conf()");
 
+    if let Err(e) = clear_meter_key(&mut *state.nvs.write().await) {
+        let msg = format!("{e:?}");
+        error!("{msg}");
+        return AppError::Message(msg).into_response();
+    }
+
     info!("Saving  default config to nvs...");
     Box::pin(save_conf(state, MyConfig::default())).await
 }
@@ -218,42 +1050,164 @@ async fn save_conf(state: Arc<Pin<Box<MyState>>>, config: MyConfig) -> Response<
         Err(e) => {
             let msg = format!("Nvs write error: {e:?}");
             error!("{}", msg);
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(serde_json::json!({"ok": false, "message": msg})),
-            )
-                .into_response()
+            AppError::Message(msg).into_response()
         }
     }
 }
-async fn update_fw(
+pub async fn post_ota(
     State(state): State<Arc<Pin<Box<MyState>>>>,
-    Form(fw_update): Form<UpdateFirmware>,
+    fw_payload: Result<Json<UpdateFirmware>, JsonRejection>,
 ) -> Response<Body> {
     let cnt = state.api_cnt.fetch_add(1, Ordering::Relaxed);
-    info!("#{cnt} update_fw()");
+    info!("#{cnt} post_ota()");
+
+    let Json(fw_update) = match fw_payload {
+        Ok(fw_update) => fw_update,
+        Err(e) => {
+            let msg = format!("Invalid OTA JSON: {e}");
+            error!("{msg}");
+            return AppError::BadRequest(msg).into_response();
+        }
+    };
+
+    ota_started_response(&state, &fw_update.url).await
+}
 
-    info!("Firmware update: \n{fw_update:#?}");
-    let url = fw_update.url.to_owned();
+/// Reply to an OTA request: 202 once the update runs in the background, the error otherwise.
+async fn ota_started_response(state: &Arc<Pin<Box<MyState>>>, url: &str) -> Response<Body> {
+    match start_ota(state, url).await {
+        Ok(()) => (
+            StatusCode::ACCEPTED,
+            Json(serde_json::json!({"ok": true, "message": "Firmware update started, progress in /api/status"})),
+        )
+            .into_response(),
+        Err(e) => {
+            error!("Firmware update not started: {e}");
+            e.into_response()
+        }
+    }
+}
+
+/// Start a firmware update from `url` on its own thread, so neither the download nor the flash
+/// writes block the executor. Progress is kept in `state.ota`; a successful update reboots.
+pub async fn start_ota(state: &Arc<Pin<Box<MyState>>>, url: &str) -> AppResult<()> {
+    {
+        let mut ota = state.ota.write().await;
+        if ota.running {
+            return Err(AppError::Conflict("OTA update already in progress".into()));
+        }
+        *ota = OtaProgress {
+            running: true,
+            ..Default::default()
+        };
+    }
+
+    let thread_state = state.clone();
+    let thread_url = url.to_string();
+    let spawned = std::thread::Builder::new()
+        .name("ota".into())
+        .stack_size(OTA_THREAD_STACK_SIZE)
+        .spawn(move || run_ota(thread_state, thread_url));
+    if let Err(e) = spawned {
+        let mut ota = state.ota.write().await;
+        ota.running = false;
+        ota.error = Some(format!("OTA thread failed to start: {e}"));
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+fn run_ota(state: Arc<Pin<Box<MyState>>>, url: String) {
+    info!("Firmware update from {url}");
+    let result = ota_from_url(&url, |bytes| state.ota.blocking_write().bytes = bytes);
+    let mut ota = state.ota.blocking_write();
+    match result {
+        Ok(size) => {
+            info!("Update done, {size} bytes written. Resetting soon...");
+            ota.done = true;
+            drop(ota);
+            // Leave pollers a moment to see the update finish
+            std::thread::sleep(Duration::from_secs(OTA_REBOOT_DELAY_SECS));
+            *state.reset.blocking_write() = true;
+        }
+        Err(e) => {
+            error!("Firmware update failed: {e}");
+            ota.running = false;
+            ota.error = Some(e.to_string());
+        }
+    }
+}
 
-    let mut ota = EspOta::new().unwrap();
-    let mut client = HttpClient::wrap(EspHttpConnection::new(&Default::default()).unwrap());
-    let req = client.get(&url).unwrap();
-    let resp = req.submit().unwrap();
+/// Download a firmware image into the inactive OTA slot and mark it as the next boot partition.
+/// `progress` gets the byte count after every chunk. Returns the number of bytes written.
+pub fn ota_from_url(url: &str, mut progress: impl FnMut(usize)) -> AppResult<usize> {
+    let mut client = HttpClient::wrap(EspHttpConnection::new(&HttpConfiguration {
+        crt_bundle_attach: Some(esp_idf_sys::esp_crt_bundle_attach),
+        ..Default::default()
+    })?);
+    let req = client
+        .get(url)
+        .map_err(|e| AppError::Message(format!("HTTP request error: {e:?}")))?;
+    let mut resp = req
+        .submit()
+        .map_err(|e| AppError::Message(format!("HTTP submit error: {e:?}")))?;
     if resp.status() != 200 {
-        let emsg = format!("Firmware download failed: HTTP {}", resp.status());
-        error!("{emsg}");
-        return (StatusCode::BAD_GATEWAY, emsg).into_response();
+        return Err(AppError::Message(format!("HTTP {}", resp.status())));
     }
 
-    let update_src = Box::new(resp);
-    let mut update = ota.initiate_update().unwrap();
+    let mut ota = EspOta::new()?;
+    let mut update = ota.initiate_update()?;
     let mut buffer = [0_u8; 8192];
+    let mut size = 0;
 
-    io::utils::copy(update_src, &mut update, &mut buffer).unwrap();
-    update.complete().unwrap();
+    loop {
+        let n = match resp.read(&mut buffer) {
+            Ok(n) => n,
+            Err(e) => {
+                update.abort()?;
+                return Err(AppError::Message(format!("HTTP read error: {e:?}")));
+            }
+        };
+        if n == 0 {
+            break;
+        }
+        if let Err(e) = update.write_all(&buffer[..n]) {
+            update.abort()?;
+            return Err(AppError::Message(format!("OTA write error: {e:?}")));
+        }
+        size += n;
+        progress(size);
+        if size % (64 * 1024) < n {
+            info!("OTA progress: {} KiB", size / 1024);
+        }
+    }
+
+    if size == 0 {
+        update.abort()?;
+        return Err(AppError::Message("Empty firmware image".into()));
+    }
+
+    // complete() validates the image and sets it as the boot partition
+    update.complete()?;
+    Ok(size)
+}
+
+async fn update_fw(
+    State(state): State<Arc<Pin<Box<MyState>>>>,
+    fw_payload: Result<Form<UpdateFirmware>, FormRejection>,
+) -> Response<Body> {
+    let cnt = state.api_cnt.fetch_add(1, Ordering::Relaxed);
+    info!("#{cnt} update_fw()");
+
+    let Form(fw_update) = match fw_payload {
+        Ok(fw_update) => fw_update,
+        Err(e) => {
+            let msg = format!("Invalid firmware update form: {e}");
+            error!("{msg}");
+            return AppError::BadRequest(msg).into_response();
+        }
+    };
 
-    info!("Update done. Restarting...");
-    esp_idf_svc::hal::reset::restart();
+    ota_started_response(&state, &fw_update.url).await
 }
 // EOF
@@ -2,20 +2,30 @@
 
 #![warn(clippy::large_futures)]
 
+use std::net::ToSocketAddrs;
+
 use esp_idf_svc::{eventloop::EspSystemEventLoop, ping, timer::EspTaskTimerService};
 use esp_idf_sys::esp;
 use esp32multical21::*;
 
-const CONFIG_RESET_COUNT: i32 = 9;
 const BUTTON_POLL_MS: u64 = 500;
 const BUTTON_BLINK_MS: u64 = 500;
-const BUTTON_COUNTDOWN_STEP_MS: u64 = 500;
+const BUTTON_COUNTDOWN_STEP_MS: u64 = 1000;
+// A press must still read low after this long, so GPIO noise cannot start the reset gesture
+const BUTTON_DEBOUNCE_MS: u64 = 50;
+// AP mode after a failed WiFi connection reboots to try the station again if nobody saved a config meanwhile
+const AP_PROVISION_SECS: u64 = 900;
+// lwIP lookups block for the whole resolver timeout, they run on their own thread
+const PING_DNS_TIMEOUT_SECS: u64 = 10;
+const PING_DNS_STACK_SIZE: usize = 6 * 1024;
+
+include!(concat!(env!("OUT_DIR"), "/board_pins.rs"));
 
 // esp_app_desc!();
 
 fn main() -> anyhow::Result<()> {
     esp_idf_sys::link_patches();
-    esp_idf_svc::log::EspLogger::initialize_default();
+    init_logger();
 
     #[allow(clippy::needless_update)]
     let config = esp_idf_sys::esp_vfs_eventfd_config_t {
@@ -52,11 +62,16 @@ fn main() -> anyhow::Result<()> {
     };
     info!("My config:\n{config:#?}");
 
-    let ap_mode = matches!(nvs.get_u8(AP_MODE_NVS_KEY)?, Some(1));
-    if ap_mode {
+    let ap_request = nvs.get_u8(AP_MODE_NVS_KEY)?;
+    if ap_request.is_some() {
         info!("One-shot AP mode requested for this boot.");
         let _ = nvs.remove(AP_MODE_NVS_KEY)?;
     }
+    if config.wifi_ssid.is_empty() {
+        info!("No WiFi configured, starting AP mode for setup.");
+    }
+    let ap_mode = ap_request.is_some() || config.wifi_ssid.is_empty();
+    let ap_retry_wifi = ap_request == Some(AP_MODE_PROVISION) && !config.wifi_ssid.is_empty();
 
     let ota_slot = {
         let mut ota = EspOta::new()?;
@@ -70,28 +85,10 @@ fn main() -> anyhow::Result<()> {
     let peripherals = Peripherals::take()?;
     let pins = peripherals.pins;
 
-    #[cfg(feature = "esp32-c3")]
-    #[rustfmt::skip]
-    let io_pins = (
-        pins.gpio9,  // BOOT
-        pins.gpio4,  // SCK
-        pins.gpio6,  // MOSI
-        pins.gpio5,  // MISO
-        pins.gpio7,  // CS
-        pins.gpio10, // GDO0
-        pins.gpio8,  // LED
-    );
-    #[cfg(all(not(feature = "esp32-c3"), feature = "esp-wroom-32"))]
-    #[rustfmt::skip]
-    let io_pins = (
-        pins.gpio0,  // BOOT
-        pins.gpio18, // SCK
-        pins.gpio23, // MOSI
-        pins.gpio19, // MISO
-        pins.gpio5,  // CS
-        pins.gpio4,  // GDO0
-        pins.gpio2,  // LED
-    );
+    // (BUTTON, SCK, MOSI, MISO, CS, GDO0, LED), generated by build.rs from
+    // the chip feature and optional PIN_* build-time overrides
+    info!("Board pins: {BOARD_PINS}");
+    let io_pins = board_pins!(pins);
 
     let button = PinDriver::input(io_pins.0.degrade_input(), Pull::Up)?;
     let driver = spi::SpiDriver::new(
@@ -101,13 +98,27 @@ fn main() -> anyhow::Result<()> {
         Some(io_pins.3),
         &spi::SpiDriverConfig::new(),
     )?;
-    let spi_cfg = spi::config::Config::new().baudrate(Hertz(4_000_000));
-    let dev = spi::SpiDeviceDriver::new(&driver, Some(io_pins.4), &spi_cfg)?;
+    let spi_baud_khz = config
+        .spi_baud_khz
+        .clamp(radio::SPI_BAUD_MIN_KHZ, radio::SPI_BAUD_MAX_KHZ);
+    info!("CC1101 SPI clock: {spi_baud_khz} kHz");
+    let spi_cfg = spi::config::Config::new().baudrate(Hertz(spi_baud_khz as u32 * 1000));
+    // The radio recreates its SPI device if the bus stops responding, so the device is
+    // built from the CS pin number
+    let cs_pin = io_pins.4.pin();
+    let driver = &driver;
+    let spi_factory: radio::SpiFactory = Box::new(move || {
+        // SAFETY: the CS pin is not used outside this factory, and the only caller besides
+        // Cc1101Radio::new() is recover_spi(), which sets its device to None first. That drops
+        // the previous SpiDeviceDriver with its CS pin, so two owners of the pin never coexist.
+        let cs = unsafe { AnyOutputPin::steal(cs_pin) };
+        spi::SpiDeviceDriver::new(driver, Some(cs), &spi_cfg)
+    });
     let gdo0 = PinDriver::input(io_pins.5.degrade_input(), Pull::Floating)?;
     let led = PinDriver::output(io_pins.6.degrade_output())?;
 
     // Create CC1101 radio
-    let radio = Cc1101Radio::new(dev, gdo0);
+    let radio = Cc1101Radio::new(spi_factory, gdo0)?;
 
     let wifidriver = WifiDriver::new(peripherals.modem, sysloop.clone(), Some(nvs_default_partition))?;
 
@@ -130,6 +141,7 @@ fn main() -> anyhow::Result<()> {
                     result = Box::pin(poll_reset(shared_state.clone(), button)) => { error!("poll_reset() ended: {result:?}"); }
                     result = Box::pin(run_api_server(shared_state.clone())) => { error!("run_api_server() ended: {result:?}"); }
                     result = Box::pin(wifi_loop.run(wifidriver, sysloop, timer)) => { error!("wifi_loop.run() ended: {result:?}"); }
+                    _ = sleep(Duration::from_secs(AP_PROVISION_SECS)), if ap_retry_wifi => { info!("No new config in AP mode, retrying WiFi."); }
                 };
             } else {
                 info!("Entering main loop...");
@@ -137,9 +149,13 @@ fn main() -> anyhow::Result<()> {
                     result = Box::pin(poll_reset(shared_state.clone(), button)) => { error!("poll_reset() ended: {result:?}"); }
                     result = Box::pin(read_meter(shared_state.clone(), radio)) => { error!("poll_sensors() ended: {result:?}"); }
                     result = Box::pin(run_mqtt(shared_state.clone())) => { error!("run_mqtt() ended: {result:?}"); }
+                    result = Box::pin(run_influx(shared_state.clone())) => { error!("run_influx() ended: {result:?}"); }
+                    result = Box::pin(run_webhook(shared_state.clone())) => { error!("run_webhook() ended: {result:?}"); }
                     result = Box::pin(run_api_server(shared_state.clone())) => { error!("run_api_server() ended: {result:?}"); }
                     result = Box::pin(run_esphome_api(shared_state.clone())) => { error!("run_esphome_api() ended: {result:?}"); }
                     result = Box::pin(run_mdns(shared_state.clone())) => { error!("run_mdns() ended: {result:?}"); }
+                    result = Box::pin(run_syslog(shared_state.clone())) => { error!("run_syslog() ended: {result:?}"); }
+                    result = Box::pin(run_status_led(shared_state.clone())) => { error!("run_status_led() ended: {result:?}"); }
                     result = Box::pin(wifi_loop.run(wifidriver, sysloop, timer)) => { error!("wifi_loop.run() ended: {result:?}"); }
                     result = Box::pin(pinger(shared_state.clone())) => { error!("pinger() ended: {result:?}"); }
                 };
@@ -162,14 +178,21 @@ async fn poll_reset(mut state: Arc<Pin<Box<MyState>>>, button: PinDriver<'_, Inp
             uptime += secs;
             uptime_ms %= 1000;
             *state.uptime.write().await = uptime;
+            state.persist_frames_lifetime(false).await;
         }
 
         if *state.reset.read().await {
+            state.persist_frames_lifetime(true).await;
             esp_idf_hal::reset::restart();
         }
 
         if button.is_low() {
-            Box::pin(reset_button(&mut state, &button)).await?;
+            sleep(Duration::from_millis(BUTTON_DEBOUNCE_MS)).await;
+            if button.is_low() {
+                Box::pin(reset_button(&mut state, &button)).await?;
+            } else {
+                warn!("Button glitch ignored");
+            }
         }
     }
 }
@@ -178,16 +201,18 @@ async fn reset_button<'a>(
     state: &mut Arc<std::pin::Pin<Box<MyState>>>,
     button: &PinDriver<'a, Input>,
 ) -> AppResult<()> {
-    let mut reset_cnt = CONFIG_RESET_COUNT;
+    let mut reset_cnt = state
+        .config
+        .read()
+        .await
+        .reset_hold_secs
+        .clamp(RESET_HOLD_SECS_MIN, RESET_HOLD_SECS_MAX);
     let mut blink_on = true;
     let mut blink_elapsed_ms = 0;
     let mut countdown_elapsed_ms = 0;
 
     while button.is_low() {
         if countdown_elapsed_ms == 0 {
-            let msg = format!("Reset? {reset_cnt}");
-            error!("{msg}");
-
             if reset_cnt == 0 {
                 error!("Factory resetting...");
                 state.led_on().await?;
@@ -196,12 +221,14 @@ async fn reset_button<'a>(
                     let new_config = MyConfig::default();
                     let mut nvs = state.nvs.write().await;
                     new_config.to_nvs(&mut nvs)?;
+                    clear_meter_key(&mut nvs)?;
                     let _ = nvs.remove(AP_MODE_NVS_KEY)?;
                 }
                 sleep(Duration::from_millis(2000)).await;
                 esp_idf_hal::reset::restart();
             }
 
+            warn!("Factory reset in {reset_cnt} s, release the button to cancel");
             reset_cnt -= 1;
         }
 
@@ -220,7 +247,7 @@ async fn reset_button<'a>(
     } else {
         state.led_off().await?;
         info!("Short button press, rebooting into AP mode for manual configuration.");
-        state.request_ap_mode_on_next_boot().await?;
+        state.request_ap_mode_on_next_boot(AP_MODE_BUTTON).await?;
         sleep(Duration::from_millis(250)).await;
         esp_idf_hal::reset::restart();
     }
@@ -237,11 +264,33 @@ async fn run_mdns(state: Arc<Pin<Box<MyState>>>) -> AppResult<()> {
         sleep(Duration::from_millis(500)).await;
     }
 
+    let hostname = state.hostname.read().await.clone();
     let mut mdns = EspMdns::take()?;
-    mdns.set_hostname("esp32multical21")?;
+    mdns.set_hostname(&hostname)?;
     mdns.set_instance_name(format!("Multical21 Water Meter ({})", FW_VERSION))?;
-    mdns.add_service(None, "_http", "_tcp", 80, &[])?;
-    info!("mDNS started: http://esp32multical21.local/");
+    mdns.add_service(None, "_http", "_tcp", HTTP_API_PORT, &[])?;
+
+    let (esphome_enable, esphome_port) = {
+        let config = state.config.read().await;
+        (config.esphome_enable, config.esphome_port)
+    };
+    if esphome_enable {
+        // Home Assistant discovers ESPHome nodes by this service and its mac TXT record
+        let mac = state.my_mac_s.read().await.replace(':', "").to_lowercase();
+        mdns.add_service(
+            Some(&hostname),
+            "_esphomelib",
+            "_tcp",
+            esphome_port,
+            &[
+                ("version", FW_VERSION),
+                ("mac", &mac),
+                ("platform", "ESP32"),
+                ("network", "wifi"),
+            ],
+        )?;
+    }
+    info!("mDNS started: http://{hostname}.local/");
 
     // Keep mDNS alive forever
     loop {
@@ -250,32 +299,124 @@ async fn run_mdns(state: Arc<Pin<Box<MyState>>>) -> AppResult<()> {
 }
 
 async fn pinger(state: Arc<Pin<Box<MyState>>>) -> AppResult<()> {
+    let (ping_enable, ping_host, ping_reboot) = {
+        let config = state.config.read().await;
+        (config.ping_enable, config.ping_host.clone(), config.ping_reboot)
+    };
+    if !ping_enable {
+        info!("Ping watchdog is disabled by configuration.");
+        // we cannot return, otherwise tokio::select in main() will exit
+        loop {
+            sleep(Duration::from_secs(3600)).await;
+        }
+    }
+
+    let mut ping_dns = PingHostCache::default();
     loop {
         sleep(Duration::from_secs(300)).await;
 
-        if let Some(ping_ip) = *state.ping_ip.read().await {
-            let if_idx = *state.if_index.read().await;
-            if if_idx > 0 {
-                info!("Starting ping {ping_ip} (if_idx {if_idx})");
-                let conf = ping::Configuration {
-                    count: 3,
-                    interval: Duration::from_secs(1),
-                    timeout: Duration::from_secs(1),
-                    data_size: 64,
-                    tos: 0,
-                };
-                let mut ping = ping::EspPing::new(if_idx);
-                let res = ping.ping(ping_ip, &conf)?;
-                info!("Pinger result: {res:?}");
-                if res.received == 0 {
-                    error!("Ping failed, rebooting.");
-                    sleep(Duration::from_millis(2000)).await;
-                    esp_idf_hal::reset::restart();
-                }
-            } else {
-                error!("No if_index. wat?");
+        // WiFi reconnects on its own, do not reboot while it is down
+        if !*state.net_up.read().await {
+            info!("Network is down, skipping ping.");
+            continue;
+        }
+
+        let Some(ping_ip) = ping_target(&state, &ping_host, &mut ping_dns).await else {
+            continue;
+        };
+        let if_idx = *state.if_index.read().await;
+        if if_idx == 0 {
+            error!("No if_index. wat?");
+            continue;
+        }
+
+        info!("Starting ping {ping_ip} (if_idx {if_idx})");
+        let conf = ping::Configuration {
+            count: 3,
+            interval: Duration::from_secs(1),
+            timeout: Duration::from_secs(1),
+            data_size: 64,
+            tos: 0,
+        };
+        let mut ping = ping::EspPing::new(if_idx);
+        let res = ping.ping(ping_ip, &conf)?;
+        info!("Pinger result: {res:?}");
+        *state.ping_ok.write().await = Some(res.received > 0);
+        if res.received == 0 {
+            if ping_reboot {
+                error!("Ping failed, rebooting.");
+                sleep(Duration::from_millis(2000)).await;
+                esp_idf_hal::reset::restart();
+            }
+            warn!("Ping {ping_ip} failed, reboot disabled by configuration.");
+        }
+    }
+}
+
+/// Last address of the ping host, and a lookup that was still running when its round gave up on it.
+#[derive(Default)]
+struct PingHostCache {
+    ip: Option<net::Ipv4Addr>,
+    lookup: Option<oneshot::Receiver<Option<net::Ipv4Addr>>>,
+}
+
+/// The configured ping host, resolved on every round so DNS changes are followed,
+/// or the gateway when none is configured. A failed or slow lookup falls back to the last address.
+async fn ping_target(
+    state: &Arc<Pin<Box<MyState>>>,
+    ping_host: &str,
+    cache: &mut PingHostCache,
+) -> Option<net::Ipv4Addr> {
+    if ping_host.is_empty() {
+        return *state.ping_ip.read().await;
+    }
+    if let Ok(ip) = ping_host.parse() {
+        return Some(ip);
+    }
+
+    // A lookup left over from the previous round is awaited instead of starting another thread
+    let mut lookup = match cache.lookup.take() {
+        Some(lookup) => lookup,
+        None => {
+            let (tx, rx) = oneshot::channel();
+            let host = ping_host.to_string();
+            let spawned = std::thread::Builder::new()
+                .name("ping-dns".into())
+                .stack_size(PING_DNS_STACK_SIZE)
+                .spawn(move || tx.send(resolve_ipv4(&host)).ok());
+            if let Err(e) = spawned {
+                error!("Cannot start ping host lookup: {e}");
+                return cache.ip;
             }
+            rx
+        }
+    };
+
+    match timeout(Duration::from_secs(PING_DNS_TIMEOUT_SECS), &mut lookup).await {
+        Ok(Ok(Some(ip))) => {
+            cache.ip = Some(ip);
+            return Some(ip);
+        }
+        Ok(_) => error!("Cannot resolve ping host {ping_host}."),
+        Err(_) => {
+            error!("Ping host {ping_host} not resolved in {PING_DNS_TIMEOUT_SECS} s.");
+            cache.lookup = Some(lookup);
         }
     }
+    match cache.ip {
+        Some(ip) => warn!("Pinging the last address {ip} of {ping_host}."),
+        // Not a reachability failure of the target itself, do not reboot over it
+        None => error!("No address for ping host {ping_host}, skipping ping."),
+    }
+    cache.ip
+}
+
+fn resolve_ipv4(host: &str) -> Option<net::Ipv4Addr> {
+    (host, 0).to_socket_addrs().ok().and_then(|mut addrs| {
+        addrs.find_map(|a| match a.ip() {
+            net::IpAddr::V4(ip) => Some(ip),
+            net::IpAddr::V6(_) => None,
+        })
+    })
 }
 // EOF
@@ -1,12 +1,126 @@
 // config.rs
 
+use std::collections::BTreeMap;
+
 use crc::{CRC_32_ISCSI, Crc};
 
 use crate::*;
 
-pub const NVS_BUF_SIZE: usize = 256;
 pub const HTTP_API_PORT: u16 = 80;
+pub const TZ_OFFSET_MIN: i16 = -720;
+pub const TZ_OFFSET_MAX: i16 = 840;
+pub const RADIO_WATCHDOG_MIN_SECS: u16 = 120;
+pub const MQTT_TOPIC_MAX_LEN: usize = 128;
+pub const ESPHOME_ACCURACY_MAX: i32 = 6;
+pub const CAPTURE_DEPTH_MAX: u16 = 128;
+pub const RESET_HOLD_SECS_MIN: u8 = 3;
+pub const RESET_HOLD_SECS_MAX: u8 = 60;
+pub const M3_DECIMALS_MAX: u8 = 6;
+pub const MQTT_QOS_MAX: u8 = 2;
 const CONFIG_NAME: &str = "cfg";
+const METER_KEY_NVS_KEY: &str = "meter_key";
+const LITERS_PER_GALLON: f32 = 3.785_411_8;
+
+/// Unit of the converted volume fields published over MQTT and ESPHome.
+/// The raw liter and m³ fields are always published as well.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VolumeUnit {
+    Liters,
+    #[default]
+    M3,
+    Gallons,
+}
+
+impl VolumeUnit {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Liters => "liters",
+            Self::M3 => "m3",
+            Self::Gallons => "gallons",
+        }
+    }
+
+    /// Unit of measurement string understood by Home Assistant for water volumes.
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Self::Liters => "L",
+            Self::M3 => "m³",
+            Self::Gallons => "gal",
+        }
+    }
+
+    /// Decimals that keep the meter's 1 liter resolution visible, `m3_decimals` for m³.
+    pub fn accuracy(self, m3_decimals: u8) -> i32 {
+        match self {
+            Self::Liters => 0,
+            Self::M3 => m3_decimals as i32,
+            Self::Gallons => 2,
+        }
+    }
+
+    /// Convert a volume in liters to this unit.
+    pub fn convert(self, liters: u32) -> f32 {
+        match self {
+            Self::Liters => liters as f32,
+            Self::M3 => liters as f32 / 1000.0,
+            Self::Gallons => liters as f32 / LITERS_PER_GALLON,
+        }
+    }
+
+    /// Volume in this unit as JSON, m³ rounded to `m3_decimals`.
+    fn convert_json(self, liters: u32, m3_decimals: u8) -> serde_json::Value {
+        match self {
+            Self::M3 => round_decimals(self.convert(liters), m3_decimals).into(),
+            _ => self.convert(liters).into(),
+        }
+    }
+
+    /// Serialize a reading with `total_volume`, `month_start_volume` and `month_consumption_volume`
+    /// converted to this unit, plus the `volume_unit` itself.
+    pub fn reading_json(self, reading: &MeterReading, m3_decimals: u8) -> serde_json::Value {
+        let mut value = reading_value(reading, m3_decimals);
+        if let Some(map) = value.as_object_mut() {
+            map.insert("total_volume".into(), self.convert_json(reading.total_l, m3_decimals));
+            map.insert(
+                "month_start_volume".into(),
+                self.convert_json(reading.month_start_l, m3_decimals),
+            );
+            map.insert(
+                "month_consumption_volume".into(),
+                self.convert_json(reading.month_consumption_l, m3_decimals),
+            );
+            map.insert("volume_unit".into(), self.symbol().into());
+            map.insert("low_battery".into(), info_low_battery(reading.info_codes).into());
+        }
+        value
+    }
+}
+
+/// Round to `decimals` places. As f64, so JSON shows no f32 conversion noise.
+pub fn round_decimals(value: f32, decimals: u8) -> f64 {
+    let scale = 10f64.powi(decimals as i32);
+    (value as f64 * scale).round() / scale
+}
+
+/// Serialize a reading with `total_m3` and `month_start_m3` rounded to `m3_decimals`.
+pub fn reading_value(reading: &MeterReading, m3_decimals: u8) -> serde_json::Value {
+    let mut value = serde_json::json!(reading);
+    if let Some(map) = value.as_object_mut() {
+        map.insert("total_m3".into(), round_decimals(reading.total_m3, m3_decimals).into());
+        map.insert(
+            "month_start_m3".into(),
+            round_decimals(reading.month_start_m3, m3_decimals).into(),
+        );
+    }
+    value
+}
+
+impl std::fmt::Display for VolumeUnit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
 
 #[derive(Clone, Debug, Serialize, Deserialize, Template)]
 #[template(path = "index.html.ask", escape = "html")]
@@ -15,6 +129,12 @@ pub struct MyConfig {
     pub wifi_pass: String,
     pub wifi_wpa2ent: bool,
     pub wifi_username: String,
+    pub wifi_max_retries: u8,
+    pub wifi_reboot_mins: u16,
+    pub hostname: String,
+    pub http_user: String,
+    pub http_pass: String,
+    pub cors_origin: String,
 
     pub v4dhcp: bool,
     pub v4addr: net::Ipv4Addr,
@@ -23,13 +143,58 @@ pub struct MyConfig {
     pub dns1: net::Ipv4Addr,
     pub dns2: net::Ipv4Addr,
 
+    pub ntp_server: String,
+    pub require_ntp: bool,
+    pub ntp_timeout_secs: u32,
+    pub tz_offset_minutes: i16,
+    pub syslog_host: String,
+    pub syslog_port: u16,
+    pub ping_enable: bool,
+    pub ping_host: String,
+    pub ping_reboot: bool,
+
     pub esphome_enable: bool,
+    pub esphome_port: u16,
+    pub esphome_psk: String,
+    pub esphome_overrides: String,
     pub mqtt_enable: bool,
     pub mqtt_url: String,
+    pub mqtt_user: String,
+    pub mqtt_pass: String,
+    pub mqtt_tls_insecure: bool,
     pub mqtt_topic: String,
+    pub mqtt_retain_meter: bool,
+    pub mqtt_retain_uptime: bool,
+    pub mqtt_qos: u8,
+    pub mqtt_clean_session: bool,
+    pub volume_unit: VolumeUnit,
+    pub m3_decimals: u8,
+    pub temp_report_delta: u8,
+
+    pub influx_url: String,
+    pub influx_org: String,
+    pub influx_bucket: String,
+    pub influx_token: String,
+
+    pub webhook_url: String,
+    pub webhook_header: String,
 
     pub meter_id: String,
+    /// Write-only: a new key to store, always empty when read back. The key itself
+    /// lives under its own nvs key, see `meter_key_bytes()`.
     pub meter_key: String,
+    pub radio_freq_hz: u32,
+    pub spi_baud_khz: u16,
+    pub radio_registers: String,
+    pub dedup_window_secs: u16,
+    pub radio_watchdog_secs: u16,
+    pub reset_hold_secs: u8,
+    pub radio_max_restarts: u8,
+    pub radio_quiet_alarm_secs: u32,
+    pub status_led_gpio: i8,
+    pub debug_inject: bool,
+    pub capture_depth: u16,
+    pub low_power: bool,
 }
 
 impl Default for MyConfig {
@@ -39,8 +204,17 @@ impl Default for MyConfig {
             wifi_pass: option_env!("WIFI_PASS").unwrap_or("").into(),
             wifi_wpa2ent: false,
             wifi_username: String::new(),
+            wifi_max_retries: 10,
+            wifi_reboot_mins: 30,
+            hostname: String::new(),
+            http_user: String::new(),
+            http_pass: String::new(),
+            cors_origin: "*".into(),
 
             esphome_enable: false,
+            esphome_port: ESPHOME_API_PORT,
+            esphome_psk: String::new(),
+            esphome_overrides: String::new(),
             v4dhcp: true,
             v4addr: net::Ipv4Addr::new(0, 0, 0, 0),
             v4mask: 0,
@@ -48,18 +222,187 @@ impl Default for MyConfig {
             dns1: net::Ipv4Addr::new(0, 0, 0, 0),
             dns2: net::Ipv4Addr::new(0, 0, 0, 0),
 
+            ntp_server: "pool.ntp.org".into(),
+            require_ntp: true,
+            ntp_timeout_secs: 120,
+            tz_offset_minutes: 0,
+            syslog_host: String::new(),
+            syslog_port: 514,
+            ping_enable: true,
+            ping_host: String::new(),
+            ping_reboot: true,
+
             mqtt_enable: false,
             mqtt_url: "mqtt://mqtt.local:1883".into(),
+            mqtt_user: String::new(),
+            mqtt_pass: String::new(),
+            mqtt_tls_insecure: false,
             mqtt_topic: "watermeter".into(),
+            mqtt_retain_meter: true,
+            mqtt_retain_uptime: false,
+            mqtt_qos: 1,
+            mqtt_clean_session: true,
+            volume_unit: VolumeUnit::default(),
+            m3_decimals: 3,
+            temp_report_delta: 0,
+
+            influx_url: String::new(),
+            influx_org: String::new(),
+            influx_bucket: String::new(),
+            influx_token: String::new(),
+
+            webhook_url: String::new(),
+            webhook_header: String::new(),
 
             meter_id: String::new(),
             meter_key: String::new(),
+            radio_freq_hz: radio::WMBUS_FREQ_HZ as u32,
+            spi_baud_khz: radio::SPI_BAUD_KHZ,
+            radio_registers: String::new(),
+            dedup_window_secs: 10,
+            radio_watchdog_secs: 600,
+            reset_hold_secs: 5,
+            radio_max_restarts: 0,
+            radio_quiet_alarm_secs: 3600,
+            status_led_gpio: -1,
+            debug_inject: false,
+            capture_depth: 0,
+            low_power: false,
+        }
+    }
+}
+
+/// Hostname usable for DHCP and mDNS: 1..=32 chars of `a-z`, `0-9` and `-`,
+/// not starting or ending with `-`.
+pub fn valid_hostname(hostname: &str) -> bool {
+    (1..=32).contains(&hostname.len())
+        && !hostname.starts_with('-')
+        && !hostname.ends_with('-')
+        && hostname
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+}
+
+/// A CORS origin: `*`, or a scheme and host without path like `https://dash.example.com:8080`.
+pub fn valid_cors_origin(origin: &str) -> bool {
+    if origin == "*" {
+        return true;
+    }
+    let Some(host) = origin
+        .strip_prefix("http://")
+        .or_else(|| origin.strip_prefix("https://"))
+    else {
+        return false;
+    };
+    !host.is_empty()
+        && host
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | ':' | '[' | ']'))
+}
+
+/// Expand the `{id}`, `{mac}` and `{hostname}` placeholders of an MQTT topic template.
+pub fn expand_mqtt_topic(template: &str, id: &str, mac: &str, hostname: &str) -> String {
+    template
+        .replace("{id}", id)
+        .replace("{mac}", mac)
+        .replace("{hostname}", hostname)
+}
+
+/// A topic we can publish to: not empty, no wildcards, no NUL and no unexpanded placeholders.
+pub fn valid_mqtt_topic(topic: &str) -> bool {
+    (1..=MQTT_TOPIC_MAX_LEN).contains(&topic.len()) && !topic.contains(['+', '#', '\0', '{', '}'])
+}
+
+/// Read a whole nvs blob, sized by querying its length first.
+pub fn nvs_get_blob_vec(nvs: &nvs::EspNvs<nvs::NvsDefault>, key: &str) -> Result<Option<Vec<u8>>, EspError> {
+    let Some(len) = nvs.blob_len(key)? else {
+        return Ok(None);
+    };
+    let mut buf = vec![0u8; len];
+    let len = nvs.get_blob(key, &mut buf)?.map(|b| b.len());
+    Ok(len.map(|len| {
+        buf.truncate(len);
+        buf
+    }))
+}
+
+/// Parse a meter_key hex string (32 hex chars) to 16 bytes.
+pub fn parse_meter_key(hex: &str) -> Option<[u8; 16]> {
+    let hex = clean_hex(hex);
+    if hex.len() != 32 {
+        return None;
+    }
+    parse_hex(&hex)?.try_into().ok()
+}
+
+/// The stored AES meter key, `None` when unset.
+pub fn meter_key_bytes(nvs: &nvs::EspNvs<nvs::NvsDefault>) -> Option<[u8; 16]> {
+    match nvs_get_blob_vec(nvs, METER_KEY_NVS_KEY) {
+        Ok(Some(blob)) => unseal(blob)?.try_into().ok(),
+        Ok(None) => None,
+        Err(e) => {
+            error!("Cannot read meter_key from nvs: {e:?}");
+            None
+        }
+    }
+}
+
+/// Rewrite a plaintext blob encrypted when the `config_encrypt` feature is on.
+fn seal_plaintext_blob(nvs: &mut nvs::EspNvs<nvs::NvsDefault>, key: &str) {
+    if !cfg!(feature = "config_encrypt") {
+        return;
+    }
+    match nvs_get_blob_vec(nvs, key) {
+        Ok(Some(blob)) if !is_sealed(&blob) => {
+            info!("Encrypting nvs blob {key}...");
+            if let Err(e) = nvs.set_blob(key, &seal(&blob)) {
+                error!("Cannot encrypt nvs blob {key}: {e:?}");
+            }
         }
+        Ok(_) => {}
+        Err(e) => error!("Nvs read error {e:?}"),
     }
 }
 
-fn parse_hex(hex: &str) -> Option<Vec<u8>> {
-    if !hex.len().is_multiple_of(2) {
+/// Store the AES meter key under its own nvs key, the config blob is not touched.
+pub fn save_meter_key(nvs: &mut nvs::EspNvs<nvs::NvsDefault>, key: &[u8; 16]) -> AppResult<()> {
+    nvs.set_blob(METER_KEY_NVS_KEY, &seal(key))
+        .map_err(|e| AppError::Message(format!("Cannot save meter_key to nvs: {e:?}")))?;
+    info!("Meter key saved.");
+    Ok(())
+}
+
+/// Forget the stored meter key, for factory reset.
+pub fn clear_meter_key(nvs: &mut nvs::EspNvs<nvs::NvsDefault>) -> AppResult<()> {
+    nvs.remove(METER_KEY_NVS_KEY)
+        .map_err(|e| AppError::Message(format!("Cannot remove meter_key from nvs: {e:?}")))?;
+    Ok(())
+}
+
+/// Hex as copied off a meter label or a supplier sheet: drop spaces and dashes, lowercase the rest.
+pub fn clean_hex(hex: &str) -> String {
+    hex.chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+/// What is wrong with `hex` as a value of `want` hex chars, `None` if it is fine.
+pub fn hex_problem(hex: &str, want: usize) -> Option<String> {
+    let hex = clean_hex(hex);
+    if let Some(c) = hex.chars().find(|c| !c.is_ascii_hexdigit()) {
+        return Some(format!("'{c}' is not a hex digit"));
+    }
+    if hex.len() != want {
+        return Some(format!("must be {want} hex chars, got {}", hex.len()));
+    }
+    None
+}
+
+/// Decode hex to bytes, ignoring spaces and dashes and accepting either case.
+pub fn parse_hex(hex: &str) -> Option<Vec<u8>> {
+    let hex = clean_hex(hex);
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
         return None;
     }
     (0..hex.len())
@@ -68,33 +411,117 @@ fn parse_hex(hex: &str) -> Option<Vec<u8>> {
         .collect()
 }
 
+/// Per-field ESPHome entity presentation set in `esphome_overrides`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EntityOverride {
+    pub accuracy: Option<i32>,
+    pub icon: Option<String>,
+}
+
 impl MyConfig {
-    /// Parse meter_id hex string (8 hex chars) to 4 bytes in wire order.
+    /// Parse `esphome_overrides`: entries `field=accuracy,icon` separated by `;` or whitespace,
+    /// either part may be empty, e.g. `total_m3=3,mdi:water;flow_temp=0;rssi=,mdi:antenna`.
+    /// Returns `None` if any entry is malformed.
+    pub fn esphome_overrides_map(&self) -> Option<BTreeMap<String, EntityOverride>> {
+        let mut map = BTreeMap::new();
+        for entry in self
+            .esphome_overrides
+            .split(|c: char| c == ';' || c.is_whitespace())
+            .filter(|e| !e.is_empty())
+        {
+            let (field, spec) = entry.split_once('=')?;
+            let (accuracy, icon) = spec.split_once(',').unwrap_or((spec, ""));
+            let accuracy = match accuracy {
+                "" => None,
+                a => {
+                    let a = a.parse::<i32>().ok()?;
+                    if !(0..=ESPHOME_ACCURACY_MAX).contains(&a) {
+                        return None;
+                    }
+                    Some(a)
+                }
+            };
+            let icon = match icon {
+                "" => None,
+                i if i.contains(':') => Some(i.to_string()),
+                _ => return None,
+            };
+            if field.is_empty() || (accuracy.is_none() && icon.is_none()) {
+                return None;
+            }
+            map.insert(field.to_string(), EntityOverride { accuracy, icon });
+        }
+        Some(map)
+    }
+
+    /// Parse meter_id hex string (8 hex chars, spaces and dashes ignored) to 4 bytes in wire order.
     /// The meter ID is entered as printed on the meter (big-endian),
     /// but the wire format is little-endian, so we reverse the bytes.
     pub fn meter_id_bytes(&self) -> Option<[u8; 4]> {
-        if self.meter_id.len() != 8 {
+        let hex = clean_hex(&self.meter_id);
+        if hex.len() != 8 {
             return None;
         }
-        let bytes = parse_hex(&self.meter_id)?;
+        let bytes = parse_hex(&hex)?;
         Some([bytes[3], bytes[2], bytes[1], bytes[0]])
     }
 
-    /// Parse meter_key hex string (32 hex chars) to 16 bytes.
-    pub fn meter_key_bytes(&self) -> Option<[u8; 16]> {
-        if self.meter_key.len() != 32 {
-            return None;
+    /// Copy of the config with passwords, keys and tokens blanked out.
+    pub fn redacted(&self) -> Self {
+        Self {
+            wifi_pass: String::new(),
+            http_pass: String::new(),
+            esphome_psk: String::new(),
+            mqtt_pass: String::new(),
+            influx_token: String::new(),
+            webhook_header: String::new(),
+            ..self.clone()
         }
-        let bytes = parse_hex(&self.meter_key)?;
-        let mut arr = [0u8; 16];
-        arr.copy_from_slice(&bytes);
-        Some(arr)
+    }
+
+    /// Fill in the secrets left empty from `current`. They are never echoed back to clients, so
+    /// an empty value means "keep current"; a secret is only cleared along with the setting it
+    /// belongs to.
+    pub fn keep_secrets(&mut self, current: &Self) {
+        let keep = |secret: &mut String, owner_set: bool, current: &String| {
+            if !owner_set {
+                secret.clear();
+            } else if secret.is_empty() {
+                secret.clone_from(current);
+            }
+        };
+        keep(&mut self.wifi_pass, !self.wifi_ssid.is_empty(), &current.wifi_pass);
+        keep(&mut self.http_pass, !self.http_user.is_empty(), &current.http_pass);
+        keep(&mut self.esphome_psk, self.esphome_enable, &current.esphome_psk);
+        keep(&mut self.mqtt_pass, !self.mqtt_user.is_empty(), &current.mqtt_pass);
+        keep(
+            &mut self.influx_token,
+            !self.influx_url.is_empty(),
+            &current.influx_token,
+        );
+        keep(
+            &mut self.webhook_header,
+            !self.webhook_url.is_empty(),
+            &current.webhook_header,
+        );
+    }
+
+    /// Copy of the config with only the HTTP password blanked, which is never echoed back.
+    pub fn without_http_pass(&self) -> Self {
+        Self {
+            http_pass: String::new(),
+            ..self.clone()
+        }
+    }
+
+    /// Static IPv4 settings are usable: non-zero address and mask length 1..=32.
+    pub fn static_ipv4_valid(&self) -> bool {
+        !self.v4addr.is_unspecified() && (1..=32).contains(&self.v4mask)
     }
 
     pub fn from_nvs(nvs: &mut nvs::EspNvs<nvs::NvsDefault>) -> Option<Self> {
-        let mut nvsbuf = [0u8; NVS_BUF_SIZE];
-        info!("Reading up to {sz} bytes from nvs...", sz = NVS_BUF_SIZE);
-        let b = match nvs.get_blob(CONFIG_NAME, &mut nvsbuf) {
+        info!("Reading config from nvs...");
+        let b = match nvs_get_blob_vec(nvs, CONFIG_NAME) {
             Err(e) => {
                 error!("Nvs read error {e:?}");
                 return None;
@@ -106,12 +533,15 @@ impl MyConfig {
             }
         };
         info!("Got {sz} bytes from nvs. Parsing config...", sz = b.len());
+        let b = unseal(b)?;
 
         let crc = Crc::<u32>::new(&CRC_32_ISCSI);
         let digest = crc.digest();
-        match postcard::from_bytes_crc32::<MyConfig>(b, digest) {
+        match postcard::from_bytes_crc32::<MyConfig>(&b, digest) {
             Ok(c) => {
                 info!("Successfully parsed config from nvs.");
+                seal_plaintext_blob(nvs, CONFIG_NAME);
+                seal_plaintext_blob(nvs, METER_KEY_NVS_KEY);
                 Some(c)
             }
             Err(e) => {
@@ -121,19 +551,140 @@ impl MyConfig {
         }
     }
 
+    /// Save the config blob. A non-empty `meter_key` goes to its own nvs key instead,
+    /// an empty one keeps the stored key.
     pub fn to_nvs(&self, nvs: &mut nvs::EspNvs<nvs::NvsDefault>) -> AppResult<()> {
-        let mut nvsbuf = [0u8; NVS_BUF_SIZE];
+        let mut config = self.clone();
+        if !config.meter_key.is_empty() {
+            let key = parse_meter_key(&config.meter_key)
+                .ok_or_else(|| AppError::Message("Cannot save invalid meter_key".into()))?;
+            save_meter_key(nvs, &key)?;
+            config.meter_key.clear();
+        }
+
         let crc = Crc::<u32>::new(&CRC_32_ISCSI);
         let digest = crc.digest();
-        let nvsdata = postcard::to_slice_crc32(self, &mut nvsbuf, digest)
+        let nvsdata = postcard::to_allocvec_crc32(&config, digest)
             .map_err(|e| AppError::Message(format!("Cannot encode config to buffer {e:?}")))?;
         info!("Encoded config to {sz} bytes. Saving to nvs...", sz = nvsdata.len());
 
-        nvs.set_blob(CONFIG_NAME, nvsdata)
+        nvs.set_blob(CONFIG_NAME, &seal(&nvsdata))
             .map_err(|e| AppError::Message(format!("Cannot save to nvs: {e:?}")))?;
         info!("Config saved.");
         Ok(())
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_meter_id(meter_id: &str) -> MyConfig {
+        MyConfig {
+            meter_id: meter_id.into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn meter_id_wire_order() {
+        let wire = Some([0x99, 0x87, 0x34, 0x76]);
+        assert_eq!(with_meter_id("76348799").meter_id_bytes(), wire);
+        assert_eq!(with_meter_id("7634 87-99").meter_id_bytes(), wire);
+        assert_eq!(
+            with_meter_id("7634879A").meter_id_bytes(),
+            Some([0x9A, 0x87, 0x34, 0x76])
+        );
+    }
+
+    #[test]
+    fn meter_id_invalid() {
+        assert_eq!(with_meter_id("").meter_id_bytes(), None);
+        assert_eq!(with_meter_id("7634879").meter_id_bytes(), None);
+        assert_eq!(with_meter_id("763487990").meter_id_bytes(), None);
+        assert_eq!(with_meter_id("7634879G").meter_id_bytes(), None);
+    }
+
+    #[test]
+    fn nvs_blob_roundtrip_large() {
+        // Every field set, with strings far beyond the fixed 256-byte `NVS_BUF_SIZE` buffer used before
+        let long = || "x".repeat(255);
+        let config = MyConfig {
+            wifi_ssid: long(),
+            wifi_pass: long(),
+            wifi_wpa2ent: true,
+            wifi_username: long(),
+            wifi_max_retries: u8::MAX,
+            wifi_reboot_mins: u16::MAX,
+            hostname: long(),
+            http_user: long(),
+            http_pass: long(),
+            cors_origin: long(),
+            v4dhcp: true,
+            v4addr: net::Ipv4Addr::BROADCAST,
+            v4mask: u8::MAX,
+            v4gw: net::Ipv4Addr::BROADCAST,
+            dns1: net::Ipv4Addr::BROADCAST,
+            dns2: net::Ipv4Addr::BROADCAST,
+            ntp_server: long(),
+            require_ntp: true,
+            ntp_timeout_secs: u32::MAX,
+            tz_offset_minutes: i16::MIN,
+            syslog_host: long(),
+            syslog_port: u16::MAX,
+            ping_enable: true,
+            ping_host: long(),
+            ping_reboot: true,
+            esphome_enable: true,
+            esphome_port: u16::MAX,
+            esphome_psk: long(),
+            esphome_overrides: long(),
+            mqtt_enable: true,
+            mqtt_url: long(),
+            mqtt_user: long(),
+            mqtt_pass: long(),
+            mqtt_tls_insecure: true,
+            mqtt_topic: long(),
+            mqtt_retain_meter: true,
+            mqtt_retain_uptime: true,
+            mqtt_qos: u8::MAX,
+            mqtt_clean_session: true,
+            volume_unit: VolumeUnit::Gallons,
+            m3_decimals: u8::MAX,
+            temp_report_delta: u8::MAX,
+            influx_url: long(),
+            influx_org: long(),
+            influx_bucket: long(),
+            influx_token: long(),
+            webhook_url: long(),
+            webhook_header: long(),
+            meter_id: long(),
+            meter_key: long(),
+            radio_freq_hz: u32::MAX,
+            spi_baud_khz: u16::MAX,
+            radio_registers: long(),
+            dedup_window_secs: u16::MAX,
+            radio_watchdog_secs: u16::MAX,
+            reset_hold_secs: u8::MAX,
+            radio_max_restarts: u8::MAX,
+            radio_quiet_alarm_secs: u32::MAX,
+            status_led_gpio: i8::MAX,
+            debug_inject: true,
+            capture_depth: u16::MAX,
+            low_power: true,
+        };
+
+        let crc = Crc::<u32>::new(&CRC_32_ISCSI);
+        let blob = postcard::to_allocvec_crc32(&config, crc.digest()).unwrap();
+        assert!(blob.len() > 4 * 1024);
+        let parsed = postcard::from_bytes_crc32::<MyConfig>(&blob, crc.digest()).unwrap();
+        assert_eq!(postcard::to_allocvec_crc32(&parsed, crc.digest()).unwrap(), blob);
+        assert_eq!(parsed.tz_offset_minutes, i16::MIN);
+        assert_eq!(parsed.volume_unit, VolumeUnit::Gallons);
+
+        let mut corrupt = blob.clone();
+        corrupt[blob.len() / 2] ^= 0x01;
+        assert!(postcard::from_bytes_crc32::<MyConfig>(&corrupt, crc.digest()).is_err());
+    }
+}
 // EOF
@@ -0,0 +1,92 @@
+// consumption.rs — consumption sums over the current day, hour and last 24 hours
+
+use std::collections::VecDeque;
+
+use crate::*;
+
+const HOUR_SECS: i64 = 3600;
+const DAY_SECS: i64 = 86_400;
+// One baseline per clock hour: a day's worth plus the hour the window starts in
+const HOURLY_BASELINES: usize = 25;
+
+/// Total at the start of a period. `partial` when the period started before the first
+/// reading after boot or a counter reset, so the sum misses the beginning.
+#[derive(Clone, Copy, Debug)]
+struct Baseline {
+    start: i64,
+    total_l: u32,
+    partial: bool,
+}
+
+/// Consumption derived from the cumulative total of NTP-timed readings. Starts empty at boot.
+#[derive(Default)]
+pub struct Consumption {
+    day: Option<Baseline>,
+    /// Oldest first
+    hours: VecDeque<Baseline>,
+    latest: Option<(i64, u32)>,
+}
+
+impl Consumption {
+    /// Account a new reading. Days and hours follow local time by `tz_offset_minutes`;
+    /// readings without NTP time are skipped.
+    pub fn update(&mut self, reading: &MeterReading, tz_offset_minutes: i16) {
+        if !reading.time_synced {
+            return;
+        }
+        if self.latest.is_some_and(|(_, total_l)| reading.total_l < total_l) {
+            info!("Consumption: meter total went down, starting over");
+            *self = Self::default();
+        }
+
+        let offset = i64::from(tz_offset_minutes) * 60;
+        let local = reading.timestamp + offset;
+        let day_start = local.div_euclid(DAY_SECS) * DAY_SECS - offset;
+        let hour_start = local.div_euclid(HOUR_SECS) * HOUR_SECS - offset;
+        // The last reading before a period boundary is the closest we get to the total at it
+        let baseline = |start| Baseline {
+            start,
+            total_l: self.latest.map_or(reading.total_l, |(_, total_l)| total_l),
+            partial: self.latest.is_none(),
+        };
+
+        if self.day.is_none_or(|day| day.start != day_start) {
+            self.day = Some(baseline(day_start));
+        }
+        if self.hours.back().is_none_or(|hour| hour.start != hour_start) {
+            let hour = baseline(hour_start);
+            self.hours.push_back(hour);
+            while self.hours.len() > HOURLY_BASELINES {
+                self.hours.pop_front();
+            }
+        }
+        self.latest = Some((reading.timestamp, reading.total_l));
+    }
+
+    /// `today_l`, `last_24h_l` and `hour_l` with their `*_partial` flags, `null` before the first timed reading.
+    /// The last 24 hours start at the first clock hour inside the window.
+    pub fn to_json(&self) -> serde_json::Value {
+        let Some((now, total_l)) = self.latest else {
+            return serde_json::json!({
+                "today_l": null, "today_partial": null,
+                "last_24h_l": null, "last_24h_partial": null,
+                "hour_l": null, "hour_partial": null,
+            });
+        };
+
+        let since = |b: &Baseline| total_l.saturating_sub(b.total_l);
+        let day = self.day.as_ref();
+        let hour = self.hours.back();
+        let window = self.hours.iter().find(|b| b.start >= now - DAY_SECS);
+        serde_json::json!({
+            "today_l": day.map(since),
+            "today_partial": day.map(|b| b.partial),
+            "last_24h_l": window.map(since),
+            "last_24h_partial": window.map(|b| b.partial || b.start > now - DAY_SECS + HOUR_SECS),
+            "hour_l": hour.map(since),
+            "hour_partial": hour.map(|b| b.partial),
+        })
+    }
+}
+
+// EOF
@@ -1,18 +1,32 @@
 // esphome_api.rs
 
-use std::collections::BTreeMap;
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::atomic::Ordering,
+    time::Instant,
+};
 
 use serde_json::{Map, Value};
 use tokio::{
     io::{self, AsyncReadExt, AsyncWriteExt},
     net::{TcpListener, TcpStream},
+    task::AbortHandle,
 };
 
 use crate::*;
 
-const ESPHOME_API_PORT: u16 = 6053;
+pub const ESPHOME_API_PORT: u16 = 6053;
 const API_VERSION_MAJOR: u32 = 1;
 const API_VERSION_MINOR: u32 = 14;
+// Oldest connection is dropped when a new client would exceed this
+const MAX_CLIENTS: usize = 3;
+// Keepalive: ping a client silent for this long, and drop it if the ping goes unanswered
+const PING_INTERVAL_SECS: u64 = 20;
+const PING_TIMEOUT_SECS: u64 = 10;
+// Largest plaintext payload accepted, a bigger length means garbage or a hostile client
+const PLAIN_FRAME_MAX: usize = 64 * 1024;
+// Keys of `Consumption::to_json()`, listed as sensors after the meter fields
+const CONSUMPTION_FIELDS: [&str; 3] = ["consumption_today_l", "consumption_last_24h_l", "consumption_hour_l"];
 
 #[repr(u32)]
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -27,14 +41,18 @@ enum ApiMessageType {
     DeviceInfoRequest = 9,
     DeviceInfoResponse = 10,
     ListEntitiesRequest = 11,
+    ListEntitiesBinarySensorResponse = 12,
     ListEntitiesSensorResponse = 16,
     ListEntitiesTextSensorResponse = 18,
     ListEntitiesDoneResponse = 19,
     SubscribeStatesRequest = 20,
+    BinarySensorStateResponse = 21,
     SensorStateResponse = 25,
     TextSensorStateResponse = 27,
     SubscribeHomeassistantServicesRequest = 34,
     SubscribeHomeassistantStatesRequest = 38,
+    ListEntitiesButtonResponse = 61,
+    ButtonCommandRequest = 62,
     NoiseEncryptionSetKeyRequest = 124,
     NoiseEncryptionSetKeyResponse = 125,
 }
@@ -60,14 +78,18 @@ impl TryFrom<u32> for ApiMessageType {
             9 => Ok(Self::DeviceInfoRequest),
             10 => Ok(Self::DeviceInfoResponse),
             11 => Ok(Self::ListEntitiesRequest),
+            12 => Ok(Self::ListEntitiesBinarySensorResponse),
             16 => Ok(Self::ListEntitiesSensorResponse),
             18 => Ok(Self::ListEntitiesTextSensorResponse),
             19 => Ok(Self::ListEntitiesDoneResponse),
             20 => Ok(Self::SubscribeStatesRequest),
+            21 => Ok(Self::BinarySensorStateResponse),
             25 => Ok(Self::SensorStateResponse),
             27 => Ok(Self::TextSensorStateResponse),
             34 => Ok(Self::SubscribeHomeassistantServicesRequest),
             38 => Ok(Self::SubscribeHomeassistantStatesRequest),
+            61 => Ok(Self::ListEntitiesButtonResponse),
+            62 => Ok(Self::ButtonCommandRequest),
             124 => Ok(Self::NoiseEncryptionSetKeyRequest),
             125 => Ok(Self::NoiseEncryptionSetKeyResponse),
             _ => Err(()),
@@ -79,25 +101,72 @@ const STATE_CLASS_NONE: u32 = 0;
 const STATE_CLASS_MEASUREMENT: u32 = 1;
 const STATE_CLASS_TOTAL_INCREASING: u32 = 2;
 
-const KNOWN_METER_FIELDS: [&str; 9] = [
+const ENTITY_CATEGORY_NONE: u32 = 0;
+const ENTITY_CATEGORY_CONFIG: u32 = 1;
+const ENTITY_CATEGORY_DIAGNOSTIC: u32 = 2;
+
+const DIAGNOSTIC_FIELDS: [&str; 25] = [
+    "reset_reason",
+    "boot_time",
+    "config_status",
+    "last_reset",
+    "meter_manufacturer",
+    "meter_device_type",
+    "meter_security",
+    "rssi",
+    "lqi",
+    "ntp_synced",
+    "radio_ok",
+    "ping_ok",
+    "rx_frames",
+    "rx_frames_lifetime",
+    "rx_preamble_errors",
+    "rx_crc_errors",
+    "rx_decrypt_suspect",
+    "rx_meter_id_mismatches",
+    "rx_decoded",
+    "rx_success_ratio",
+    "rx_antenna_suspect",
+    "rx_meter_quiet",
+    "rx_radio_cal_failed",
+    "rx_spi_errors",
+    "rx_spi_resets",
+];
+
+const BUTTON_REBOOT: &str = "reboot";
+const BUTTON_RESTART_RADIO: &str = "restart_radio";
+const BUTTONS: [&str; 2] = [BUTTON_REBOOT, BUTTON_RESTART_RADIO];
+
+const KNOWN_METER_FIELDS: [&str; 18] = [
     "total_l",
     "month_start_l",
     "total_m3",
     "month_start_m3",
+    "month_consumption_l",
     "flow_temp",
     "ambient_temp",
     "info_codes",
+    "low_battery",
+    "meter_datetime",
+    "prev_period_date",
+    "total_volume",
+    "month_start_volume",
+    "month_consumption_volume",
+    "volume_unit",
     "timestamp",
     "timestamp_s",
+    "time_synced",
 ];
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 enum EntityKind {
     Sensor,
+    BinarySensor,
     TextSensor,
+    Button,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 struct EntityDef {
     field: String,
     key: u32,
@@ -107,7 +176,9 @@ struct EntityDef {
     unit: Option<String>,
     accuracy: i32,
     device_class: Option<String>,
+    icon: Option<String>,
     state_class: u32,
+    entity_category: u32,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -133,32 +204,151 @@ pub async fn run_esphome_api(state: Arc<Pin<Box<MyState>>>) -> AppResult<()> {
         sleep(Duration::from_secs(5)).await;
     }
 
-    let listen = format!("0.0.0.0:{ESPHOME_API_PORT}");
+    let psk = state.config.read().await.esphome_psk.clone();
+    let psk = if psk.is_empty() {
+        None
+    } else if let Some(key) = parse_noise_psk(&psk) {
+        Some(key)
+    } else {
+        error!("ESPHome API encryption key is invalid, API is disabled.");
+        loop {
+            sleep(Duration::from_secs(3600)).await;
+        }
+    };
+
+    let port = state.config.read().await.esphome_port;
+    let listen = format!("0.0.0.0:{port}");
     let addr = listen.parse::<net::SocketAddr>()?;
     let listener = TcpListener::bind(addr).await?;
-    info!("ESPHome API listening on {listen}");
+    info!(
+        "ESPHome API listening on {listen} ({})",
+        if psk.is_some() { "encrypted" } else { "plaintext" }
+    );
 
+    let shared = Arc::new(SharedEntities::new(&state).await);
+    let mut clients = VecDeque::<(net::SocketAddr, AbortHandle)>::new();
     loop {
         let (stream, peer) = listener.accept().await?;
         info!("ESPHome API client connected: {peer}");
+
+        clients.retain(|(_, handle)| !handle.is_finished());
+        if clients.len() >= MAX_CLIENTS
+            && let Some((old_peer, handle)) = clients.pop_front()
+        {
+            warn!("ESPHome API: too many clients, dropping oldest {old_peer}");
+            handle.abort();
+        }
+
         let state2 = state.clone();
-        tokio::spawn(async move {
-            if let Err(e) = Box::pin(handle_client(state2, stream)).await {
+        let shared2 = shared.clone();
+        let task = tokio::spawn(async move {
+            if let Err(e) = Box::pin(handle_client(state2, shared2, stream, psk)).await {
                 warn!("ESPHome API client error: {e}");
             }
             info!("ESPHome API client disconnected: {peer}");
         });
+        clients.push_back((peer, task.abort_handle()));
+    }
+}
+
+/// Entity list shared by all clients, so every client sees the same entities and keys.
+struct SharedEntities {
+    entities: RwLock<Arc<Vec<EntityDef>>>,
+}
+
+impl SharedEntities {
+    async fn new(state: &Arc<Pin<Box<MyState>>>) -> Self {
+        let (unit, m3_decimals, overrides) = entity_options(state).await;
+        Self {
+            entities: RwLock::new(Arc::new(build_entity_defs(unit, m3_decimals, &overrides))),
+        }
+    }
+
+    async fn get(&self) -> Arc<Vec<EntityDef>> {
+        self.entities.read().await.clone()
+    }
+
+    /// Rebuild from the config. The list is only replaced if it actually changed.
+    async fn refresh(&self, state: &Arc<Pin<Box<MyState>>>) -> Arc<Vec<EntityDef>> {
+        let (unit, m3_decimals, overrides) = entity_options(state).await;
+        let fresh = build_entity_defs(unit, m3_decimals, &overrides);
+        let mut entities = self.entities.write().await;
+        if **entities != fresh {
+            info!("ESPHome: entity list changed");
+            *entities = Arc::new(fresh);
+        }
+        entities.clone()
     }
 }
 
-async fn handle_client(state: Arc<Pin<Box<MyState>>>, mut stream: TcpStream) -> AppResult<()> {
+/// Volume unit, m³ decimals and per-field overrides from the config. Invalid overrides are ignored.
+async fn entity_options(state: &Arc<Pin<Box<MyState>>>) -> (VolumeUnit, u8, BTreeMap<String, EntityOverride>) {
+    let config = state.config.read().await;
+    let overrides = config.esphome_overrides_map().unwrap_or_else(|| {
+        warn!("ESPHome: invalid esphome_overrides, ignored");
+        BTreeMap::new()
+    });
+    (config.volume_unit, config.m3_decimals, overrides)
+}
+
+async fn handle_client(
+    state: Arc<Pin<Box<MyState>>>,
+    shared: Arc<SharedEntities>,
+    tcp: TcpStream,
+    psk: Option<[u8; 32]>,
+) -> AppResult<()> {
+    let mut stream = ApiStream {
+        tcp,
+        noise: None,
+        rx_buf: Vec::new(),
+        synced: false,
+    };
+    if let Some(psk) = psk {
+        let node_name = state.my_id.read().await.clone();
+        let mac = state.my_mac_s.read().await.clone();
+        let handshake = NoiseSession::accept(&mut stream.tcp, &psk, &node_name, &mac);
+        match Box::pin(timeout(Duration::from_secs(10), handshake)).await {
+            Ok(Ok(session)) => stream.noise = Some(session),
+            Ok(Err(e)) => return Err(e.into()),
+            Err(_) => return Err(AppError::Message("ESPHome noise handshake timeout".into())),
+        }
+        info!("ESPHome: noise handshake completed");
+    }
+
     let mut state_subscribed = false;
-    let mut entities = build_entity_defs(None);
+    let mut entities = shared.get().await;
     let mut last_sent = BTreeMap::<u32, EntityStateValue>::new();
+    let mut data_rx = state.latest_data.subscribe();
+    let mut hello_done = false;
+    let mut last_heard = Instant::now();
+    // Our ping still waiting for its response, one at a time
+    let mut ping_sent: Option<Instant> = None;
 
     loop {
-        match Box::pin(timeout(Duration::from_secs(5), read_frame(&mut stream))).await {
-            Ok(Ok((msg_type_raw, payload))) => match ApiMessageType::try_from(msg_type_raw) {
+        // A dead connection (NAT timeout, client gone) is noticed here instead of piling up state updates
+        if let Some(sent) = ping_sent {
+            if sent.elapsed() >= Duration::from_secs(PING_TIMEOUT_SECS) {
+                warn!("ESPHome: no ping response in {PING_TIMEOUT_SECS} s, dropping client");
+                return Ok(());
+            }
+        } else if hello_done && last_heard.elapsed() >= Duration::from_secs(PING_INTERVAL_SECS) {
+            debug!("ESPHome: pinging idle client");
+            send_frame(&mut stream, ApiMessageType::PingRequest, &[]).await?;
+            ping_sent = Some(Instant::now());
+        }
+
+        let received = tokio::select! {
+            r = Box::pin(timeout(Duration::from_secs(5), read_frame(&mut stream))) => r.ok(),
+            // Push a fresh reading right away instead of waiting for the next tick
+            Ok(()) = data_rx.changed(), if state_subscribed => None,
+        };
+        if matches!(received, Some(Ok(_))) {
+            // Any message proves the client is alive
+            last_heard = Instant::now();
+            ping_sent = None;
+        }
+        match received {
+            Some(Ok((msg_type_raw, payload))) => match ApiMessageType::try_from(msg_type_raw) {
                 Ok(ApiMessageType::HelloRequest) => {
                     if let Some((client_info, major, minor)) = parse_hello_request(&payload) {
                         info!(
@@ -168,6 +358,7 @@ async fn handle_client(state: Arc<Pin<Box<MyState>>>, mut stream: TcpStream) ->
                         info!("ESPHome hello request received");
                     }
                     send_hello_response(&state, &mut stream).await?;
+                    hello_done = true;
                 }
                 Ok(ApiMessageType::AuthRequest) => {
                     info!("ESPHome auth request ignored (password auth removed upstream)");
@@ -176,6 +367,9 @@ async fn handle_client(state: Arc<Pin<Box<MyState>>>, mut stream: TcpStream) ->
                     info!("ESPHome: sending ping response");
                     send_frame(&mut stream, ApiMessageType::PingResponse, &[]).await?;
                 }
+                Ok(ApiMessageType::PingResponse) => {
+                    debug!("ESPHome: recvd ping response");
+                }
                 Ok(ApiMessageType::DisconnectRequest) => {
                     info!("ESPHome: recvd disconnect request");
                     send_frame(&mut stream, ApiMessageType::DisconnectResponse, &[]).await?;
@@ -187,8 +381,7 @@ async fn handle_client(state: Arc<Pin<Box<MyState>>>, mut stream: TcpStream) ->
                 }
                 Ok(ApiMessageType::ListEntitiesRequest) => {
                     info!("ESPHome: recvd list entities request");
-                    let latest = state.latest_data.read().await.clone();
-                    entities = build_entity_defs(latest.as_ref());
+                    entities = shared.refresh(&state).await;
                     send_list_entities_response(&mut stream, &entities).await?;
                 }
                 Ok(ApiMessageType::SubscribeStatesRequest) => {
@@ -196,13 +389,34 @@ async fn handle_client(state: Arc<Pin<Box<MyState>>>, mut stream: TcpStream) ->
                     info!("ESPHome: recvd subscribe states");
                     Box::pin(send_state_updates(&state, &mut stream, &entities, &mut last_sent, true)).await?;
                 }
+                Ok(ApiMessageType::ButtonCommandRequest) => {
+                    let Some(key) = parse_button_command(&payload) else {
+                        warn!("ESPHome: malformed button command");
+                        continue;
+                    };
+                    match entities.iter().find(|e| e.key == key && e.kind == EntityKind::Button) {
+                        Some(entity) if entity.field == BUTTON_REBOOT => {
+                            info!("ESPHome: reboot button pressed, resetting soon...");
+                            *state.reset.write().await = true;
+                        }
+                        Some(entity) if entity.field == BUTTON_RESTART_RADIO => {
+                            info!("ESPHome: restart radio button pressed");
+                            // Fire and forget, the outcome is logged by the radio task
+                            let (reply_tx, _) = oneshot::channel();
+                            if state.radio_cmd.try_send(RadioCommand::Restart(reply_tx)).is_err() {
+                                warn!("ESPHome: radio command queue full");
+                            }
+                        }
+                        _ => warn!("ESPHome: unknown button key {key:08X}"),
+                    }
+                }
                 Ok(ApiMessageType::SubscribeHomeassistantServicesRequest)
                 | Ok(ApiMessageType::SubscribeHomeassistantStatesRequest) => {
                     // Home Assistant sends these by default; this firmware does not consume them.
                     continue;
                 }
                 Ok(ApiMessageType::NoiseEncryptionSetKeyRequest) => {
-                    // This implementation is plaintext-only. Report failure.
+                    // The encryption key is only set through the config, not at runtime. Report failure.
                     let mut payload = Vec::new();
                     pb_put_bool(1, false, &mut payload);
                     send_frame(&mut stream, ApiMessageType::NoiseEncryptionSetKeyResponse, &payload).await?;
@@ -217,20 +431,26 @@ async fn handle_client(state: Arc<Pin<Box<MyState>>>, mut stream: TcpStream) ->
                     continue;
                 }
             },
-            Ok(Err(e)) => {
+            Some(Err(e)) => {
                 if is_closed_connection(&e) {
                     return Ok(());
                 }
+                if e.kind() == io::ErrorKind::InvalidData {
+                    // Not an API client, or one we lost step with: close instead of guessing further
+                    warn!("ESPHome: dropping client: {e}");
+                    return Ok(());
+                }
                 return Err(e.into());
             }
-            Err(_) => {
-                // timeout tick
-                info!("ESPHome API: tick");
-                // continue;
+            None => {
+                // timeout tick or fresh data
+                debug!("ESPHome API: tick");
             }
         }
 
         if state_subscribed {
+            // Follow entity list changes made by other clients
+            entities = shared.get().await;
             Box::pin(send_state_updates(
                 &state,
                 &mut stream,
@@ -243,7 +463,7 @@ async fn handle_client(state: Arc<Pin<Box<MyState>>>, mut stream: TcpStream) ->
     }
 }
 
-async fn send_hello_response(state: &Arc<Pin<Box<MyState>>>, stream: &mut TcpStream) -> AppResult<()> {
+async fn send_hello_response(state: &Arc<Pin<Box<MyState>>>, stream: &mut ApiStream) -> AppResult<()> {
     let device_name = state.my_id.read().await.clone();
     let mut payload = Vec::new();
     pb_put_varint(1, API_VERSION_MAJOR, &mut payload);
@@ -255,7 +475,7 @@ async fn send_hello_response(state: &Arc<Pin<Box<MyState>>>, stream: &mut TcpStr
     Ok(())
 }
 
-async fn send_device_info_response(state: &Arc<Pin<Box<MyState>>>, stream: &mut TcpStream) -> AppResult<()> {
+async fn send_device_info_response(state: &Arc<Pin<Box<MyState>>>, stream: &mut ApiStream) -> AppResult<()> {
     let mut payload = Vec::new();
     let device_name = state.my_id.read().await.clone();
     let device_mac = state.my_mac_s.read().await.clone();
@@ -263,8 +483,11 @@ async fn send_device_info_response(state: &Arc<Pin<Box<MyState>>>, stream: &mut
     pb_put_string(2, &device_name, &mut payload);
     pb_put_string(3, &device_mac, &mut payload);
     pb_put_string(4, FW_VERSION, &mut payload);
-    pb_put_string(5, "", &mut payload);
+    pb_put_string(5, FW_BUILD_TIME, &mut payload);
     pb_put_string(6, "ESP32", &mut payload);
+    // Project version shows up as the firmware in Home Assistant's device info, with the running slot
+    pb_put_string(8, "sjm42.esp32multical21", &mut payload);
+    pb_put_string(9, &format!("{FW_VERSION} {}", state.ota_slot), &mut payload);
     pb_put_string(12, "Espressif", &mut payload);
     pb_put_string(13, "Multical 21", &mut payload);
 
@@ -273,7 +496,7 @@ async fn send_device_info_response(state: &Arc<Pin<Box<MyState>>>, stream: &mut
     Ok(())
 }
 
-async fn send_list_entities_response(stream: &mut TcpStream, entities: &[EntityDef]) -> AppResult<()> {
+async fn send_list_entities_response(stream: &mut ApiStream, entities: &[EntityDef]) -> AppResult<()> {
     for entity in entities {
         match entity.kind {
             EntityKind::Sensor => {
@@ -281,6 +504,9 @@ async fn send_list_entities_response(stream: &mut TcpStream, entities: &[EntityD
                 pb_put_string(1, &entity.object_id, &mut payload);
                 pb_put_fixed32(2, entity.key, &mut payload);
                 pb_put_string(3, &entity.name, &mut payload);
+                if let Some(icon) = &entity.icon {
+                    pb_put_string(5, icon, &mut payload);
+                }
                 if let Some(unit) = &entity.unit {
                     pb_put_string(6, unit, &mut payload);
                 }
@@ -289,18 +515,51 @@ async fn send_list_entities_response(stream: &mut TcpStream, entities: &[EntityD
                     pb_put_string(9, device_class, &mut payload);
                 }
                 pb_put_varint(10, entity.state_class, &mut payload);
+                pb_put_varint(13, entity.entity_category, &mut payload);
                 send_frame(stream, ApiMessageType::ListEntitiesSensorResponse, &payload).await?;
             }
+            EntityKind::BinarySensor => {
+                let mut payload = Vec::new();
+                pb_put_string(1, &entity.object_id, &mut payload);
+                pb_put_fixed32(2, entity.key, &mut payload);
+                pb_put_string(3, &entity.name, &mut payload);
+                if let Some(device_class) = &entity.device_class {
+                    pb_put_string(5, device_class, &mut payload);
+                }
+                if let Some(icon) = &entity.icon {
+                    pb_put_string(8, icon, &mut payload);
+                }
+                pb_put_varint(9, entity.entity_category, &mut payload);
+                send_frame(stream, ApiMessageType::ListEntitiesBinarySensorResponse, &payload).await?;
+            }
             EntityKind::TextSensor => {
                 let mut payload = Vec::new();
                 pb_put_string(1, &entity.object_id, &mut payload);
                 pb_put_fixed32(2, entity.key, &mut payload);
                 pb_put_string(3, &entity.name, &mut payload);
+                if let Some(icon) = &entity.icon {
+                    pb_put_string(5, icon, &mut payload);
+                }
+                pb_put_varint(7, entity.entity_category, &mut payload);
                 if let Some(device_class) = &entity.device_class {
                     pb_put_string(8, device_class, &mut payload);
                 }
                 send_frame(stream, ApiMessageType::ListEntitiesTextSensorResponse, &payload).await?;
             }
+            EntityKind::Button => {
+                let mut payload = Vec::new();
+                pb_put_string(1, &entity.object_id, &mut payload);
+                pb_put_fixed32(2, entity.key, &mut payload);
+                pb_put_string(3, &entity.name, &mut payload);
+                if let Some(icon) = &entity.icon {
+                    pb_put_string(5, icon, &mut payload);
+                }
+                pb_put_varint(7, entity.entity_category, &mut payload);
+                if let Some(device_class) = &entity.device_class {
+                    pb_put_string(8, device_class, &mut payload);
+                }
+                send_frame(stream, ApiMessageType::ListEntitiesButtonResponse, &payload).await?;
+            }
         }
     }
 
@@ -311,14 +570,16 @@ async fn send_list_entities_response(stream: &mut TcpStream, entities: &[EntityD
 
 async fn send_state_updates(
     state: &Arc<Pin<Box<MyState>>>,
-    stream: &mut TcpStream,
+    stream: &mut ApiStream,
     entities: &[EntityDef],
     last_sent: &mut BTreeMap<u32, EntityStateValue>,
     force: bool,
 ) -> AppResult<()> {
     let current_states = build_entity_states(state, entities).await;
+    let temp_delta = state.config.read().await.temp_report_delta;
     last_sent.retain(|key, _| current_states.contains_key(key));
 
+    let mut sent = 0;
     for entity in entities {
         let value = current_states
             .get(&entity.key)
@@ -328,8 +589,21 @@ async fn send_state_updates(
         if !changed {
             continue;
         }
+        // Temperature jitter below temp_report_delta, compared to the last value actually sent
+        if !force
+            && TEMP_FIELDS.contains(&entity.field.as_str())
+            && let (Some(EntityStateValue::Number(prev)), EntityStateValue::Number(v)) =
+                (last_sent.get(&entity.key), &value)
+            && !temp_reportable(*prev as i8, *v as i8, temp_delta)
+        {
+            continue;
+        }
 
         match (&entity.kind, &value) {
+            (EntityKind::Button, _) => {
+                // Buttons are stateless
+                continue;
+            }
             (EntityKind::Sensor, EntityStateValue::Number(v)) => {
                 let mut payload = Vec::new();
                 pb_put_fixed32(1, entity.key, &mut payload);
@@ -348,6 +622,18 @@ async fn send_state_updates(
                 pb_put_bool(3, true, &mut payload);
                 send_frame(stream, ApiMessageType::SensorStateResponse, &payload).await?;
             }
+            (EntityKind::BinarySensor, EntityStateValue::Number(v)) => {
+                let mut payload = Vec::new();
+                pb_put_fixed32(1, entity.key, &mut payload);
+                pb_put_bool(2, *v != 0.0, &mut payload);
+                send_frame(stream, ApiMessageType::BinarySensorStateResponse, &payload).await?;
+            }
+            (EntityKind::BinarySensor, _) => {
+                let mut payload = Vec::new();
+                pb_put_fixed32(1, entity.key, &mut payload);
+                pb_put_bool(3, true, &mut payload);
+                send_frame(stream, ApiMessageType::BinarySensorStateResponse, &payload).await?;
+            }
             (EntityKind::TextSensor, EntityStateValue::Text(v)) => {
                 let mut payload = Vec::new();
                 pb_put_fixed32(1, entity.key, &mut payload);
@@ -369,39 +655,56 @@ async fn send_state_updates(
         }
 
         last_sent.insert(entity.key, value);
+        sent += 1;
+    }
+    if sent > 0 {
+        debug!("ESPHome: sent {sent} state updates");
     }
-    info!("ESPHome: sent state updates");
     Ok(())
 }
 
-fn build_entity_defs(latest: Option<&MeterReading>) -> Vec<EntityDef> {
-    let value_map = latest.and_then(reading_to_map);
-    let mut field_order = vec!["uptime".to_string()];
-
-    for field in KNOWN_METER_FIELDS {
-        field_order.push(field.to_string());
+/// The full entity set, fixed by the field lists and independent of any reading, so Home
+/// Assistant registers every entity right away and keys never shift. Fields without a value
+/// yet are reported with the missing state flag.
+fn build_entity_defs(
+    unit: VolumeUnit,
+    m3_decimals: u8,
+    overrides: &BTreeMap<String, EntityOverride>,
+) -> Vec<EntityDef> {
+    let field_order = std::iter::once("uptime")
+        .chain(DIAGNOSTIC_FIELDS)
+        .chain(KNOWN_METER_FIELDS)
+        .chain(CONSUMPTION_FIELDS);
+
+    let mut entities = Vec::new();
+    for field in field_order {
+        entities.push(build_entity_def(field, unit, m3_decimals));
     }
-
-    if let Some(map) = &value_map {
-        for key in map.keys() {
-            if field_order.iter().all(|known| known != key) {
-                field_order.push(key.clone());
+    for button in BUTTONS {
+        entities.push(build_button_def(button));
+    }
+    for entity in &mut entities {
+        if let Some(o) = overrides.get(&entity.field) {
+            if let Some(accuracy) = o.accuracy {
+                entity.accuracy = accuracy;
+            }
+            if o.icon.is_some() {
+                entity.icon = o.icon.clone();
             }
         }
     }
-
-    let mut entities = Vec::with_capacity(field_order.len());
-    for field in field_order {
-        let value = value_map.as_ref().and_then(|map| map.get(&field));
-        entities.push(build_entity_def(&field, value));
-    }
     entities
 }
 
-fn build_entity_def(field: &str, value: Option<&Value>) -> EntityDef {
+fn build_entity_def(field: &str, volume_unit: VolumeUnit, m3_decimals: u8) -> EntityDef {
     let key = stable_key(field);
-    let kind = entity_kind_for_field(field, value);
-    let (unit, accuracy, device_class, state_class) = field_metadata(field, value, kind);
+    let kind = entity_kind_for_field(field);
+    let (unit, accuracy, device_class, state_class) = field_metadata(field, kind, volume_unit, m3_decimals);
+    let entity_category = if DIAGNOSTIC_FIELDS.contains(&field) {
+        ENTITY_CATEGORY_DIAGNOSTIC
+    } else {
+        ENTITY_CATEGORY_NONE
+    };
 
     EntityDef {
         field: field.to_string(),
@@ -411,22 +714,73 @@ fn build_entity_def(field: &str, value: Option<&Value>) -> EntityDef {
         kind,
         unit,
         accuracy,
+        icon: default_icon(field, kind),
         device_class,
         state_class,
+        entity_category,
     }
 }
 
-fn entity_kind_for_field(field: &str, value: Option<&Value>) -> EntityKind {
-    if field == "timestamp_s" {
+fn build_button_def(field: &str) -> EntityDef {
+    EntityDef {
+        field: field.to_string(),
+        key: stable_key(field),
+        object_id: field.to_string(),
+        name: humanize_field(field),
+        kind: EntityKind::Button,
+        unit: None,
+        accuracy: 0,
+        device_class: (field == BUTTON_REBOOT).then(|| "restart".to_string()),
+        icon: (field == BUTTON_RESTART_RADIO).then(|| "mdi:radio-tower".to_string()),
+        state_class: STATE_CLASS_NONE,
+        entity_category: ENTITY_CATEGORY_CONFIG,
+    }
+}
+
+/// Icons where Home Assistant has no device class default: water volumes and temperatures.
+fn default_icon(field: &str, kind: EntityKind) -> Option<String> {
+    if kind != EntityKind::Sensor {
+        return None;
+    }
+    if field.ends_with("_l") || field.ends_with("_m3") || field.ends_with("_volume") {
+        return Some("mdi:water".to_string());
+    }
+    if field.contains("temp") {
+        return Some("mdi:thermometer".to_string());
+    }
+    None
+}
+
+fn entity_kind_for_field(field: &str) -> EntityKind {
+    if field == "timestamp_s"
+        || field == "meter_datetime"
+        || field == "prev_period_date"
+        || field == "volume_unit"
+        || field == "reset_reason"
+        || field == "boot_time"
+        || field == "config_status"
+        || field == "last_reset"
+        || field == "meter_manufacturer"
+        || field == "meter_device_type"
+        || field == "meter_security"
+    {
         return EntityKind::TextSensor;
     }
-    match value {
-        Some(Value::String(_)) | Some(Value::Array(_)) | Some(Value::Object(_)) => EntityKind::TextSensor,
-        _ => EntityKind::Sensor,
+    if field == "low_battery" {
+        return EntityKind::BinarySensor;
     }
+    EntityKind::Sensor
 }
 
-fn field_metadata(field: &str, value: Option<&Value>, kind: EntityKind) -> (Option<String>, i32, Option<String>, u32) {
+fn field_metadata(
+    field: &str,
+    kind: EntityKind,
+    volume_unit: VolumeUnit,
+    m3_decimals: u8,
+) -> (Option<String>, i32, Option<String>, u32) {
+    if field == "low_battery" {
+        return (None, 0, Some("battery".to_string()), STATE_CLASS_NONE);
+    }
     if field == "uptime" {
         return (
             Some("s".to_string()),
@@ -436,17 +790,59 @@ fn field_metadata(field: &str, value: Option<&Value>, kind: EntityKind) -> (Opti
         );
     }
 
+    if field == "rssi" {
+        return (
+            Some("dBm".to_string()),
+            0,
+            Some("signal_strength".to_string()),
+            STATE_CLASS_MEASUREMENT,
+        );
+    }
+    if field == "lqi" {
+        return (None, 0, None, STATE_CLASS_MEASUREMENT);
+    }
+    if field == "ntp_synced"
+        || field == "radio_ok"
+        || field == "ping_ok"
+        || field == "time_synced"
+        || field == "rx_antenna_suspect"
+        || field == "rx_meter_quiet"
+        || field == "rx_radio_cal_failed"
+    {
+        return (None, 0, None, STATE_CLASS_NONE);
+    }
+    if field == "rx_success_ratio" {
+        return (Some("%".to_string()), 0, None, STATE_CLASS_MEASUREMENT);
+    }
+    if field.starts_with("rx_") {
+        return (None, 0, None, STATE_CLASS_TOTAL_INCREASING);
+    }
+
     if kind == EntityKind::TextSensor {
-        if field.contains("timestamp") {
+        if field.contains("timestamp") || field == "last_reset" || field == "boot_time" {
             return (None, 0, Some("timestamp".to_string()), STATE_CLASS_NONE);
         }
         return (None, 0, None, STATE_CLASS_NONE);
     }
 
+    if field.ends_with("_volume") {
+        let state_class = if field == "total_volume" {
+            STATE_CLASS_TOTAL_INCREASING
+        } else {
+            STATE_CLASS_MEASUREMENT
+        };
+        return (
+            Some(volume_unit.symbol().to_string()),
+            volume_unit.accuracy(m3_decimals),
+            Some("water".to_string()),
+            state_class,
+        );
+    }
+
     if field == "total_m3" {
         return (
             Some("m³".to_string()),
-            3,
+            m3_decimals as i32,
             Some("water".to_string()),
             STATE_CLASS_TOTAL_INCREASING,
         );
@@ -454,7 +850,7 @@ fn field_metadata(field: &str, value: Option<&Value>, kind: EntityKind) -> (Opti
     if field == "month_start_m3" {
         return (
             Some("m³".to_string()),
-            3,
+            m3_decimals as i32,
             Some("water".to_string()),
             STATE_CLASS_MEASUREMENT,
         );
@@ -491,7 +887,12 @@ fn field_metadata(field: &str, value: Option<&Value>, kind: EntityKind) -> (Opti
         } else {
             STATE_CLASS_MEASUREMENT
         };
-        return (Some("m³".to_string()), 3, Some("water".to_string()), state_class);
+        return (
+            Some("m³".to_string()),
+            m3_decimals as i32,
+            Some("water".to_string()),
+            state_class,
+        );
     }
 
     if field.ends_with("_l") {
@@ -503,25 +904,95 @@ fn field_metadata(field: &str, value: Option<&Value>, kind: EntityKind) -> (Opti
         );
     }
 
-    let accuracy = match value {
-        Some(Value::Number(n)) if n.is_i64() || n.is_u64() => 0,
-        _ => 3,
-    };
-    (None, accuracy, None, STATE_CLASS_NONE)
+    // What is left are integers: info codes, timestamps and flags
+    (None, 0, None, STATE_CLASS_NONE)
 }
 
 async fn build_entity_states(
     state: &Arc<Pin<Box<MyState>>>,
     entities: &[EntityDef],
 ) -> BTreeMap<u32, EntityStateValue> {
-    let latest = state.latest_data.read().await.clone();
+    let latest = state.latest_data.borrow().clone();
     let uptime = *state.uptime.read().await as f32;
-    let meter_map = latest.as_ref().and_then(reading_to_map);
+    let rssi = *state.last_rssi.read().await;
+    let lqi = *state.last_lqi.read().await;
+    let ntp_synced = *state.ntp_synced.read().await;
+    let radio_ok = *state.radio_ok.read().await;
+    let ping_ok = *state.ping_ok.read().await;
+    let config_status = if state.config_incomplete.load(Ordering::Relaxed) {
+        CONFIG_INCOMPLETE_MSG
+    } else {
+        "ok"
+    };
+    let last_reset = state.last_reset_s().await;
+    let boot_time = state.boot_time_s().await;
+    let last_header = *state.last_header.read().await;
+    let last_security = *state.last_security.read().await;
+    let rx_stats = state.rx_stats.to_json();
+    let consumption = state.consumption.read().await.to_json();
+    let (volume_unit, m3_decimals) = {
+        let config = state.config.read().await;
+        (config.volume_unit, config.m3_decimals)
+    };
+    let meter_map = latest
+        .as_ref()
+        .and_then(|r| reading_to_map(r, volume_unit, m3_decimals));
 
     let mut out = BTreeMap::new();
     for entity in entities {
         let value = if entity.field == "uptime" {
             EntityStateValue::Number(uptime)
+        } else if entity.field == "reset_reason" {
+            EntityStateValue::Text(state.reset_reason.to_string())
+        } else if entity.field == "boot_time" {
+            boot_time
+                .clone()
+                .map(EntityStateValue::Text)
+                .unwrap_or(EntityStateValue::Missing)
+        } else if entity.field == "config_status" {
+            EntityStateValue::Text(config_status.to_string())
+        } else if entity.field == "last_reset" {
+            last_reset
+                .clone()
+                .map(EntityStateValue::Text)
+                .unwrap_or(EntityStateValue::Missing)
+        } else if entity.field == "meter_manufacturer" {
+            last_header
+                .map(|h| EntityStateValue::Text(h.manufacturer_code()))
+                .unwrap_or(EntityStateValue::Missing)
+        } else if entity.field == "meter_device_type" {
+            last_header
+                .map(|h| EntityStateValue::Text(h.device_type_s()))
+                .unwrap_or(EntityStateValue::Missing)
+        } else if entity.field == "meter_security" {
+            last_security
+                .map(|m| EntityStateValue::Text(m.label()))
+                .unwrap_or(EntityStateValue::Missing)
+        } else if entity.field == "rssi" {
+            rssi.map(EntityStateValue::Number).unwrap_or(EntityStateValue::Missing)
+        } else if entity.field == "lqi" {
+            lqi.map(|v| EntityStateValue::Number(v as f32))
+                .unwrap_or(EntityStateValue::Missing)
+        } else if entity.field == "ntp_synced" {
+            EntityStateValue::Number(if ntp_synced { 1.0 } else { 0.0 })
+        } else if entity.field == "radio_ok" {
+            radio_ok
+                .map(|ok| EntityStateValue::Number(if ok { 1.0 } else { 0.0 }))
+                .unwrap_or(EntityStateValue::Missing)
+        } else if entity.field == "ping_ok" {
+            ping_ok
+                .map(|ok| EntityStateValue::Number(if ok { 1.0 } else { 0.0 }))
+                .unwrap_or(EntityStateValue::Missing)
+        } else if let Some(key) = entity.field.strip_prefix("consumption_") {
+            consumption
+                .get(key)
+                .map(|v| value_to_state(v, entity.kind))
+                .unwrap_or(EntityStateValue::Missing)
+        } else if let Some(stat) = entity.field.strip_prefix("rx_") {
+            rx_stats
+                .get(stat)
+                .map(|v| value_to_state(v, entity.kind))
+                .unwrap_or(EntityStateValue::Missing)
         } else if let Some(map) = &meter_map {
             match map.get(&entity.field) {
                 Some(v) => value_to_state(v, entity.kind),
@@ -556,16 +1027,21 @@ fn value_to_state(value: &Value, kind: EntityKind) -> EntityStateValue {
                 .unwrap_or(EntityStateValue::Missing),
             _ => EntityStateValue::Missing,
         },
+        EntityKind::BinarySensor => match value {
+            Value::Bool(v) => EntityStateValue::Number(if *v { 1.0 } else { 0.0 }),
+            _ => EntityStateValue::Missing,
+        },
         EntityKind::TextSensor => match value {
             Value::String(v) => EntityStateValue::Text(v.clone()),
             Value::Null => EntityStateValue::Missing,
             _ => EntityStateValue::Text(value.to_string()),
         },
+        EntityKind::Button => EntityStateValue::Missing,
     }
 }
 
-fn reading_to_map(reading: &MeterReading) -> Option<Map<String, Value>> {
-    match serde_json::to_value(reading).ok()? {
+fn reading_to_map(reading: &MeterReading, unit: VolumeUnit, m3_decimals: u8) -> Option<Map<String, Value>> {
+    match unit.reading_json(reading, m3_decimals) {
         Value::Object(map) => Some(map),
         _ => None,
     }
@@ -597,33 +1073,108 @@ fn stable_key(object_id: &str) -> u32 {
     if hash == 0 { 1 } else { hash }
 }
 
-async fn read_frame(stream: &mut TcpStream) -> io::Result<(u32, Vec<u8>)> {
-    let preamble = stream.read_u8().await?;
-    if preamble != 0x00 {
-        return Err(io::Error::new(
-            io::ErrorKind::InvalidData,
-            format!("invalid preamble 0x{preamble:02X}"),
-        ));
+/// Client connection, Noise encrypted when an API encryption key is configured.
+struct ApiStream {
+    tcp: TcpStream,
+    noise: Option<NoiseSession>,
+    /// Bytes received but not yet consumed as a whole frame
+    rx_buf: Vec<u8>,
+    /// A plaintext frame has been read, so garbage after it is resynchronized instead of rejected
+    synced: bool,
+}
+
+/// Why the plaintext stream does not start with a frame.
+#[derive(Debug, thiserror::Error)]
+enum PlainFrameError {
+    #[error("invalid preamble 0x{0:02X}")]
+    Preamble(u8),
+    #[error("varuint overflow")]
+    VarintOverflow,
+    #[error("payload too large: {0}")]
+    TooLarge(usize),
+}
+
+/// Read one message. Cancel safe: whatever arrives is kept in `rx_buf` until a whole frame is there,
+/// so a frame cut short by the tick timeout is completed by the next call.
+async fn read_frame(stream: &mut ApiStream) -> io::Result<(u32, Vec<u8>)> {
+    loop {
+        let frame = match &mut stream.noise {
+            Some(noise) => noise.take_message(&mut stream.rx_buf)?,
+            None => match take_plain_frame(&mut stream.rx_buf) {
+                Ok(frame) => frame,
+                Err(PlainFrameError::Preamble(preamble)) if !stream.synced => {
+                    // Same reply as ESPHome: the plaintext marker plus enough text for the client to read a header.
+                    // Home Assistant reports it as encryption not being enabled on the device.
+                    stream.tcp.write_all(b"\x00Bad indicator byte").await?;
+                    let msg = if preamble == 0x01 {
+                        "client requested encryption, but esphome_psk is not set".to_string()
+                    } else {
+                        format!("invalid preamble 0x{preamble:02X}")
+                    };
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, msg));
+                }
+                Err(e @ PlainFrameError::TooLarge(_)) => {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, e.to_string()));
+                }
+                Err(e) => {
+                    // Skip to the next plausible preamble, always at least one byte so this cannot spin
+                    let skip = stream.rx_buf[1..]
+                        .iter()
+                        .position(|&b| b == 0x00)
+                        .map_or(stream.rx_buf.len(), |pos| pos + 1);
+                    warn!("ESPHome: {e}, skipping {skip} bytes to resync");
+                    stream.rx_buf.drain(..skip);
+                    continue;
+                }
+            },
+        };
+        if let Some(frame) = frame {
+            stream.synced = true;
+            return Ok(frame);
+        }
+        if stream.tcp.read_buf(&mut stream.rx_buf).await? == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed"));
+        }
     }
+}
 
-    let payload_len = read_varuint_async(stream).await? as usize;
-    let msg_type = read_varuint_async(stream).await? as u32;
+async fn send_frame(stream: &mut ApiStream, msg_type: ApiMessageType, payload: &[u8]) -> io::Result<()> {
+    match &mut stream.noise {
+        Some(noise) => noise.write_message(&mut stream.tcp, msg_type.id(), payload).await,
+        None => send_plain_frame(&mut stream.tcp, msg_type, payload).await,
+    }
+}
 
-    if payload_len > 64 * 1024 {
-        return Err(io::Error::new(
-            io::ErrorKind::InvalidData,
-            format!("payload too large: {payload_len}"),
-        ));
+/// Take one complete plaintext frame (`0x00`, payload length, message type, payload) off the front of `rx`,
+/// `None` until the whole frame has arrived.
+fn take_plain_frame(rx: &mut Vec<u8>) -> Result<Option<(u32, Vec<u8>)>, PlainFrameError> {
+    let Some(&preamble) = rx.first() else {
+        return Ok(None);
+    };
+    if preamble != 0x00 {
+        return Err(PlainFrameError::Preamble(preamble));
     }
 
-    let mut payload = vec![0_u8; payload_len];
-    if payload_len > 0 {
-        stream.read_exact(&mut payload).await?;
+    let mut idx = 1;
+    let Some(payload_len) = peek_varuint(rx, &mut idx)? else {
+        return Ok(None);
+    };
+    let payload_len = payload_len as usize;
+    if payload_len > PLAIN_FRAME_MAX {
+        return Err(PlainFrameError::TooLarge(payload_len));
+    }
+    let Some(msg_type) = peek_varuint(rx, &mut idx)? else {
+        return Ok(None);
+    };
+    if rx.len() < idx + payload_len {
+        return Ok(None);
     }
-    Ok((msg_type, payload))
+
+    let payload = rx.drain(..idx + payload_len).skip(idx).collect();
+    Ok(Some((msg_type as u32, payload)))
 }
 
-async fn send_frame(stream: &mut TcpStream, msg_type: ApiMessageType, payload: &[u8]) -> io::Result<()> {
+async fn send_plain_frame(stream: &mut TcpStream, msg_type: ApiMessageType, payload: &[u8]) -> io::Result<()> {
     let mut frame = Vec::with_capacity(1 + 10 + 10 + payload.len());
     frame.push(0x00);
     put_varuint(payload.len() as u64, &mut frame);
@@ -633,18 +1184,22 @@ async fn send_frame(stream: &mut TcpStream, msg_type: ApiMessageType, payload: &
     stream.write_all(&frame).await
 }
 
-async fn read_varuint_async(stream: &mut TcpStream) -> io::Result<u64> {
+/// Varuint at `idx` of a partly received frame, `None` while it is incomplete.
+fn peek_varuint(data: &[u8], idx: &mut usize) -> Result<Option<u64>, PlainFrameError> {
     let mut result = 0_u64;
     let mut shift = 0_u32;
     for _ in 0..10 {
-        let byte = stream.read_u8().await?;
+        let Some(&byte) = data.get(*idx) else {
+            return Ok(None);
+        };
+        *idx += 1;
         result |= (u64::from(byte & 0x7F)) << shift;
         if (byte & 0x80) == 0 {
-            return Ok(result);
+            return Ok(Some(result));
         }
         shift += 7;
     }
-    Err(io::Error::new(io::ErrorKind::InvalidData, "varuint overflow"))
+    Err(PlainFrameError::VarintOverflow)
 }
 
 fn parse_hello_request(payload: &[u8]) -> Option<(String, u32, u32)> {
@@ -688,6 +1243,40 @@ fn parse_hello_request(payload: &[u8]) -> Option<(String, u32, u32)> {
     Some((client_info, major, minor))
 }
 
+/// Extract the entity key (field 1, fixed32) from a ButtonCommandRequest.
+fn parse_button_command(payload: &[u8]) -> Option<u32> {
+    let mut idx = 0_usize;
+    let mut key = None;
+
+    while idx < payload.len() {
+        let tag = read_varuint_from_slice(payload, &mut idx)?;
+        let field_number = (tag >> 3) as u32;
+        match tag & 0x07 {
+            0 => {
+                read_varuint_from_slice(payload, &mut idx)?;
+            }
+            2 => {
+                let len = read_varuint_from_slice(payload, &mut idx)? as usize;
+                idx += len;
+            }
+            5 => {
+                let bytes = payload.get(idx..idx + 4)?;
+                if field_number == 1 {
+                    key = Some(u32::from_le_bytes(bytes.try_into().ok()?));
+                }
+                idx += 4;
+            }
+            1 => idx += 8,
+            _ => return None,
+        }
+        if idx > payload.len() {
+            return None;
+        }
+    }
+
+    key
+}
+
 fn read_varuint_from_slice(data: &[u8], idx: &mut usize) -> Option<u64> {
     let mut out = 0_u64;
     let mut shift = 0_u32;
@@ -756,4 +1345,48 @@ fn pb_put_string(field_number: u32, value: &str, out: &mut Vec<u8>) {
     put_varuint(value.len() as u64, out);
     out.extend_from_slice(value.as_bytes());
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_frame_truncated() {
+        // PingRequest (type 7, empty) followed by a DeviceInfoRequest (type 9) with a 2-byte payload
+        let stream = [0x00, 0x00, 0x07, 0x00, 0x02, 0x09, 0xAA, 0xBB];
+        for end in 0..3 {
+            let mut rx = stream[..end].to_vec();
+            assert!(take_plain_frame(&mut rx).unwrap().is_none());
+            assert_eq!(rx.len(), end);
+        }
+
+        let mut rx = stream[..7].to_vec();
+        assert_eq!(take_plain_frame(&mut rx).unwrap(), Some((7, vec![])));
+        assert_eq!(rx, [0x00, 0x02, 0x09, 0xAA]);
+        assert!(take_plain_frame(&mut rx).unwrap().is_none());
+
+        rx.push(0xBB);
+        assert_eq!(take_plain_frame(&mut rx).unwrap(), Some((9, vec![0xAA, 0xBB])));
+        assert!(rx.is_empty());
+    }
+
+    #[test]
+    fn plain_frame_garbage() {
+        let mut rx = b"GET / HTTP/1.1".to_vec();
+        assert!(matches!(
+            take_plain_frame(&mut rx),
+            Err(PlainFrameError::Preamble(b'G'))
+        ));
+
+        let mut rx = vec![0x00, 0xFF, 0xFF, 0x7F, 0x07];
+        assert!(matches!(take_plain_frame(&mut rx), Err(PlainFrameError::TooLarge(_))));
+
+        let mut rx = vec![0x00];
+        rx.extend_from_slice(&[0xFF; 10]);
+        assert!(matches!(
+            take_plain_frame(&mut rx),
+            Err(PlainFrameError::VarintOverflow)
+        ));
+    }
+}
 // EOF
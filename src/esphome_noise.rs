@@ -0,0 +1,169 @@
+// esphome_noise.rs — ESPHome native API Noise transport (Noise_NNpsk0_25519_ChaChaPoly_SHA256)
+
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use tokio::{
+    io::{self, AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+const NOISE_PARAMS: &str = "Noise_NNpsk0_25519_ChaChaPoly_SHA256";
+const NOISE_PROLOGUE: &[u8] = b"NoiseAPIInit\x00\x00";
+const NOISE_INDICATOR: u8 = 0x01;
+const NOISE_PROTOCOL_VERSION: u8 = 0x01;
+const NOISE_MAX_MESSAGE: usize = 65535;
+const NOISE_TAG_LEN: usize = 16;
+
+/// Decode the base64 ESPHome API encryption key. Returns `None` unless it is exactly 32 bytes.
+pub fn parse_noise_psk(psk: &str) -> Option<[u8; 32]> {
+    BASE64.decode(psk.trim()).ok()?.try_into().ok()
+}
+
+/// Encrypted ESPHome API session after a completed handshake.
+pub struct NoiseSession {
+    transport: snow::TransportState,
+}
+
+impl NoiseSession {
+    /// Run the server side of the ESPHome Noise handshake:
+    /// client hello → server hello, client handshake → server handshake.
+    pub async fn accept(stream: &mut TcpStream, psk: &[u8; 32], node_name: &str, mac: &str) -> io::Result<Self> {
+        // Client hello carries no data we need. A plaintext client gets the same explicit
+        // reject as from ESPHome, which Home Assistant reports as "encryption required".
+        if let Err(e) = read_noise_frame(stream).await {
+            if e.kind() == io::ErrorKind::InvalidData {
+                let mut reply = vec![0x01];
+                reply.extend_from_slice(b"Bad indicator byte");
+                write_noise_frame(stream, &reply).await?;
+            }
+            return Err(e);
+        }
+
+        let mut hello = vec![NOISE_PROTOCOL_VERSION];
+        hello.extend_from_slice(node_name.as_bytes());
+        hello.push(0x00);
+        hello.extend_from_slice(mac.as_bytes());
+        hello.push(0x00);
+        write_noise_frame(stream, &hello).await?;
+
+        let handshake = read_noise_frame(stream).await?;
+        let mut responder = snow::Builder::new(NOISE_PARAMS.parse().map_err(noise_error)?)
+            .prologue(NOISE_PROLOGUE)
+            .map_err(noise_error)?
+            .psk(0, psk)
+            .map_err(noise_error)?
+            .build_responder()
+            .map_err(noise_error)?;
+
+        // Handshake frame: status byte 0x00 followed by the Noise message
+        let mut buf = vec![0_u8; NOISE_MAX_MESSAGE];
+        let read_result = match handshake.split_first() {
+            Some((0x00, message)) => responder.read_message(message, &mut buf).map_err(noise_error),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidData, "bad handshake frame")),
+        };
+        if let Err(e) = read_result {
+            let mut reply = vec![0x01];
+            reply.extend_from_slice(b"Handshake MAC failure");
+            write_noise_frame(stream, &reply).await?;
+            return Err(e);
+        }
+
+        let len = responder.write_message(&[], &mut buf).map_err(noise_error)?;
+        let mut reply = Vec::with_capacity(1 + len);
+        reply.push(0x00);
+        reply.extend_from_slice(&buf[..len]);
+        write_noise_frame(stream, &reply).await?;
+
+        let transport = responder.into_transport_mode().map_err(noise_error)?;
+        Ok(Self { transport })
+    }
+
+    /// Take one complete frame off the front of `rx` and decrypt it. Returns (message type, protobuf payload),
+    /// or `None` until the whole frame has arrived. Any error means the session is out of step and must be closed.
+    pub fn take_message(&mut self, rx: &mut Vec<u8>) -> io::Result<Option<(u32, Vec<u8>)>> {
+        let Some(&indicator) = rx.first() else {
+            return Ok(None);
+        };
+        if indicator != NOISE_INDICATOR {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid noise indicator 0x{indicator:02X}"),
+            ));
+        }
+        if rx.len() < 3 {
+            return Ok(None);
+        }
+        let len = usize::from(u16::from_be_bytes([rx[1], rx[2]]));
+        if rx.len() < 3 + len {
+            return Ok(None);
+        }
+        let frame = rx.drain(..3 + len).skip(3).collect::<Vec<u8>>();
+        self.decrypt_message(&frame).map(Some)
+    }
+
+    fn decrypt_message(&mut self, frame: &[u8]) -> io::Result<(u32, Vec<u8>)> {
+        let mut buf = vec![0_u8; frame.len()];
+        let len = self.transport.read_message(frame, &mut buf).map_err(noise_error)?;
+        if len < 4 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "noise message too short"));
+        }
+
+        // Plaintext: message type (u16 BE), payload length (u16 BE), payload
+        let msg_type = u32::from(u16::from_be_bytes([buf[0], buf[1]]));
+        let payload_len = usize::from(u16::from_be_bytes([buf[2], buf[3]]));
+        if 4 + payload_len > len {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "noise payload length mismatch",
+            ));
+        }
+        Ok((msg_type, buf[4..4 + payload_len].to_vec()))
+    }
+
+    /// Encrypt and send one API message.
+    pub async fn write_message(&mut self, stream: &mut TcpStream, msg_type: u32, payload: &[u8]) -> io::Result<()> {
+        if 4 + payload.len() + NOISE_TAG_LEN > NOISE_MAX_MESSAGE {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "noise message too large"));
+        }
+
+        let mut plain = Vec::with_capacity(4 + payload.len());
+        plain.extend_from_slice(&(msg_type as u16).to_be_bytes());
+        plain.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+        plain.extend_from_slice(payload);
+
+        let mut buf = vec![0_u8; plain.len() + NOISE_TAG_LEN];
+        let len = self.transport.write_message(&plain, &mut buf).map_err(noise_error)?;
+        write_noise_frame(stream, &buf[..len]).await
+    }
+}
+
+async fn read_noise_frame(stream: &mut TcpStream) -> io::Result<Vec<u8>> {
+    let indicator = stream.read_u8().await?;
+    if indicator != NOISE_INDICATOR {
+        let msg = if indicator == 0x00 {
+            "plaintext client, but esphome_psk requires encryption".to_string()
+        } else {
+            format!("invalid noise indicator 0x{indicator:02X}")
+        };
+        return Err(io::Error::new(io::ErrorKind::InvalidData, msg));
+    }
+
+    let len = usize::from(stream.read_u16().await?);
+    let mut frame = vec![0_u8; len];
+    if len > 0 {
+        stream.read_exact(&mut frame).await?;
+    }
+    Ok(frame)
+}
+
+async fn write_noise_frame(stream: &mut TcpStream, data: &[u8]) -> io::Result<()> {
+    let mut frame = Vec::with_capacity(3 + data.len());
+    frame.push(NOISE_INDICATOR);
+    frame.extend_from_slice(&(data.len() as u16).to_be_bytes());
+    frame.extend_from_slice(data);
+    stream.write_all(&frame).await
+}
+
+fn noise_error(e: snow::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("noise error: {e}"))
+}
+// EOF
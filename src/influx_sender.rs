@@ -0,0 +1,127 @@
+// influx_sender.rs — InfluxDB v2 line-protocol push
+
+use embedded_svc::http::client::Client as HttpClient;
+use esp_idf_svc::{http::client::Configuration as HttpConfiguration, io::Write};
+
+use crate::*;
+
+const INFLUX_MEASUREMENT: &str = "watermeter";
+const INFLUX_HTTP_TIMEOUT_SECS: u64 = 10;
+
+pub async fn run_influx(state: Arc<Pin<Box<MyState>>>) -> AppResult<()> {
+    let (url, org, bucket, token, meter_id) = {
+        let config = state.config.read().await;
+        (
+            config.influx_url.clone(),
+            config.influx_org.clone(),
+            config.influx_bucket.clone(),
+            config.influx_token.clone(),
+            config.meter_id.clone(),
+        )
+    };
+
+    if url.is_empty() || bucket.is_empty() {
+        info!("InfluxDB push is disabled by configuration.");
+        // we cannot return, otherwise tokio::select in main() will exit
+        loop {
+            sleep(Duration::from_secs(3600)).await;
+        }
+    }
+
+    loop {
+        if *state.net_up.read().await {
+            break;
+        }
+        sleep(Duration::from_secs(5)).await;
+    }
+
+    let write_url = influx_write_url(&url, &org, &bucket);
+    info!("InfluxDB push enabled: {write_url}");
+
+    let mut reading_rx = state.latest_data.subscribe();
+    loop {
+        sleep(Duration::from_secs(10)).await;
+        if !*state.net_up.read().await {
+            continue;
+        }
+
+        if !reading_rx.has_changed().unwrap_or(false) {
+            continue;
+        }
+        let Some(reading) = reading_rx.borrow().clone() else {
+            continue;
+        };
+        // Uptime-relative timestamps are useless in a time series, wait for NTP to correct them
+        if !reading.time_synced {
+            continue;
+        }
+        reading_rx.mark_unchanged();
+
+        let line = influx_line(&meter_id, &reading);
+        info!("InfluxDB sending: {line}");
+        // Failures are only logged, the next fresh reading will be sent normally.
+        match influx_post(&write_url, &token, &line) {
+            Ok(status) if (200..300).contains(&status) => {}
+            Ok(status) => error!("InfluxDB write failed: HTTP {status}"),
+            Err(e) => error!("InfluxDB write failed: {e}"),
+        }
+    }
+}
+
+fn influx_write_url(url: &str, org: &str, bucket: &str) -> String {
+    let mut write_url = format!(
+        "{}/api/v2/write?bucket={bucket}&precision=ns",
+        url.trim_end_matches('/')
+    );
+    if !org.is_empty() {
+        write_url.push_str(&format!("&org={org}"));
+    }
+    write_url
+}
+
+/// Format one reading as an InfluxDB line-protocol record with a nanosecond timestamp.
+fn influx_line(meter_id: &str, reading: &MeterReading) -> String {
+    format!(
+        "{INFLUX_MEASUREMENT},meter={meter_id} total_l={}i,month_start_l={}i,total_m3={},month_start_m3={},flow_temp={}i,ambient_temp={}i,info_codes={}i {}",
+        reading.total_l,
+        reading.month_start_l,
+        reading.total_m3,
+        reading.month_start_m3,
+        reading.flow_temp,
+        reading.ambient_temp,
+        reading.info_codes,
+        reading.timestamp * 1_000_000_000,
+    )
+}
+
+fn influx_post(url: &str, token: &str, line: &str) -> AppResult<u16> {
+    let conn = EspHttpConnection::new(&HttpConfiguration {
+        timeout: Some(Duration::from_secs(INFLUX_HTTP_TIMEOUT_SECS)),
+        crt_bundle_attach: Some(esp_idf_sys::esp_crt_bundle_attach),
+        ..Default::default()
+    })?;
+    let mut client = HttpClient::wrap(conn);
+
+    let content_length = line.len().to_string();
+    let auth = format!("Token {token}");
+    let mut headers = vec![
+        ("content-type", "text/plain; charset=utf-8"),
+        ("content-length", content_length.as_str()),
+    ];
+    if !token.is_empty() {
+        headers.push(("authorization", auth.as_str()));
+    }
+
+    let mut req = client
+        .post(url, &headers)
+        .map_err(|e| AppError::Message(format!("HTTP request error: {e:?}")))?;
+    req.write_all(line.as_bytes())
+        .map_err(|e| AppError::Message(format!("HTTP write error: {e:?}")))?;
+    req.flush()
+        .map_err(|e| AppError::Message(format!("HTTP write error: {e:?}")))?;
+    let resp = req
+        .submit()
+        .map_err(|e| AppError::Message(format!("HTTP submit error: {e:?}")))?;
+    Ok(resp.status())
+}
+// EOF
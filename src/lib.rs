@@ -14,7 +14,7 @@ pub use askama::Template;
 pub use chrono::*;
 pub use esp_idf_hal::{
     delay::FreeRtos,
-    gpio::{AnyInputPin, Input, InputPin, Output, PinDriver, Pull},
+    gpio::{AnyInputPin, AnyOutputPin, Input, InputPin, Output, PinDriver, Pull},
     peripherals::Peripherals,
     spi,
     units::Hertz,
@@ -34,11 +34,13 @@ pub use esp_idf_sys::EspError;
 pub use log::*;
 pub use serde::{Deserialize, Serialize};
 pub use tokio::{
-    sync::RwLock,
+    sync::{RwLock, mpsc, oneshot, watch},
     time::{Duration, sleep, timeout},
 };
 
 pub const FW_VERSION: &str = env!("CARGO_PKG_VERSION");
+// Set by build_data in build.rs
+pub const FW_BUILD_TIME: &str = env!("SOURCE_TIMESTAMP");
 pub const AP_MODE_SSID: &str = "esp32multical21";
 pub const AP_MODE_IP_ADDR: net::Ipv4Addr = net::Ipv4Addr::new(10, 42, 42, 1);
 pub const AP_MODE_IP_MASK: u8 = 24;
@@ -53,6 +55,9 @@ pub const LED_ACTIVE_LOW: bool = true;
 #[cfg(all(not(feature = "esp32-c3"), feature = "esp-wroom-32"))]
 pub const LED_ACTIVE_LOW: bool = false;
 
+// Set by build_pin_map in build.rs
+include!(concat!(env!("OUT_DIR"), "/board_gpios.rs"));
+
 pub type AppResult<T> = Result<T, AppError>;
 
 #[derive(Debug, thiserror::Error)]
@@ -67,26 +72,99 @@ pub enum AppError {
     Json(#[from] serde_json::Error),
     #[error("Radio error: {0}")]
     Radio(#[from] crate::radio::Cc1101RadioError),
+    /// Unusable request from the client, answered with 400 by the API
+    #[error("{0}")]
+    BadRequest(String),
+    /// Something the request needs is not up yet, answered with 503 by the API
+    #[error("{0}")]
+    NotReady(String),
+    /// The request clashes with one already in progress, answered with 409 by the API
+    #[error("{0}")]
+    Conflict(String),
     #[error("{0}")]
     Message(String),
 }
 
-#[derive(Clone, Debug, Serialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct MeterReading {
     pub total_l: u32,
     pub month_start_l: u32,
     pub total_m3: f32,
     pub month_start_m3: f32,
-    pub flow_temp: u8,
-    pub ambient_temp: u8,
+    pub month_consumption_l: u32,
+    pub flow_temp: i8,
+    pub ambient_temp: i8,
     pub info_codes: u8,
+    pub meter_datetime: Option<String>,
+    pub prev_period_date: Option<String>,
     pub timestamp: i64,
     pub timestamp_s: String,
+    pub time_synced: bool,
+}
+
+/// Compact history entry, numeric fields of a `MeterReading` only.
+#[derive(Clone, Debug, Serialize)]
+pub struct HistoryEntry {
+    pub timestamp: i64,
+    pub total_l: u32,
+    pub month_start_l: u32,
+    pub flow_temp: i8,
+    pub ambient_temp: i8,
+    pub info_codes: u8,
+}
+
+impl From<&MeterReading> for HistoryEntry {
+    fn from(r: &MeterReading) -> Self {
+        Self {
+            timestamp: r.timestamp,
+            total_l: r.total_l,
+            month_start_l: r.month_start_l,
+            flow_temp: r.flow_temp,
+            ambient_temp: r.ambient_temp,
+            info_codes: r.info_codes,
+        }
+    }
+}
+
+/// Most recently received raw radio frame, kept for debugging.
+#[derive(Clone, Debug)]
+pub struct LastFrame {
+    pub timestamp: i64,
+    pub raw: Vec<u8>,
+    pub parsed: bool,
+    pub rssi_dbm: Option<f32>,
+    pub lqi: Option<u8>,
 }
 
 #[derive(Clone, Debug, Serialize)]
 pub struct Uptime {
     pub uptime: usize,
+    /// CC1101 detected and initialized, `None` until the radio task has tried
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub radio_ok: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HistoryQuery {
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ConfigQuery {
+    pub secrets: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct InjectFrame {
+    pub hex: String,
+}
+
+/// Body of `POST /api/meterkey`, an empty `meter_id` keeps the configured one.
+#[derive(Debug, Deserialize)]
+pub struct MeterKeyUpdate {
+    pub meter_key: String,
+    #[serde(default)]
+    pub meter_id: String,
 }
 
 #[derive(Debug, Deserialize)]
@@ -94,34 +172,80 @@ pub struct UpdateFirmware {
     pub url: String,
 }
 
+/// Progress of the background firmware update, reported in `/api/status`.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct OtaProgress {
+    pub running: bool,
+    /// Bytes written to the inactive OTA slot so far
+    pub bytes: usize,
+    /// The image is complete and the device is about to reboot
+    pub done: bool,
+    /// Why the last update failed, cleared when a new one starts
+    pub error: Option<String>,
+}
+
+/// JSON command received on the `{mqtt_topic}/cmd` topic.
+#[derive(Debug, Deserialize)]
+pub struct MqttCommand {
+    pub action: String,
+    pub url: Option<String>,
+}
+
 pub mod radio;
-pub use radio::Cc1101Radio;
+pub use radio::{Cc1101Radio, RadioCommand, RadioSnapshot, RadioSource};
+
+mod mock_radio;
+pub use mock_radio::*;
 
 mod wmbus;
 pub use wmbus::*;
 
+mod decoder;
+pub use decoder::*;
+
 mod multical21;
 pub use multical21::*;
 
+mod nvs_crypt;
+pub use nvs_crypt::*;
+
 mod config;
 pub use config::*;
 
 mod state;
 pub use state::*;
 
+mod consumption;
+pub use consumption::*;
+
 mod measure;
 pub use measure::*;
 
 mod mqtt_sender;
 pub use mqtt_sender::*;
 
+mod influx_sender;
+pub use influx_sender::*;
+
+mod webhook_sender;
+pub use webhook_sender::*;
+
 mod apiserver;
 pub use apiserver::*;
 
 mod esphome_api;
 pub use esphome_api::*;
 
+mod esphome_noise;
+pub use esphome_noise::*;
+
 mod wifi;
 pub use wifi::*;
 
+mod syslog;
+pub use syslog::*;
+
+mod status_led;
+pub use status_led::*;
+
 // EOF
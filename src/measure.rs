@@ -1,11 +1,18 @@
 // measure.rs — Radio reception + wMBus data pipeline
 
+use std::{sync::atomic::Ordering, time::Instant};
+
 use crate::*;
 
-// Radio watchdog timeout: restart if no packet in set time
-const RADIO_WAIT_SECS: u64 = 600;
+// Meters transmit less at night, the radio watchdog timeout is multiplied
+// by this factor between these local hours
+const NIGHT_WATCHDOG_FACTOR: u64 = 2;
+const NIGHT_START_HOUR: i64 = 0;
+const NIGHT_END_HOUR: i64 = 6;
+// How often to look for the CC1101 again when it is not detected
+const RADIO_DETECT_RETRY_SECS: u64 = 60;
 
-pub async fn read_meter(state: Arc<Pin<Box<MyState>>>, mut radio: Cc1101Radio<'_>) -> AppResult<()> {
+pub async fn read_meter<R: RadioSource>(state: Arc<Pin<Box<MyState>>>, mut radio: R) -> AppResult<()> {
     loop {
         if *state.net_up.read().await {
             break;
@@ -14,54 +21,336 @@ pub async fn read_meter(state: Arc<Pin<Box<MyState>>>, mut radio: Cc1101Radio<'_
     }
     info!("Network is up.");
 
+    let mut radio_cmd_rx = state
+        .radio_cmd_rx
+        .write()
+        .await
+        .take()
+        .ok_or_else(|| AppError::Message("Radio command channel already taken".into()))?;
+
     // Parse meter config
-    let (meter_id, meter_key) = {
-        let config = state.config.read().await;
-        match (config.meter_id_bytes(), config.meter_key_bytes()) {
-            (Some(id), Some(key)) => (id, key),
-            _ => {
-                warn!("No valid meter_id and/or meter_key configured.");
-                error!("Now we are doing nothing useful. Radio is idle.");
-                loop {
-                    sleep(Duration::from_secs(3600)).await;
+    let credentials = (
+        state.config.read().await.meter_id_bytes(),
+        meter_key_bytes(&*state.nvs.read().await),
+    );
+    let (mut meter_id, mut meter_key) = match credentials {
+        (Some(id), Some(key)) => (id, key),
+        _ => {
+            // Reported as config_incomplete on the status page, /api/status and ESPHome
+            warn!("No valid meter_id and/or meter_key configured.");
+            error!("Now we are doing nothing useful. Radio is idle, {CONFIG_INCOMPLETE_MSG} and save.");
+            // POST /api/meterkey can still bring the radio up without a reboot
+            loop {
+                match radio_cmd_rx.recv().await {
+                    Some(RadioCommand::Rekey(id, key, reply)) => {
+                        state.config_incomplete.store(false, Ordering::Relaxed);
+                        reply.send(Ok(())).ok();
+                        break (id, key);
+                    }
+                    Some(cmd) => radio.handle_command(cmd),
+                    None => return Err(AppError::Message("Radio command channel closed".into())),
                 }
             }
         }
     };
 
+    let tz_offset_minutes = state.config.read().await.tz_offset_minutes;
+    let dedup_window = Duration::from_secs(state.config.read().await.dedup_window_secs as u64);
+    // Never go below the minimum even if nvs holds an older, lower value
+    let watchdog_secs = state
+        .config
+        .read()
+        .await
+        .radio_watchdog_secs
+        .max(RADIO_WATCHDOG_MIN_SECS) as u64;
+    let max_restarts = state.config.read().await.radio_max_restarts;
+    let mut restarts: u8 = 0;
+    let quiet_alarm = Duration::from_secs(state.config.read().await.radio_quiet_alarm_secs as u64);
+    let listening_since = Instant::now();
+
     info!(
         "Meter ID: {:02X}{:02X}{:02X}{:02X}, key configured. Initializing radio...",
         meter_id[0], meter_id[1], meter_id[2], meter_id[3]
     );
 
-    radio.init()?;
+    radio.set_freq_hz(state.config.read().await.radio_freq_hz as u64);
+    match radio::parse_register_overrides(&state.config.read().await.radio_registers) {
+        Some(overrides) => radio.set_register_overrides(overrides),
+        None => warn!("CC1101: Invalid radio_registers, ignored"),
+    }
+    radio.set_stats(state.rx_stats.clone());
+    init_radio(&state, &mut radio).await?;
+    if state.config.read().await.low_power {
+        radio.enable_gdo0_wakeup()?;
+        enable_light_sleep()?;
+    }
 
     info!("Waiting for wMBus packets...");
     loop {
-        match radio.wait_for_packet(RADIO_WAIT_SECS).await? {
+        if !quiet_alarm.is_zero() {
+            state.check_meter_quiet(quiet_alarm, listening_since).await;
+        }
+
+        // A command interrupts the wait, the watchdog period starts over after it.
+        let wait_secs = watchdog_timeout(watchdog_secs, tz_offset_minutes);
+        let packet = tokio::select! {
+            packet = radio.wait_for_packet(wait_secs) => packet?,
+            Some(cmd) = radio_cmd_rx.recv() => {
+                match cmd {
+                    RadioCommand::Inject(payload, reply) => {
+                        info!("Injecting frame ({} bytes)", payload.len());
+                        let result = process_frame(
+                            &state,
+                            payload,
+                            &meter_id,
+                            &meter_key,
+                            tz_offset_minutes,
+                            (None, None),
+                        )
+                        .await;
+                        reply.send(result).ok();
+                    }
+                    RadioCommand::Rekey(id, key, reply) => {
+                        info!(
+                            "New meter credentials for {:02X}{:02X}{:02X}{:02X}, restarting reception",
+                            id[0], id[1], id[2], id[3]
+                        );
+                        meter_id = id;
+                        meter_key = key;
+                        let result = radio.restart_radio().map_err(|e| e.to_string());
+                        if let Err(e) = &result {
+                            error!("CC1101: Restart failed: {e}");
+                        }
+                        reply.send(result).ok();
+                    }
+                    cmd => radio.handle_command(cmd),
+                }
+                continue;
+            }
+        };
+
+        match packet {
             Some(payload) => {
+                restarts = 0;
                 info!("Got wMBus packet ({} bytes), parsing...", payload.len());
-                match parse_frame(&payload, &meter_id, &meter_key) {
-                    Some(reading) => {
-                        info!("Meter reading: {:?}", reading);
-                        *state.latest_data.write().await = Some(reading);
-                        *state.data_updated.write().await = true;
+                let (rssi, lqi) = radio.last_signal();
+                *state.last_rssi.write().await = rssi;
+                *state.last_lqi.write().await = lqi;
+                if !dedup_window.is_zero()
+                    && let (Some(id), Some(acc)) = (frame_meter_id(&payload), frame_acc(&payload))
+                    && state.is_duplicate_frame(id, acc, dedup_window).await
+                {
+                    info!("Dropping duplicate frame (ACC={acc:02X})");
+                    continue;
+                }
+                match process_frame(&state, payload, &meter_id, &meter_key, tz_offset_minutes, (rssi, lqi)).await {
+                    Ok(_) => {
                         // The next packet will not come very soon, so we can sleep here safely
                         state.led_on().await?;
                         sleep(Duration::from_millis(2000)).await;
                         state.led_off().await?;
                     }
-                    None => {
-                        info!("Packet did not yield a valid reading");
+                    Err(e) => {
+                        info!("Packet did not yield a valid reading: {e:?}");
                     }
                 }
             }
             None => {
-                // Watchdog timeout, restart radio
-                warn!("No packets received in {RADIO_WAIT_SECS} s, restarting radio...");
-                radio.restart_radio()?;
+                // Watchdog timeout, restart the radio. Rebooting is opt-in through radio_max_restarts,
+                // a silent meter alone keeps the device up and reachable.
+                if max_restarts > 0 && restarts >= max_restarts {
+                    error!("No packets after {restarts} radio restarts, rebooting.");
+                    *state.reset.write().await = true;
+                    continue;
+                }
+                restarts = restarts.saturating_add(1);
+                warn!("No packets received in {wait_secs} s, restarting radio ({restarts})...");
+                // radio_ok reports a failure, the next watchdog period retries
+                if let Err(e) = init_radio(&state, &mut radio).await {
+                    error!("Radio restart failed: {e}");
+                }
             }
         }
     }
 }
+
+/// Decode one received frame and publish the reading to all consumers.
+/// Received packets and frames injected through `RadioCommand::Inject` take exactly the same path.
+pub async fn process_frame(
+    state: &Arc<Pin<Box<MyState>>>,
+    payload: Vec<u8>,
+    meter_id: &[u8; 4],
+    meter_key: &[u8; 16],
+    tz_offset_minutes: i16,
+    (rssi, lqi): (Option<f32>, Option<u8>),
+) -> Result<MeterReading, FrameError> {
+    let parsed = parse_frame(&payload, meter_id, meter_key);
+    state.rx_stats.decode_result(&parsed);
+    if check_meter_id(&payload, meter_id) {
+        if let Some(header) = FrameHeader::from_raw(&payload) {
+            *state.last_header.write().await = Some(header);
+        }
+        if let Some(mode) = frame_security(&payload) {
+            *state.last_security.write().await = Some(mode);
+        }
+    }
+    let (timestamp, time_synced) = state.reading_timestamp().await;
+    let frame = LastFrame {
+        timestamp,
+        raw: payload,
+        parsed: parsed.is_ok(),
+        rssi_dbm: rssi,
+        lqi,
+    };
+    state.push_capture(&frame).await;
+    *state.last_frame.write().await = Some(frame);
+
+    let mut reading = parsed?;
+    *state.last_decoded.write().await = Some(Instant::now());
+    reading.timestamp = timestamp;
+    reading.time_synced = time_synced;
+    // timestamp stays in UTC, only the display string is localized
+    reading.timestamp_s = format_timestamp(reading.timestamp, tz_offset_minutes);
+    info!("Meter reading: {:?}", reading);
+    if let Some(prev_total) = state.check_counter_reset(&reading).await {
+        warn!(
+            "Meter counter reset: total {prev_total} L -> {} L, new baseline stored",
+            reading.total_l
+        );
+    }
+    if let Err(e) = state.persist_reading(&reading).await {
+        error!("Cannot persist meter reading: {e}");
+    }
+    state.push_history(&reading).await;
+    state.consumption.write().await.update(&reading, tz_offset_minutes);
+    state.latest_data.send_replace(Some(reading.clone()));
+    Ok(reading)
+}
+
+/// Initialize the CC1101 and record the outcome in `radio_ok`.
+/// A missing chip is not fatal: keep looking for it, other tasks stay useful meanwhile.
+async fn init_radio<R: RadioSource>(state: &Arc<Pin<Box<MyState>>>, radio: &mut R) -> AppResult<()> {
+    loop {
+        match radio.init() {
+            Ok(()) => {
+                *state.radio_ok.write().await = Some(true);
+                return Ok(());
+            }
+            Err(e @ radio::Cc1101RadioError::ChipNotDetected { .. }) => {
+                *state.radio_ok.write().await = Some(false);
+                error!("{e}, check the wiring. Retrying in {RADIO_DETECT_RETRY_SECS} s...");
+                sleep(Duration::from_secs(RADIO_DETECT_RETRY_SECS)).await;
+            }
+            Err(e) => {
+                *state.radio_ok.write().await = Some(false);
+                return Err(e.into());
+            }
+        }
+    }
+}
+
+/// Let the MCU drop into light sleep whenever every task is idle (tickless idle).
+/// The CC1101 stays in RX and wakes us through GDO0; tokio timers and the WiFi driver
+/// hold power management locks while they are busy, so nothing in flight is cut short.
+fn enable_light_sleep() -> AppResult<()> {
+    let pm_config = esp_idf_sys::esp_pm_config_t {
+        max_freq_mhz: esp_idf_sys::CONFIG_ESP_DEFAULT_CPU_FREQ_MHZ as i32,
+        min_freq_mhz: esp_idf_sys::CONFIG_XTAL_FREQ as i32,
+        light_sleep_enable: true,
+    };
+    esp_idf_sys::esp!(unsafe { esp_idf_sys::esp_pm_configure(&pm_config as *const _ as *const core::ffi::c_void) })?;
+    info!("Low power mode: automatic light sleep enabled");
+    Ok(())
+}
+
+/// Radio watchdog timeout for the current local time, longer during the night.
+pub fn watchdog_timeout(watchdog_secs: u64, tz_offset_minutes: i16) -> u64 {
+    let local = Utc::now().timestamp() + tz_offset_minutes as i64 * 60;
+    let hour = local.rem_euclid(86400) / 3600;
+    if (NIGHT_START_HOUR..NIGHT_END_HOUR).contains(&hour) {
+        watchdog_secs * NIGHT_WATCHDOG_FACTOR
+    } else {
+        watchdog_secs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Frame and key of the wmbusmeters Multical 21 test vector, see the wmbus tests
+    const FRAME: &str = "2C442D2C998734761B168D2091D37CAC21E1D68CDAFFCD3DC452BD802913FF7B1706CA9E355D6C2701CC2427BD";
+    const KEY: [u8; 16] = [
+        0x28, 0xF6, 0x4A, 0x24, 0x98, 0x80, 0x64, 0xA0, 0x79, 0xAA, 0x2C, 0x80, 0x7D, 0x61, 0x02, 0xAE,
+    ];
+
+    /// Station mode state with the test vector's meter configured, on its own nvs namespace.
+    fn mock_state() -> Arc<Pin<Box<MyState>>> {
+        let partition = nvs::EspDefaultNvsPartition::take().unwrap();
+        let mut nvs = nvs::EspNvs::new(partition, "mc21test", true).unwrap();
+        save_meter_key(&mut nvs, &KEY).unwrap();
+        let config = MyConfig {
+            meter_id: "76348799".into(),
+            ..Default::default()
+        };
+        let (_, led_gpio) = BOARD_GPIOS.iter().find(|(name, _)| *name == "LED").unwrap();
+        // SAFETY: nothing else in the test binary takes the peripherals or drives the LED pin
+        let led = PinDriver::output(unsafe { AnyOutputPin::steal(*led_gpio as _) }).unwrap();
+        Arc::new(Box::pin(MyState::new(false, config, nvs, "test".into(), led)))
+    }
+
+    fn frame(payload: Vec<u8>, rssi_dbm: f32) -> MockFrame {
+        MockFrame {
+            delay: Duration::ZERO,
+            payload,
+            rssi_dbm: Some(rssi_dbm),
+            lqi: Some(40),
+        }
+    }
+
+    #[test]
+    fn read_meter_from_mock_radio() {
+        let state = mock_state();
+        let captured = parse_hex(FRAME).unwrap();
+        // Another meter's ID: received and counted, but not ours
+        let mut other = captured.clone();
+        other[4] = 0x11;
+        let radio = MockRadio::new([
+            frame(captured.clone(), -70.0),
+            frame(captured, -71.0),
+            frame(other, -72.0),
+        ]);
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            *state.net_up.write().await = true;
+            // The frames are handled in order, so the last one being counted means all are done
+            let done = async {
+                while state.rx_stats.meter_id_mismatches.load(Ordering::Relaxed) == 0 {
+                    sleep(Duration::from_millis(100)).await;
+                }
+            };
+            tokio::select! {
+                result = read_meter(state.clone(), radio) => panic!("read_meter returned {result:?}"),
+                result = timeout(Duration::from_secs(30), done) => result.unwrap(),
+            }
+        });
+
+        let stats = &state.rx_stats;
+        assert_eq!(stats.frames.load(Ordering::Relaxed), 3);
+        // The repeated frame is dropped by dedup before decoding
+        assert_eq!(stats.decoded.load(Ordering::Relaxed), 1);
+        assert_eq!(stats.meter_id_mismatches.load(Ordering::Relaxed), 1);
+        assert_eq!(*runtime.block_on(state.last_rssi.read()), Some(-72.0));
+
+        let reading = state.latest_data.borrow().clone().unwrap();
+        assert_eq!(reading.total_l, 6408);
+        assert_eq!(reading.info_codes, 0x71);
+        let header = (*runtime.block_on(state.last_header.read())).unwrap();
+        assert_eq!(header.manufacturer_code(), "KAM");
+    }
+}
 // EOF
@@ -0,0 +1,191 @@
+// mock_radio.rs — Simulated radio replaying scripted frames, for running the pipeline without a CC1101
+
+use std::{collections::VecDeque, time::Instant};
+
+use crate::*;
+
+/// One scripted frame, delivered `delay` after the previous one (or after the start).
+#[derive(Clone, Debug)]
+pub struct MockFrame {
+    pub delay: Duration,
+    /// Raw frame from the L-field on, like the `hex` of `/api/lastframe`
+    pub payload: Vec<u8>,
+    pub rssi_dbm: Option<f32>,
+    pub lqi: Option<u8>,
+}
+
+/// `RadioSource` that hands out preloaded frames with their timing, then stays silent.
+/// Commands behave like on a healthy chip, only `DumpRegisters` has nothing to show.
+#[derive(Default)]
+pub struct MockRadio {
+    frames: VecDeque<MockFrame>,
+    /// When the front frame is due, set by the first wait for it so a cancelled wait keeps the schedule
+    next_due: Option<Instant>,
+    last_signal: (Option<f32>, Option<u8>),
+    stats: Option<Arc<RxStats>>,
+}
+
+impl MockRadio {
+    pub fn new(frames: impl IntoIterator<Item = MockFrame>) -> Self {
+        Self {
+            frames: frames.into_iter().collect(),
+            ..Default::default()
+        }
+    }
+
+    /// Replay a `/api/capture` download, `interval` apart. Comment lines and lines that
+    /// do not parse are skipped.
+    pub fn from_capture(capture: &str, interval: Duration) -> Self {
+        let frames = capture
+            .lines()
+            .filter(|line| !line.starts_with('#'))
+            .filter_map(|line| {
+                // timestamp rssi_dbm lqi parsed hex
+                let fields: Vec<&str> = line.split_whitespace().collect();
+                let [_, rssi_dbm, lqi, _, hex] = fields[..] else {
+                    return None;
+                };
+                Some(MockFrame {
+                    delay: interval,
+                    payload: parse_hex(hex).filter(|b| !b.is_empty())?,
+                    rssi_dbm: rssi_dbm.parse().ok(),
+                    lqi: lqi.parse().ok(),
+                })
+            });
+        Self::new(frames)
+    }
+
+    /// Frames not delivered yet.
+    pub fn pending(&self) -> usize {
+        self.frames.len()
+    }
+}
+
+impl RadioSource for MockRadio {
+    fn set_stats(&mut self, stats: Arc<RxStats>) {
+        self.stats = Some(stats);
+    }
+
+    fn set_freq_hz(&mut self, _freq_hz: u64) {}
+
+    fn set_register_overrides(&mut self, _overrides: Vec<(u8, u8)>) {}
+
+    fn enable_gdo0_wakeup(&mut self) -> Result<(), Cc1101RadioError> {
+        Ok(())
+    }
+
+    fn init(&mut self) -> Result<(), Cc1101RadioError> {
+        info!("MockRadio: {} frames scripted", self.frames.len());
+        Ok(())
+    }
+
+    fn restart_radio(&mut self) -> Result<(), Cc1101RadioError> {
+        warn!("MockRadio: Restarting radio (watchdog)...");
+        Ok(())
+    }
+
+    async fn wait_for_packet(&mut self, timeout_s: u64) -> Result<Option<Vec<u8>>, Cc1101RadioError> {
+        let wait = Duration::from_secs(timeout_s);
+        let Some(delay) = self.frames.front().map(|f| f.delay) else {
+            sleep(wait).await;
+            warn!("MockRadio: Watchdog timeout ({timeout_s} s), no frames left");
+            return Ok(None);
+        };
+        let due = *self.next_due.get_or_insert_with(|| Instant::now() + delay);
+        let remaining = due.saturating_duration_since(Instant::now());
+        if remaining > wait {
+            sleep(wait).await;
+            warn!("MockRadio: Watchdog timeout ({timeout_s} s) with no packets received");
+            return Ok(None);
+        }
+        sleep(remaining).await;
+
+        // Nothing awaited from here on, so the frame is never lost to a cancelled wait
+        let Some(frame) = self.frames.pop_front() else {
+            return Ok(None);
+        };
+        self.next_due = None;
+        self.last_signal = (frame.rssi_dbm, frame.lqi);
+        if let Some(stats) = &self.stats {
+            stats.frame_received();
+        }
+        info!("MockRadio: Valid wMBus packet, {} bytes", frame.payload.len());
+        Ok(Some(frame.payload))
+    }
+
+    fn last_signal(&self) -> (Option<f32>, Option<u8>) {
+        self.last_signal
+    }
+
+    fn handle_command(&mut self, cmd: RadioCommand) {
+        match cmd {
+            RadioCommand::DumpRegisters(reply) => {
+                reply.send(Err("No registers on the simulated radio".into())).ok();
+            }
+            RadioCommand::Restart(reply) => {
+                reply.send(self.restart_radio().map_err(|e| e.to_string())).ok();
+            }
+            RadioCommand::Inject(_, reply) => {
+                error!("MockRadio: Inject command reached the radio driver");
+                reply.send(Err(FrameError::Payload)).ok();
+            }
+            RadioCommand::Rekey(_, _, reply) => {
+                error!("MockRadio: Rekey command reached the radio driver");
+                reply.send(Err("Not served by the radio driver".into())).ok();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+
+    use super::*;
+
+    // Frame and key of the wmbusmeters Multical 21 test vector, see the wmbus tests
+    const CAPTURE: &str = "\
+# esp32multical21 capture
+2026-02-18T20:33:00Z -71.5 45 ok 2C442D2C998734761B168D2091D37CAC21E1D68CDAFFCD3DC452BD802913FF7B1706CA9E355D6C2701CC2427BD
+not a capture line
+2026-02-18T20:33:16Z -90 12 crc 2C44
+";
+    const KEY: &str = "28F64A24988064A079AA2C807D6102AE";
+
+    #[test]
+    fn replay_capture_into_parser() {
+        let mut radio = MockRadio::from_capture(CAPTURE, Duration::ZERO);
+        let stats = Arc::new(RxStats::default());
+        radio.set_stats(stats.clone());
+        radio.init().unwrap();
+        assert_eq!(radio.pending(), 2);
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let frames: Vec<_> = runtime.block_on(async {
+            let mut frames = Vec::new();
+            while let Some(frame) = radio.wait_for_packet(0).await.unwrap() {
+                frames.push((frame, radio.last_signal()));
+            }
+            frames
+        });
+        assert_eq!(radio.pending(), 0);
+        assert_eq!(stats.frames.load(Ordering::Relaxed), 2);
+
+        let (raw, signal) = &frames[0];
+        assert_eq!(*signal, (Some(-71.5), Some(45)));
+        let key = parse_meter_key(KEY).unwrap();
+        let reading = parse_frame(raw, &[0x99, 0x87, 0x34, 0x76], &key).unwrap();
+        assert_eq!(reading.total_l, 6408);
+
+        let (raw, signal) = &frames[1];
+        assert_eq!(*signal, (Some(-90.0), Some(12)));
+        assert_eq!(
+            parse_frame(raw, &[0x99, 0x87, 0x34, 0x76], &key).unwrap_err(),
+            FrameError::TooShort
+        );
+    }
+}
+// EOF
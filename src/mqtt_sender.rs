@@ -1,7 +1,27 @@
 // mqtt_sender.rs
 
+use std::time::Instant;
+
 use crate::*;
 
+const MQTT_EVENT_QUEUE_LEN: usize = 4;
+const MQTT_URL_SCHEMES: [&str; 4] = ["mqtt://", "mqtts://", "ws://", "wss://"];
+// Reconnect backoff, doubled after every failed session
+const MQTT_RETRY_MIN_SECS: u64 = 5;
+const MQTT_RETRY_MAX_SECS: u64 = 300;
+// Give the command result a chance to reach the broker before rebooting
+const MQTT_REBOOT_DELAY_SECS: u64 = 2;
+const MQTT_RADIO_REPLY_SECS: u64 = 5;
+// Link health is published on its own schedule, also when no readings come through
+const MQTT_LINK_STATS_SECS: u64 = 60;
+
+/// Events forwarded from the connection to the task owning the client.
+#[derive(Debug)]
+enum MqttEvent {
+    Connected,
+    Command(Vec<u8>),
+}
+
 pub async fn run_mqtt(state: Arc<Pin<Box<MyState>>>) -> AppResult<()> {
     if !state.config.read().await.mqtt_enable {
         info!("MQTT is disabled by configuration.");
@@ -18,76 +38,333 @@ pub async fn run_mqtt(state: Arc<Pin<Box<MyState>>>) -> AppResult<()> {
         sleep(Duration::from_secs(5)).await;
     }
 
-    let url = state.config.read().await.mqtt_url.clone();
+    let config = state.config.read().await.clone();
     let myid = state.my_id.read().await.clone();
 
+    let mqtt_topic = expand_mqtt_topic(
+        &config.mqtt_topic,
+        &myid,
+        &state.my_mac_s.read().await.replace(':', ""),
+        &state.hostname.read().await,
+    );
+    if !valid_mqtt_topic(&mqtt_topic) {
+        error!(
+            "Invalid MQTT topic \"{mqtt_topic}\" expanded from \"{}\", MQTT disabled.",
+            config.mqtt_topic
+        );
+        // we cannot return, otherwise tokio::select in main() will exit
+        loop {
+            sleep(Duration::from_secs(3600)).await;
+        }
+    }
+    info!("MQTT topic: {mqtt_topic}");
+
+    if !MQTT_URL_SCHEMES
+        .iter()
+        .any(|scheme| config.mqtt_url.starts_with(scheme))
+    {
+        error!("Invalid MQTT URL \"{}\", MQTT disabled.", config.mqtt_url);
+        // we cannot return, otherwise tokio::select in main() will exit
+        loop {
+            sleep(Duration::from_secs(3600)).await;
+        }
+    }
+
     sleep(Duration::from_secs(10)).await;
 
-    info!("MQTT conn: {url} [{myid}]");
-    let (client, conn) = match mqtt::client::EspAsyncMqttClient::new(
-        &url,
-        &mqtt::client::MqttClientConfiguration {
-            client_id: Some(&myid),
-            keep_alive_interval: Some(Duration::from_secs(25)),
-            ..Default::default()
-        },
-    ) {
-        Ok(c) => c,
-        Err(e) => {
-            let emsg = format!("MQTT conn failed: {e:?}");
-            error!("{emsg}");
-            return Err(AppError::Message(emsg));
+    // A broker outage must not end this task, that would reboot the whole device
+    let mut retry_secs = MQTT_RETRY_MIN_SECS;
+    loop {
+        let started = Instant::now();
+        if let Err(e) = Box::pin(mqtt_session(&state, &config, &myid, &mqtt_topic)).await {
+            error!("{e}");
+        }
+        // A session that stayed up for a while starts the backoff over
+        if started.elapsed() >= Duration::from_secs(MQTT_RETRY_MAX_SECS) {
+            retry_secs = MQTT_RETRY_MIN_SECS;
         }
+        warn!("MQTT reconnecting in {retry_secs} s...");
+        sleep(Duration::from_secs(retry_secs)).await;
+        retry_secs = (retry_secs * 2).min(MQTT_RETRY_MAX_SECS);
+    }
+}
+
+/// One client lifetime, from creating the client until it fails.
+async fn mqtt_session(
+    state: &Arc<Pin<Box<MyState>>>,
+    config: &MyConfig,
+    myid: &str,
+    mqtt_topic: &str,
+) -> AppResult<()> {
+    let url = &config.mqtt_url;
+    let mut mqtt_config = mqtt::client::MqttClientConfiguration {
+        client_id: Some(myid),
+        keep_alive_interval: Some(Duration::from_secs(25)),
+        // A persistent session lets the broker queue commands while we are offline
+        disable_clean_session: !config.mqtt_clean_session,
+        ..Default::default()
     };
 
+    if config.mqtt_user.is_empty() {
+        info!("MQTT auth: anonymous");
+    } else {
+        info!("MQTT auth: username {}", config.mqtt_user);
+        mqtt_config.username = Some(&config.mqtt_user);
+        mqtt_config.password = Some(&config.mqtt_pass);
+    }
+
+    if url.starts_with("mqtts://") || url.starts_with("wss://") {
+        if config.mqtt_tls_insecure {
+            warn!("MQTT TLS: server certificate is NOT verified");
+            mqtt_config.skip_cert_common_name_check = true;
+            mqtt_config.crt_bundle_attach = Some(skip_server_cert_verify);
+        } else {
+            info!("MQTT TLS: verifying server certificate against the CA bundle");
+            mqtt_config.crt_bundle_attach = Some(esp_idf_sys::esp_crt_bundle_attach);
+        }
+    }
+
+    info!("MQTT conn: {url} [{myid}]");
+    let (client, conn) = mqtt::client::EspAsyncMqttClient::new(url, &mqtt_config)
+        .map_err(|e| AppError::Message(format!("MQTT conn failed: {e:?}")))?;
+
+    let (event_tx, event_rx) = mpsc::channel(MQTT_EVENT_QUEUE_LEN);
     tokio::select! {
-        _ = Box::pin(data_sender(state.clone(), client)) => { error!("data_sender() ended."); }
-        _ = Box::pin(event_loop(state.clone(), conn)) => { error!("event_loop() ended."); }
-    };
-    Ok(())
+        res = Box::pin(data_sender(state.clone(), client, mqtt_topic, event_rx)) => {
+            error!("data_sender() ended.");
+            res
+        }
+        res = Box::pin(event_loop(conn, mqtt_topic, event_tx)) => {
+            error!("event_loop() ended.");
+            res
+        }
+    }
 }
 
-async fn data_sender(state: Arc<Pin<Box<MyState>>>, mut client: mqtt::client::EspAsyncMqttClient) -> AppResult<()> {
-    let mqtt_topic = state.config.read().await.mqtt_topic.clone();
+/// Owns the client: publishes fresh data every 10 s and after every (re)connect,
+/// and serves events from `event_loop()`.
+async fn data_sender(
+    state: Arc<Pin<Box<MyState>>>,
+    mut client: mqtt::client::EspAsyncMqttClient,
+    mqtt_topic: &str,
+    mut event_rx: mpsc::Receiver<MqttEvent>,
+) -> AppResult<()> {
+    let cmd_topic = format!("{mqtt_topic}/cmd");
+    let (volume_unit, m3_decimals, retain_meter, retain_uptime, temp_delta, qos) = {
+        let config = state.config.read().await;
+        (
+            config.volume_unit,
+            config.m3_decimals,
+            config.mqtt_retain_meter,
+            config.mqtt_retain_uptime,
+            config.temp_report_delta,
+            mqtt_qos(config.mqtt_qos),
+        )
+    };
+    // Last reading sent to {topic}/meter, for `temp_report_delta`
+    let mut published: Option<MeterReading> = None;
+    let mut link_stats_sent: Option<Instant> = None;
+    let mut reading_rx = state.latest_data.subscribe();
+    // Set on every (re)connect: republish everything once, even without fresh data
+    let mut republish = false;
 
     loop {
-        sleep(Duration::from_secs(10)).await;
-        let uptime = *(state.uptime.read().await);
-
-        {
-            let mut fresh_data = state.data_updated.write().await;
-            if !*fresh_data {
+        let event = tokio::select! {
+            _ = sleep(Duration::from_secs(10)) => None,
+            event = event_rx.recv() => Some(event),
+        };
+        match event {
+            None => {}
+            // The broker forgets subscriptions of a clean session, subscribe on every connect
+            Some(Some(MqttEvent::Connected)) => {
+                info!("MQTT subscribing {cmd_topic}");
+                if let Err(e) = client.subscribe(&cmd_topic, mqtt::client::QoS::AtLeastOnce).await {
+                    error!("MQTT subscribe error: {e}");
+                }
+                republish = true;
+                link_stats_sent = None;
+            }
+            Some(Some(MqttEvent::Command(data))) => {
+                Box::pin(handle_command(&state, &mut client, &cmd_topic, qos, &data)).await;
                 continue;
             }
-            *fresh_data = false;
+            Some(None) => return Ok(()),
+        }
+
+        if !*state.net_up.read().await {
+            continue;
+        }
+
+        if link_stats_sent.is_none_or(|t| t.elapsed() >= Duration::from_secs(MQTT_LINK_STATS_SECS)) {
+            link_stats_sent = Some(Instant::now());
+            Box::pin(link_stats_sender(&state, &mut client, mqtt_topic, qos)).await?;
+        }
+
+        let uptime = *(state.uptime.read().await);
+
+        if !reading_rx.has_changed().unwrap_or(false) && !republish {
+            continue;
+        }
+        let republishing = std::mem::take(&mut republish);
+        if republishing {
+            info!("MQTT (re)connected, republishing current state");
+            let topic = format!("{mqtt_topic}/reset_reason");
+            Box::pin(mqtt_send(&mut client, &topic, qos, true, state.reset_reason)).await?;
         }
 
         {
             let topic = format!("{mqtt_topic}/uptime");
-            let mqtt_data = format!("{{ \"uptime\": {} }}", uptime);
-            Box::pin(mqtt_send(&mut client, &topic, false, &mqtt_data)).await?;
+            let radio_ok = *state.radio_ok.read().await;
+            let mqtt_data = serde_json::to_string(&Uptime { uptime, radio_ok })?;
+            Box::pin(mqtt_send(&mut client, &topic, qos, retain_uptime, &mqtt_data)).await?;
         }
 
-        // Publish meter reading if available
-        if let Some(ref reading) = *state.latest_data.read().await {
+        // Publish the full meter reading, serialized straight from the struct
+        let latest = reading_rx.borrow_and_update().clone();
+        if let Some(prev) = &published
+            && let Some(reading) = &latest
+            && !republishing
+            && !reading_reportable(prev, reading, temp_delta)
+        {
+            debug!("MQTT: reading unchanged beyond temp_report_delta, not published");
+            continue;
+        }
+        if let Some(reading) = latest {
             let topic = format!("{mqtt_topic}/meter");
-            let mqtt_data = serde_json::to_string(&reading)?;
-            Box::pin(mqtt_send(&mut client, &topic, true, &mqtt_data)).await?;
+            let mqtt_data = volume_unit.reading_json(&reading, m3_decimals).to_string();
+            Box::pin(mqtt_send(&mut client, &topic, qos, retain_meter, &mqtt_data)).await?;
+
+            let topic = format!("{mqtt_topic}/low_battery");
+            let low_battery = info_low_battery(reading.info_codes).to_string();
+            Box::pin(mqtt_send(&mut client, &topic, qos, retain_meter, &low_battery)).await?;
+            published = Some(reading);
+        }
+        if let Some(last_reset) = state.last_reset_s().await {
+            let topic = format!("{mqtt_topic}/last_reset");
+            Box::pin(mqtt_send(&mut client, &topic, qos, retain_meter, &last_reset)).await?;
         }
     }
 }
 
+/// Publish the signal of the last received frame and the reception counters.
+async fn link_stats_sender(
+    state: &Arc<Pin<Box<MyState>>>,
+    client: &mut mqtt::client::EspAsyncMqttClient,
+    mqtt_topic: &str,
+    qos: mqtt::client::QoS,
+) -> AppResult<()> {
+    if let Some(rssi) = *state.last_rssi.read().await {
+        let topic = format!("{mqtt_topic}/rssi");
+        Box::pin(mqtt_send(client, &topic, qos, false, &rssi.to_string())).await?;
+    }
+    if let Some(lqi) = *state.last_lqi.read().await {
+        let topic = format!("{mqtt_topic}/lqi");
+        Box::pin(mqtt_send(client, &topic, qos, false, &lqi.to_string())).await?;
+    }
+
+    // Counters are cumulative since boot, retain them like the meter reading
+    let topic = format!("{mqtt_topic}/stats");
+    let mqtt_data = state.rx_stats.to_json().to_string();
+    Box::pin(mqtt_send(client, &topic, qos, true, &mqtt_data)).await?;
+    Ok(())
+}
+
+/// Execute a JSON command and publish the outcome to `{topic}/cmd/result`. Unknown actions are ignored.
+async fn handle_command(
+    state: &Arc<Pin<Box<MyState>>>,
+    client: &mut mqtt::client::EspAsyncMqttClient,
+    cmd_topic: &str,
+    qos: mqtt::client::QoS,
+    data: &[u8],
+) {
+    let cmd = match serde_json::from_slice::<MqttCommand>(data) {
+        Ok(cmd) => cmd,
+        Err(e) => {
+            warn!("MQTT invalid command JSON: {e}");
+            return;
+        }
+    };
+    info!("MQTT command: {}", cmd.action);
+
+    let (ok, message, reboot) = match (cmd.action.as_str(), cmd.url) {
+        ("reboot", _) => (true, "Rebooting".to_string(), true),
+        ("restart_radio", _) => Box::pin(mqtt_restart_radio(state)).await,
+        ("ota", None) => (false, "OTA command without url".to_string(), false),
+        ("ota", Some(url)) => Box::pin(mqtt_ota(state, &url)).await,
+        (action, _) => {
+            warn!("MQTT unknown command action: {action}");
+            return;
+        }
+    };
+    if !ok {
+        error!("MQTT command {} failed: {message}", cmd.action);
+    }
+
+    let topic = format!("{cmd_topic}/result");
+    let result = serde_json::json!({"ok": ok, "action": cmd.action, "message": message}).to_string();
+    // The error is already logged, and a failed publish must not prevent a reboot
+    let _ = Box::pin(mqtt_send(client, &topic, qos, false, &result)).await;
+
+    if reboot {
+        sleep(Duration::from_secs(MQTT_REBOOT_DELAY_SECS)).await;
+        info!("MQTT command: resetting...");
+        *state.reset.write().await = true;
+    }
+}
+
+/// Restart the radio through the radio task and wait for the outcome. Returns (ok, message, reboot).
+async fn mqtt_restart_radio(state: &Arc<Pin<Box<MyState>>>) -> (bool, String, bool) {
+    let (reply_tx, reply_rx) = oneshot::channel();
+    if state.radio_cmd.try_send(RadioCommand::Restart(reply_tx)).is_err() {
+        return (false, "radio command queue full".to_string(), false);
+    }
+    match timeout(Duration::from_secs(MQTT_RADIO_REPLY_SECS), reply_rx).await {
+        Ok(Ok(Ok(()))) => (true, "Radio restarted".to_string(), false),
+        Ok(Ok(Err(e))) => (false, format!("Radio restart failed: {e}"), false),
+        Ok(Err(_)) | Err(_) => (false, "radio not active".to_string(), false),
+    }
+}
+
+/// Start a firmware update like `POST /api/ota`, which reboots by itself once the image is
+/// written. Returns (ok, message, reboot).
+async fn mqtt_ota(state: &Arc<Pin<Box<MyState>>>, url: &str) -> (bool, String, bool) {
+    match start_ota(state, url).await {
+        Ok(()) => (true, "Firmware update started".to_string(), false),
+        Err(e) => (false, format!("Firmware update not started: {e}"), false),
+    }
+}
+
+/// `crt_bundle_attach` hook for `mqtt_tls_insecure`. esp-tls sets the authmode to required just
+/// before calling it, so turning it off here skips verification for this connection only.
+unsafe extern "C" fn skip_server_cert_verify(conf: *mut core::ffi::c_void) -> esp_idf_sys::esp_err_t {
+    unsafe {
+        esp_idf_sys::mbedtls_ssl_conf_authmode(
+            conf as *mut esp_idf_sys::mbedtls_ssl_config,
+            esp_idf_sys::MBEDTLS_SSL_VERIFY_NONE as i32,
+        );
+    }
+    esp_idf_sys::ESP_OK
+}
+
+/// `mqtt_qos` as the client enum, anything above 2 is clamped.
+fn mqtt_qos(level: u8) -> mqtt::client::QoS {
+    match level {
+        0 => mqtt::client::QoS::AtMostOnce,
+        1 => mqtt::client::QoS::AtLeastOnce,
+        _ => mqtt::client::QoS::ExactlyOnce,
+    }
+}
+
 async fn mqtt_send(
     client: &mut mqtt::client::EspAsyncMqttClient,
     topic: &str,
+    qos: mqtt::client::QoS,
     retain: bool,
     data: &str,
 ) -> Result<mqtt::client::MessageId, EspError> {
     info!("MQTT sending {topic} {data}");
 
-    let result = client
-        .publish(topic, mqtt::client::QoS::AtLeastOnce, retain, data.as_bytes())
-        .await;
+    let result = client.publish(topic, qos, retain, data.as_bytes()).await;
     if let Err(e) = result {
         let msg = format!("MQTT send error: {e}");
         error!("{msg}");
@@ -95,9 +372,35 @@ async fn mqtt_send(
     result
 }
 
-async fn event_loop(_state: Arc<Pin<Box<MyState>>>, mut conn: mqtt::client::EspAsyncMqttConnection) -> AppResult<()> {
+async fn event_loop(
+    mut conn: mqtt::client::EspAsyncMqttConnection,
+    mqtt_topic: &str,
+    event_tx: mpsc::Sender<MqttEvent>,
+) -> AppResult<()> {
+    let cmd_topic = format!("{mqtt_topic}/cmd");
+
     while let Ok(notification) = Box::pin(conn.next()).await {
-        info!("MQTT received: {:?}", notification.payload());
+        let event = match notification.payload() {
+            mqtt::client::EventPayload::Connected(_) => {
+                info!("MQTT connected");
+                Some(MqttEvent::Connected)
+            }
+            mqtt::client::EventPayload::Received {
+                topic: Some(topic),
+                data,
+                details: mqtt::client::Details::Complete,
+                ..
+            } if topic == cmd_topic => Some(MqttEvent::Command(data.to_vec())),
+            payload => {
+                info!("MQTT received: {payload:?}");
+                None
+            }
+        };
+        if let Some(event) = event
+            && event_tx.try_send(event).is_err()
+        {
+            warn!("MQTT event queue full, event dropped");
+        }
     }
 
     error!("MQTT connection closed.");
@@ -2,32 +2,106 @@
 
 use crate::*;
 
+const MANUFACTURER_KAM: u16 = 0x2C2D;
+// wMBus device types: warm water, hot water, cold water, dual water
+const WATER_DEVICE_TYPES: [u8; 4] = [0x06, 0x15, 0x16, 0x17];
+// Info code bits 0..3 flag dry, reverse, leak and burst, bits 4..6 hold their duration.
+// The remaining top bit is the low battery warning.
+pub const INFO_CODE_BATTERY_LOW: u8 = 0x80;
+// Inner CI of a correctly decrypted payload: long and compact frame
+const KNOWN_INNER_CI: [u8; 2] = [0x78, 0x79];
+
+/// Kamstrup Multical 21 and other Kamstrup water meters using the same payload format.
+pub struct Multical21Decoder;
+
+impl MeterDecoder for Multical21Decoder {
+    fn name(&self) -> &'static str {
+        "Kamstrup Multical 21"
+    }
+
+    fn matches(&self, header: &FrameHeader) -> bool {
+        header.manufacturer == MANUFACTURER_KAM && WATER_DEVICE_TYPES.contains(&header.device_type)
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<MeterReading, FrameError> {
+        parse_multical21(data)
+    }
+}
+
+/// Low battery warning from the meter's info codes byte.
+pub fn info_low_battery(info_codes: u8) -> bool {
+    info_codes & INFO_CODE_BATTERY_LOW != 0
+}
+
+/// Meter fields in whole degrees Celsius, subject to `temp_report_delta`.
+pub const TEMP_FIELDS: [&str; 2] = ["flow_temp", "ambient_temp"];
+
+/// A temperature moved enough to report: by at least `delta` degrees, or at all with `delta` 0.
+pub fn temp_reportable(prev: i8, new: i8, delta: u8) -> bool {
+    if delta == 0 {
+        prev != new
+    } else {
+        prev.abs_diff(new) >= delta
+    }
+}
+
+/// A reading is worth publishing after `prev`: always with `temp_delta` 0, otherwise when a
+/// volume, info code or date changed, or a temperature moved by at least `temp_delta` degrees.
+pub fn reading_reportable(prev: &MeterReading, new: &MeterReading, temp_delta: u8) -> bool {
+    temp_delta == 0
+        || new.total_l != prev.total_l
+        || new.month_start_l != prev.month_start_l
+        || new.info_codes != prev.info_codes
+        || new.meter_datetime != prev.meter_datetime
+        || new.prev_period_date != prev.prev_period_date
+        || temp_reportable(prev.flow_temp, new.flow_temp, temp_delta)
+        || temp_reportable(prev.ambient_temp, new.ambient_temp, temp_delta)
+}
+
+/// Format an epoch timestamp as ISO 8601 in the given fixed UTC offset.
+/// Zero offset keeps the plain `Z` suffix.
+pub fn format_timestamp(timestamp: i64, tz_offset_minutes: i16) -> String {
+    let utc = DateTime::from_timestamp(timestamp, 0).unwrap_or_default();
+    match FixedOffset::east_opt(i32::from(tz_offset_minutes) * 60) {
+        Some(tz) if tz_offset_minutes != 0 => utc.with_timezone(&tz).format("%Y-%m-%dT%H:%M:%S%:z").to_string(),
+        _ => utc.format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+    }
+}
+
 /// Parse decrypted Multical 21 payload into a MeterReading.
-/// Decrypted data layout (matching C++ reference):
+/// Decrypted data layout:
 ///   [0..2]  = CRC-16 of [2..end]
 ///   [2]     = CI field (0x79 = compact, 0x78 = long)
-///   [3..]   = frame data (offsets below are absolute from data[0])
-pub fn parse_multical21(data: &[u8]) -> Option<MeterReading> {
+///   [3..]   = compact: format signature, data CRC and values at fixed offsets (absolute from data[0]),
+///             long: DIF/VIF data records
+pub fn parse_multical21(data: &[u8]) -> Result<MeterReading, FrameError> {
     if data.len() < 3 {
         warn!("Multical21: Decrypted data too short ({} bytes)", data.len());
-        return None;
+        return Err(FrameError::Payload);
     }
 
     // Verify CRC: data[0..2] = CRC of data[2..end]
     let read_crc = (data[1] as u16) << 8 | data[0] as u16;
     let calc_crc = crc16_en13757(&data[2..]);
     if read_crc != calc_crc {
-        warn!("Multical21: CRC mismatch (read={:04X} calc={:04X})", read_crc, calc_crc);
         info!("Multical21: data[{}]: {:02X?}", data.len(), data);
-        return None;
+        // Radio bit errors rarely hit the CI, a wrong key scrambles the whole payload
+        if !KNOWN_INNER_CI.contains(&data[2]) {
+            warn!(
+                "Multical21: CRC mismatch (read={:04X} calc={:04X}) and unknown CI={:02X} after decryption, likely wrong meter_key",
+                read_crc, calc_crc, data[2]
+            );
+            return Err(FrameError::KeySuspect);
+        }
+        warn!("Multical21: CRC mismatch (read={:04X} calc={:04X})", read_crc, calc_crc);
+        return Err(FrameError::Crc);
     }
 
     let ci = data[2];
     info!("Multical21: CI={:02X} CRC OK", ci);
 
-    let now = Utc::now();
-    let timestamp = now.timestamp();
-    let timestamp_s = now.format("%Y-%m-%dT%H:%M:%SZ").to_string();
+    let timestamp = Utc::now().timestamp();
+    let timestamp_s = format_timestamp(timestamp, 0);
     let reading = match ci {
         0x79 => {
             info!("Multical21: parsing compact dataframe (CI=0x79)");
@@ -37,10 +111,11 @@ pub fn parse_multical21(data: &[u8]) -> Option<MeterReading> {
             } else {
                 // Parse compact frame (CI=0x79).
                 // Absolute offsets from decrypted data start (matching C++ reference impl):
+                //   [7..9]:   info codes (u16 LE)
                 //   [9..13]:  total volume (u32 LE, liters)
                 //   [13..17]: target volume (u32 LE, liters)
-                //   [17]:     flow temperature
-                //   [18]:     ambient temperature
+                //   [17]:     flow temperature (i8, °C)
+                //   [18]:     ambient temperature (i8, °C)
                 let total_l = u32::from_le_bytes([data[9], data[10], data[11], data[12]]);
                 let month_start_l = u32::from_le_bytes([data[13], data[14], data[15], data[16]]);
                 Some(MeterReading {
@@ -48,40 +123,54 @@ pub fn parse_multical21(data: &[u8]) -> Option<MeterReading> {
                     month_start_l,
                     total_m3: total_l as f32 / 1000.0,
                     month_start_m3: month_start_l as f32 / 1000.0,
-                    flow_temp: data[17],
-                    ambient_temp: data[18],
-                    info_codes: data[4],
+                    month_consumption_l: month_consumption(total_l, month_start_l),
+                    flow_temp: data[17] as i8,
+                    ambient_temp: data[18] as i8,
+                    info_codes: data[7],
+                    meter_datetime: None,
+                    prev_period_date: None,
                     timestamp,
                     timestamp_s,
+                    time_synced: false,
                 })
             }
         }
         0x78 => {
-            info!("Multical21: parsing compact dataframe (CI=0x78)");
-            if data.len() < 30 {
-                warn!("Multical21: Long frame too short ({} bytes)", data.len());
-                None
-            } else {
-                // Parse long frame (CI=0x78).
-                // Absolute offsets from decrypted data start (matching C++ reference):
-                //   [10..14]: total volume (u32 LE, liters)
-                //   [16..20]: target volume (u32 LE, liters)
-                //   [23]:     flow temperature
-                //   [29]:     ambient temperature
-                let total_l = u32::from_le_bytes([data[10], data[11], data[12], data[13]]);
-                let month_start_l = u32::from_le_bytes([data[16], data[17], data[18], data[19]]);
-                Some(MeterReading {
-                    total_l,
-                    month_start_l,
-                    total_m3: total_l as f32 / 1000.0,
-                    month_start_m3: month_start_l as f32 / 1000.0,
-                    flow_temp: data[23],
-                    ambient_temp: data[29],
-                    info_codes: data[4],
-                    timestamp,
-                    timestamp_s,
-                })
-            }
+            info!("Multical21: parsing long dataframe (CI=0x78)");
+            // The long frame is a DIF/VIF record list, located by VIF rather than offset:
+            //   02 FF 20: info codes (u16 LE, manufacturer specific)
+            //   04 13:    total volume (u32 LE, liters)
+            //   44 13:    target volume (u32 LE, liters, storage 1)
+            //   61 5B:    flow temperature (i8, °C)
+            //   61 67:    ambient temperature (i8, °C)
+            let records = data_records(&data[3..]);
+            let (Some(info), Some(total), Some(target), Some(flow), Some(ambient)) = (
+                record_value(&records, VIF_MANUFACTURER, None, 2),
+                record_value(&records, VIF_VOLUME_L, Some(0), 4),
+                record_value(&records, VIF_VOLUME_L, Some(STORAGE_PREV_PERIOD), 4),
+                record_value(&records, VIF_FLOW_TEMP, None, 1),
+                record_value(&records, VIF_EXTERNAL_TEMP, None, 1),
+            ) else {
+                warn!("Multical21: Long frame lacks a volume, temperature or info code record");
+                return Err(FrameError::Payload);
+            };
+            let total_l = u32::from_le_bytes([total[0], total[1], total[2], total[3]]);
+            let month_start_l = u32::from_le_bytes([target[0], target[1], target[2], target[3]]);
+            Some(MeterReading {
+                total_l,
+                month_start_l,
+                total_m3: total_l as f32 / 1000.0,
+                month_start_m3: month_start_l as f32 / 1000.0,
+                month_consumption_l: month_consumption(total_l, month_start_l),
+                flow_temp: flow[0] as i8,
+                ambient_temp: ambient[0] as i8,
+                info_codes: info[0],
+                meter_datetime: find_meter_datetime(&records),
+                prev_period_date: find_prev_period_date(&records),
+                timestamp,
+                timestamp_s,
+                time_synced: false,
+            })
         }
         _ => {
             warn!("Multical21: Unknown CI field 0x{:02X}", ci);
@@ -89,6 +178,257 @@ pub fn parse_multical21(data: &[u8]) -> Option<MeterReading> {
         }
     };
     info!("Multical21 parsed reading: {reading:#?}");
-    reading
+    reading.ok_or(FrameError::Payload)
+}
+
+// wMBus VIF codes for volume in liters, flow and external temperature in °C, date (type G),
+// date+time (type F) and manufacturer specific data, extension bit masked off
+const VIF_VOLUME_L: u8 = 0x13;
+const VIF_FLOW_TEMP: u8 = 0x5B;
+const VIF_EXTERNAL_TEMP: u8 = 0x67;
+const VIF_DATE_G: u8 = 0x6C;
+const VIF_DATETIME_F: u8 = 0x6D;
+const VIF_MANUFACTURER: u8 = 0x7F;
+// Storage number of the values at the last target (billing) date
+const STORAGE_PREV_PERIOD: u8 = 1;
+
+/// One DIF/VIF data record of a long frame, extension bits masked off.
+struct DataRecord<'a> {
+    storage: u8,
+    vif: u8,
+    value: &'a [u8],
+}
+
+/// Walk the DIF/VIF data records of a long frame. The walk stops at the first record
+/// that is variable length, special or truncated, so every value is fully inside `records`.
+fn data_records(records: &[u8]) -> Vec<DataRecord<'_>> {
+    let mut found = Vec::new();
+    let mut i = 0;
+    while i < records.len() {
+        let dif = records[i];
+        i += 1;
+        // 0x2F is an idle filler
+        if dif == 0x2F {
+            continue;
+        }
+        // Variable length, special functions and manufacturer data end the walk
+        let len = match dif & 0x0F {
+            n @ 0..=4 => n as usize,
+            5 => 4,
+            6 => 6,
+            7 => 8,
+            _ => break,
+        };
+        // Storage number: LSB in the DIF, further bits in the DIFEs
+        let mut storage = (dif & 0x40) >> 6;
+        let mut last = dif;
+        let mut shift = 1;
+        while last & 0x80 != 0 {
+            let Some(&dife) = records.get(i) else {
+                return found;
+            };
+            last = dife;
+            i += 1;
+            storage |= (dife & 0x0F).checked_shl(shift).unwrap_or(0);
+            shift += 4;
+        }
+
+        let Some(&vif) = records.get(i) else {
+            break;
+        };
+        i += 1;
+        // VIFE bytes
+        let mut last = vif;
+        while last & 0x80 != 0 {
+            let Some(&vife) = records.get(i) else {
+                return found;
+            };
+            last = vife;
+            i += 1;
+        }
+
+        let Some(value) = records.get(i..i + len) else {
+            break;
+        };
+        i += len;
+        found.push(DataRecord {
+            storage,
+            vif: vif & 0x7F,
+            value,
+        });
+    }
+    found
+}
+
+/// Value of the first record with `vif`, a `len` byte value and, if given, the storage number.
+fn record_value<'a>(records: &[DataRecord<'a>], vif: u8, storage: Option<u8>, len: usize) -> Option<&'a [u8]> {
+    records
+        .iter()
+        .find(|r| r.vif == vif && r.value.len() == len && storage.is_none_or(|s| r.storage == s))
+        .map(|r| r.value)
+}
+
+/// Decode the first current (storage 0) date or date/time record from the meter's RTC.
+/// Returns `None` if there is none or it is unset.
+fn find_meter_datetime(records: &[DataRecord]) -> Option<String> {
+    let record = records
+        .iter()
+        .find(|r| r.storage == 0 && matches!((r.vif, r.value.len()), (VIF_DATE_G, 2) | (VIF_DATETIME_F, 4)))?;
+    match record.vif {
+        VIF_DATE_G => decode_date_g(record.value),
+        _ => decode_datetime_f(record.value),
+    }
+}
+
+/// Last target date (end of the previous billing period) stored by the meter. The volume of that
+/// date is the target volume already read as `month_start_l`.
+fn find_prev_period_date(records: &[DataRecord]) -> Option<String> {
+    record_value(records, VIF_DATE_G, Some(STORAGE_PREV_PERIOD), 2).and_then(decode_date_g)
+}
+
+/// Type G date (EN 13757-3): 2 bytes, day/month/year bit fields.
+fn decode_date_g(b: &[u8]) -> Option<String> {
+    let day = b[0] & 0x1F;
+    let month = b[1] & 0x0F;
+    let year = ((b[0] & 0xE0) >> 5) | ((b[1] & 0xF0) >> 1);
+    // All ones means unset, zero day or month is rejected by chrono
+    if b == [0xFF, 0xFF] {
+        return None;
+    }
+    let date = NaiveDate::from_ymd_opt(2000 + year as i32, month as u32, day as u32)?;
+    Some(date.format("%Y-%m-%d").to_string())
+}
+
+/// Type F date/time (EN 13757-3): 4 bytes, minute/hour/day/month/year bit fields,
+/// bit 7 of the first byte flags an invalid time.
+fn decode_datetime_f(b: &[u8]) -> Option<String> {
+    if b[0] & 0x80 != 0 {
+        return None;
+    }
+    let minute = b[0] & 0x3F;
+    let hour = b[1] & 0x1F;
+    let day = b[2] & 0x1F;
+    let month = b[3] & 0x0F;
+    let year = ((b[2] & 0xE0) >> 5) | ((b[3] & 0xF0) >> 1);
+    let date = NaiveDate::from_ymd_opt(2000 + year as i32, month as u32, day as u32)?;
+    let datetime = date.and_hms_opt(hour as u32, minute as u32, 0)?;
+    Some(datetime.format("%Y-%m-%dT%H:%M").to_string())
+}
+
+/// Consumption since the month start reading. The target volume can briefly exceed
+/// the total at month rollover, clamp to zero then.
+fn month_consumption(total_l: u32, month_start_l: u32) -> u32 {
+    total_l.saturating_sub(month_start_l)
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Decrypted long frame of meter 76348799 (wmbusmeters test suite), CRC and CI included
+    const CAPTURED_LONG: &str = "576C7802FF207100041308190000441308190000615B7F616713";
+
+    /// Prefix the application layer with its CRC, like a decrypted payload.
+    fn with_crc(app: &[u8]) -> Vec<u8> {
+        let mut data = crc16_en13757(app).to_le_bytes().to_vec();
+        data.extend_from_slice(app);
+        data
+    }
+
+    #[test]
+    fn parse_captured_long_frame() {
+        let reading = parse_multical21(&parse_hex(CAPTURED_LONG).unwrap()).unwrap();
+        assert_eq!(reading.total_l, 6408);
+        assert_eq!(reading.month_start_l, 6408);
+        assert_eq!(reading.month_consumption_l, 0);
+        assert_eq!(reading.flow_temp, 127);
+        assert_eq!(reading.ambient_temp, 19);
+        assert_eq!(reading.info_codes, 0x71);
+        assert_eq!(reading.meter_datetime, None);
+        assert_eq!(reading.prev_period_date, None);
+    }
+
+    #[test]
+    fn parse_long_frame_with_dates() {
+        // Synthetic long frame with the records of the captured one plus the meter clock
+        // (04 6D, type F) and the last target date (42 6C, type G, storage 1)
+        let app = parse_hex("78 02FF206100 0413D1880500 44139D7E0500 615B01 61670A 046D21145232 426C4132").unwrap();
+        let reading = parse_multical21(&with_crc(&app)).unwrap();
+        assert_eq!(reading.total_l, 362_705);
+        assert_eq!(reading.month_start_l, 360_093);
+        assert_eq!(reading.month_consumption_l, 2612);
+        assert_eq!(reading.flow_temp, 1);
+        assert_eq!(reading.ambient_temp, 10);
+        assert_eq!(reading.info_codes, 0x61);
+        assert_eq!(reading.meter_datetime.as_deref(), Some("2026-02-18T20:33"));
+        assert_eq!(reading.prev_period_date.as_deref(), Some("2026-02-01"));
+    }
+
+    #[test]
+    fn parse_rejects_bad_crc() {
+        let mut data = parse_hex(CAPTURED_LONG).unwrap();
+        data[10] ^= 0x01;
+        assert_eq!(parse_multical21(&data).unwrap_err(), FrameError::Crc);
+        data[2] = 0x00;
+        assert_eq!(parse_multical21(&data).unwrap_err(), FrameError::KeySuspect);
+    }
+
+    #[test]
+    fn low_battery_info_bit() {
+        assert!(info_low_battery(INFO_CODE_BATTERY_LOW));
+        assert!(info_low_battery(0xFF));
+        assert!(!info_low_battery(0x7F));
+        assert!(!info_low_battery(0x00));
+    }
+
+    #[test]
+    fn low_battery_in_reading_json() {
+        let app = parse_hex("79 1234 5678 8000 D1880500 9D7E0500 0A 0C").unwrap();
+        let mut reading = parse_multical21(&with_crc(&app)).unwrap();
+        assert_eq!(reading.info_codes, INFO_CODE_BATTERY_LOW);
+        assert_eq!(VolumeUnit::M3.reading_json(&reading, 3)["low_battery"], true);
+        reading.info_codes = 0x01;
+        assert_eq!(VolumeUnit::M3.reading_json(&reading, 3)["low_battery"], false);
+    }
+
+    #[test]
+    fn parse_compact_frame_below_zero() {
+        // Compact frame: signature, data CRC, info codes, total, target, flow -3 °C, ambient -12 °C
+        let app = parse_hex("79 1234 5678 0000 D1880500 9D7E0500 FD F4").unwrap();
+        let reading = parse_multical21(&with_crc(&app)).unwrap();
+        assert_eq!(reading.total_l, 362_705);
+        assert_eq!(reading.month_start_l, 360_093);
+        assert_eq!(reading.flow_temp, -3);
+        assert_eq!(reading.ambient_temp, -12);
+    }
+
+    #[test]
+    fn temp_reportable_across_zero() {
+        assert!(temp_reportable(1, -1, 2));
+        assert!(!temp_reportable(-1, -2, 2));
+        assert!(temp_reportable(-1, -2, 0));
+        assert!(temp_reportable(i8::MIN, i8::MAX, u8::MAX));
+    }
+
+    #[test]
+    fn date_g_fields() {
+        assert_eq!(decode_date_g(&[0x41, 0x32]).as_deref(), Some("2026-02-01"));
+        assert_eq!(decode_date_g(&[0xFF, 0xFF]), None);
+        // Day 0 and month 13 are not dates
+        assert_eq!(decode_date_g(&[0x40, 0x32]), None);
+        assert_eq!(decode_date_g(&[0x41, 0x3D]), None);
+    }
+
+    #[test]
+    fn datetime_f_fields() {
+        assert_eq!(
+            decode_datetime_f(&[0x21, 0x14, 0x52, 0x32]).as_deref(),
+            Some("2026-02-18T20:33")
+        );
+        // Invalid time flag
+        assert_eq!(decode_datetime_f(&[0xA1, 0x14, 0x52, 0x32]), None);
+        // Hour 24 and minute 60 are out of range
+        assert_eq!(decode_datetime_f(&[0x21, 0x18, 0x52, 0x32]), None);
+        assert_eq!(decode_datetime_f(&[0x3C, 0x14, 0x52, 0x32]), None);
+    }
 }
 // EOF
@@ -0,0 +1,91 @@
+// nvs_crypt.rs — optional encryption of the config and meter key blobs at rest
+
+#[cfg(feature = "config_encrypt")]
+use aes::Aes128;
+#[cfg(feature = "config_encrypt")]
+use ctr::{
+    Ctr128BE,
+    cipher::{KeyIvInit, StreamCipher},
+};
+#[cfg(feature = "config_encrypt")]
+use sha2::{Digest, Sha256};
+
+use crate::*;
+
+/// Start of an encrypted blob, followed by a 16-byte random IV and the AES-128-CTR ciphertext.
+const SEALED_MAGIC: &[u8; 4] = b"ENC1";
+#[cfg(feature = "config_encrypt")]
+const IV_LEN: usize = 16;
+// Mixed with the eFuse MAC into the key. build.rs refuses to build the feature without it.
+#[cfg(feature = "config_encrypt")]
+const CONFIG_SECRET: &str = env!(
+    "CONFIG_SECRET",
+    "the config_encrypt feature needs CONFIG_SECRET set at build time"
+);
+
+pub fn is_sealed(blob: &[u8]) -> bool {
+    blob.starts_with(SEALED_MAGIC)
+}
+
+/// Encrypt a blob for nvs. Without the `config_encrypt` feature the blob is stored as is.
+#[cfg(feature = "config_encrypt")]
+pub fn seal(plain: &[u8]) -> Vec<u8> {
+    let mut iv = [0u8; IV_LEN];
+    unsafe { esp_idf_sys::esp_fill_random(iv.as_mut_ptr().cast(), IV_LEN) };
+
+    let mut out = Vec::with_capacity(SEALED_MAGIC.len() + IV_LEN + plain.len());
+    out.extend_from_slice(SEALED_MAGIC);
+    out.extend_from_slice(&iv);
+    let start = out.len();
+    out.extend_from_slice(plain);
+    let mut cipher = Ctr128BE::<Aes128>::new((&blob_key()).into(), &iv.into());
+    cipher.apply_keystream(&mut out[start..]);
+    out
+}
+
+#[cfg(not(feature = "config_encrypt"))]
+pub fn seal(plain: &[u8]) -> Vec<u8> {
+    plain.to_vec()
+}
+
+/// Decrypt a blob read from nvs. Plaintext blobs pass through, so older firmware's data
+/// still loads and gets encrypted on the next save. `None` for an encrypted blob this build cannot read.
+#[cfg(feature = "config_encrypt")]
+pub fn unseal(blob: Vec<u8>) -> Option<Vec<u8>> {
+    if !is_sealed(&blob) {
+        return Some(blob);
+    }
+    let Some((iv, data)) = blob[SEALED_MAGIC.len()..].split_first_chunk::<IV_LEN>() else {
+        error!("Encrypted nvs blob is truncated");
+        return None;
+    };
+    let mut plain = data.to_vec();
+    let mut cipher = Ctr128BE::<Aes128>::new((&blob_key()).into(), &(*iv).into());
+    cipher.apply_keystream(&mut plain);
+    Some(plain)
+}
+
+#[cfg(not(feature = "config_encrypt"))]
+pub fn unseal(blob: Vec<u8>) -> Option<Vec<u8>> {
+    if is_sealed(&blob) {
+        error!("Nvs blob is encrypted, this firmware was built without the config_encrypt feature");
+        return None;
+    }
+    Some(blob)
+}
+
+/// AES-128 key: SHA-256 over the build-time secret and the factory MAC from eFuse, truncated.
+#[cfg(feature = "config_encrypt")]
+fn blob_key() -> [u8; 16] {
+    let mut mac = [0u8; 6];
+    unsafe { esp_idf_sys::esp_efuse_mac_get_default(mac.as_mut_ptr()) };
+    let digest = Sha256::new()
+        .chain_update(CONFIG_SECRET.as_bytes())
+        .chain_update(mac)
+        .finalize();
+    let mut key = [0u8; 16];
+    key.copy_from_slice(&digest[..16]);
+    key
+}
+
+// EOF
@@ -1,5 +1,7 @@
 // radio.rs — CC1101 SPI radio driver for wMBus C1 mode
 
+use std::{sync::atomic::Ordering, time::Instant};
+
 use cc1101::{
     Cc1101,
     lowlevel::{
@@ -18,39 +20,113 @@ pub enum Cc1101RadioError {
     Spi(#[from] spi::SpiError),
     #[error("ESP-IDF error: {0}")]
     Esp(#[from] esp_idf_sys::EspError),
+    #[error("CC1101 not detected (PARTNUM=0x{partnum:02X} VERSION=0x{version:02X})")]
+    ChipNotDetected { partnum: u8, version: u8 },
+    #[error("SPI device not available")]
+    NoSpiDevice,
+    #[error("TX frame length {0} out of range 1..={max}", max = TX_FIFO_SIZE)]
+    TxLength(usize),
+    #[error("TX did not finish (MARCSTATE=0x{marcstate:02X})")]
+    TxTimeout { marcstate: u8 },
 }
 
 // SPI access mode bits
+const WRITE_BURST: u8 = 0x40;
+const READ_SINGLE: u8 = 0x80;
 const READ_BURST: u8 = 0xC0;
 
-// FIFO
+// Register address ranges
+const CONFIG_REG_LAST: u8 = 0x2E;
+const STATUS_REG_PARTNUM: u8 = 0x30;
+const STATUS_REG_VERSION: u8 = 0x31;
+const STATUS_REG_MARCSTATE: u8 = 0x35;
+const STATUS_REG_RXBYTES: u8 = 0x3B;
+
+// Expected chip identification, a missing chip reads as all zeros or all ones
+const CC1101_PARTNUM: u8 = 0x00;
+const CC1101_VERSION_MIN: u8 = 0x04;
+const CC1101_VERSION_MAX: u8 = 0x17;
+
+// FIFO and PA table
+const PATABLE: u8 = 0x3E;
 const FIFO: u8 = 0x3F;
+const TX_FIFO_SIZE: usize = 64;
 
 // MARCSTATE values
 const MARC_IDLE: u8 = 0x01;
 const MARC_RX: u8 = 0x0D;
+const MARC_RXFIFO_OVERFLOW: u8 = 0x11;
+
+// Calibration check: FSCAL1 at the end of the VCO capacitor range means the synthesizer cannot lock.
+// SCAL is tried this many times before the radio is flagged and left running as is.
+const FSCAL1_CAL_FAILED: u8 = 0x3F;
+const CAL_ATTEMPTS: u32 = 3;
+
+// TX: PATABLE[0] (FREND0.PA_POWER = 0) for about +10 dBm at 868 MHz (datasheet table 39),
+// and how long to poll for the end of a packet, a full FIFO takes about 6 ms on air
+const TX_PA_POWER: u8 = 0xC0;
+const TX_POLL_MS: u64 = 2;
+const TX_POLL_TRIES: u32 = 50;
+
+// RXBYTES: bit 7 flags overflow, bits 6:0 count bytes
+const RXBYTES_OVERFLOW: u8 = 0x80;
+const RXBYTES_MASK: u8 = 0x7F;
+
+// PKTCTRL0 length modes: RX starts in infinite mode and switches to fixed once the L-field
+// has set PKTLEN, TX is always fixed
+const PKTCTRL0_FIXED: u8 = 0x00;
+const PKTCTRL0_INFINITE: u8 = 0x02;
+// Frame format sync bytes and L-field, read before the length of the rest is known
+const WMBUS_HEADER_BYTES: usize = 3;
+// Shortest L-field with a complete header (C, M, A, CI), anything below is noise
+const WMBUS_MIN_L_FIELD: u8 = 10;
+// The longest frame (255 + 3 bytes) takes about 21 ms on air at 100 kbps
+const PACKET_READ_TIMEOUT_MS: u64 = 100;
 
 // wMBus C1 mode register targets
 const WMBUS_SYNC_WORD: u16 = 0x543D;
 const WMBUS_IF_HZ: u64 = 203_125; // FSCTRL1 = 0x08
-const WMBUS_FREQ_HZ: u64 = 868_949_708; // FREQ2/1/0 = 0x21,0x6B,0xD0
+pub const WMBUS_FREQ_HZ: u64 = 868_949_708; // FREQ2/1/0 = 0x21,0x6B,0xD0
+pub const WMBUS_FREQ_MIN_HZ: u64 = 863_000_000;
+pub const WMBUS_FREQ_MAX_HZ: u64 = 870_000_000;
 const WMBUS_CHANBW_HZ: u64 = 325_000; // MDMCFG4.CHANBW = 0b01_01
 const WMBUS_DATA_RATE_BPS: u64 = 103_149; // MDMCFG3/4 = 0x04/0x5C
 const WMBUS_DEVIATION_HZ: u64 = 34_913; // DEVIATN = 0x44
 
+pub const SPI_BAUD_KHZ: u16 = 4_000;
+pub const SPI_BAUD_MIN_KHZ: u16 = 100;
+// CC1101 limit for burst access
+pub const SPI_BAUD_MAX_KHZ: u16 = 6_500;
+
+// Antenna check: RSSI sampling interval while waiting, the floor below the thermal noise
+// of the 325 kHz channel that a working front end never reports, and how long it must last
+const RSSI_SAMPLE_SECS: u64 = 10;
+const ANTENNA_FLOOR_DBM: f32 = -110.0;
+const ANTENNA_SUSPECT_SECS: u64 = 900;
+
+// SPI failures in a row before the SPI device is recreated and the chip reinitialized,
+// and the pause before the next attempt after a single failure
+const SPI_ERROR_RESET_THRESHOLD: u32 = 5;
+const SPI_RETRY_MS: u64 = 100;
+
+pub type SpiDevice<'a> = spi::SpiDeviceDriver<'a, &'a spi::SpiDriver<'a>>;
+/// Creates the CC1101 SPI device, called again to replace a device that stopped responding.
+/// The previous device is always dropped before the next call.
+pub type SpiFactory<'a> = Box<dyn FnMut() -> Result<SpiDevice<'a>, EspError> + 'a>;
+
 // https://www.ti.com/lit/ds/symlink/cc1101.pdf
 
 const LEGACY_PROFILE: &[(CcConfig, u8)] = &[
-    // (CcConfig::FIFOTHR, 0x00),
-    // (CcConfig::IOCFG0, 0x06),
-    (CcConfig::FIFOTHR, 0x01),
-    (CcConfig::IOCFG0, 0x01),
+    // GDO0 asserts on sync word and deasserts at the end of the packet
+    (CcConfig::FIFOTHR, 0x00),
+    (CcConfig::IOCFG0, 0x06),
     (CcConfig::IOCFG2, 0x2E),
     (CcConfig::SYNC1, 0x54),
     (CcConfig::SYNC0, 0x3D),
-    (CcConfig::PKTLEN, 0x30),
+    (CcConfig::PKTLEN, 0xFF),
     (CcConfig::PKTCTRL1, 0x00),
-    (CcConfig::PKTCTRL0, 0x02),
+    // Infinite packet length until the L-field is read, see read_packet()
+    (CcConfig::PKTCTRL0, PKTCTRL0_INFINITE),
     (CcConfig::ADDR, 0x00),
     (CcConfig::CHANNR, 0x00),
     (CcConfig::FSCTRL0, 0x00),
@@ -85,37 +161,260 @@ const LEGACY_PROFILE: &[(CcConfig, u8)] = &[
     // (CcConfig::DEVIATN, 0x44), // set_deviation()
 ];
 
+/// Requests served by the task owning the radio, each with its own reply channel.
+/// Senders use `try_send` so a busy radio task never blocks them; a dropped reply
+/// receiver just means the requester does not care about the outcome.
+#[derive(Debug)]
+pub enum RadioCommand {
+    DumpRegisters(oneshot::Sender<Result<RadioSnapshot, String>>),
+    Restart(oneshot::Sender<Result<(), String>>),
+    /// Feed a raw frame through the receive pipeline, served by `read_meter()` itself.
+    Inject(Vec<u8>, oneshot::Sender<Result<MeterReading, FrameError>>),
+    /// Switch to a new meter_id/meter_key and restart reception, served by `read_meter()` itself.
+    Rekey([u8; 4], [u8; 16], oneshot::Sender<Result<(), String>>),
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct RadioSnapshot {
+    /// (address, value) pairs: all config registers followed by selected status registers
+    pub registers: Vec<(u8, u8)>,
+    pub rssi_dbm: Option<f32>,
+    pub lqi: Option<u8>,
+}
+
+/// Parse `radio_registers`: comma-separated `reg=val` hex pairs such as `1B=43,21=B6`, with or without `0x`.
+/// Only config registers 0x00..=0x2E are accepted. `None` if anything is malformed.
+pub fn parse_register_overrides(spec: &str) -> Option<Vec<(u8, u8)>> {
+    let hex = |s: &str| {
+        let s = s.trim();
+        let s = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")).unwrap_or(s);
+        u8::from_str_radix(s, 16).ok()
+    };
+    spec.split(',')
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (reg, value) = pair.split_once('=')?;
+            let reg = hex(reg).filter(|reg| *reg <= CONFIG_REG_LAST)?;
+            Some((reg, hex(value)?))
+        })
+        .collect()
+}
+
+/// Convert the raw RSSI status register value to dBm (datasheet section 17.3, offset 74 dB).
+pub fn rssi_to_dbm(raw: u8) -> f32 {
+    (raw as i8) as f32 / 2.0 - 74.0
+}
+
+/// What `read_meter()` needs from a receiver. Implemented by the CC1101 driver and by
+/// `MockRadio`, which replays scripted frames to run the pipeline without a chip.
+pub trait RadioSource {
+    /// Count received frames and preamble errors into shared reception statistics.
+    fn set_stats(&mut self, stats: Arc<RxStats>);
+    fn set_freq_hz(&mut self, freq_hz: u64);
+    fn set_register_overrides(&mut self, overrides: Vec<(u8, u8)>);
+    fn enable_gdo0_wakeup(&mut self) -> Result<(), Cc1101RadioError>;
+    fn init(&mut self) -> Result<(), Cc1101RadioError>;
+    fn restart_radio(&mut self) -> Result<(), Cc1101RadioError>;
+    /// Next frame from the L-field on, `Ok(None)` on watchdog timeout. Must be cancel safe.
+    fn wait_for_packet(&mut self, timeout_s: u64) -> impl Future<Output = Result<Option<Vec<u8>>, Cc1101RadioError>>;
+    /// RSSI (dBm) and LQI of the last received packet.
+    fn last_signal(&self) -> (Option<f32>, Option<u8>);
+    fn handle_command(&mut self, cmd: RadioCommand);
+}
+
 pub struct Cc1101Radio<'a> {
-    spi: spi::SpiDeviceDriver<'a, &'a esp_idf_hal::spi::SpiDriver<'a>>,
+    /// Only `None` between dropping a failed device and creating its replacement
+    spi: Option<SpiDevice<'a>>,
+    spi_factory: SpiFactory<'a>,
+    /// SPI transactions failed in a row, see `with_spi()`
+    spi_error_streak: u32,
     gdo0: PinDriver<'a, Input>,
+    freq_hz: u64,
+    register_overrides: Vec<(u8, u8)>,
+    last_rssi: Option<u8>,
+    last_lqi: Option<u8>,
+    stats: Option<Arc<RxStats>>,
+    rssi_window: Option<RssiWindow>,
+    /// A transmit was cut short before RX was restored, `wait_for_packet()` finishes the job
+    rx_restore_pending: bool,
+}
+
+/// RSSI range seen in RX since the signal last looked alive.
+#[derive(Clone, Copy, Debug)]
+struct RssiWindow {
+    since: Instant,
+    min_dbm: f32,
+    max_dbm: f32,
 }
 
 impl<'a> Cc1101Radio<'a> {
-    pub fn new(spi: spi::SpiDeviceDriver<'a, &'a esp_idf_hal::spi::SpiDriver<'a>>, gdo0: PinDriver<'a, Input>) -> Self {
-        Self { spi, gdo0 }
+    pub fn new(mut spi_factory: SpiFactory<'a>, gdo0: PinDriver<'a, Input>) -> Result<Self, EspError> {
+        Ok(Self {
+            spi: Some(spi_factory()?),
+            spi_factory,
+            spi_error_streak: 0,
+            gdo0,
+            freq_hz: WMBUS_FREQ_HZ,
+            register_overrides: Vec::new(),
+            last_rssi: None,
+            last_lqi: None,
+            stats: None,
+            rssi_window: None,
+            rx_restore_pending: false,
+        })
     }
 
-    fn write_config(&mut self, reg: CcConfig, value: u8) -> Result<(), Cc1101RadioError> {
-        let mut radio = LowLevelCc1101::new(&mut self.spi)?;
-        radio.write_register(reg, value)?;
+    /// Count received frames and preamble errors into shared reception statistics.
+    pub fn set_stats(&mut self, stats: Arc<RxStats>) {
+        self.stats = Some(stats);
+    }
+
+    /// Let GDO0 wake the MCU from automatic light sleep. Only level interrupts work as
+    /// GPIO wakeup sources, which is how packets are awaited anyway: the sync word wakes
+    /// the MCU and the packet is drained from the FIFO awake.
+    pub fn enable_gdo0_wakeup(&mut self) -> Result<(), Cc1101RadioError> {
+        let pin = self.gdo0.pin();
+        esp_idf_sys::esp!(unsafe {
+            esp_idf_sys::gpio_wakeup_enable(pin, esp_idf_sys::gpio_int_type_t_GPIO_INTR_HIGH_LEVEL)
+        })?;
+        esp_idf_sys::esp!(unsafe { esp_idf_sys::esp_sleep_enable_gpio_wakeup() })?;
+        info!("CC1101: GDO0 (GPIO{pin}) enabled as light sleep wakeup source");
         Ok(())
     }
 
-    #[allow(dead_code)]
+    /// Set the RX center frequency used by the next `init()`.
+    /// Values outside the 863–870 MHz SRD band fall back to the wMBus C1 default.
+    pub fn set_freq_hz(&mut self, freq_hz: u64) {
+        if (WMBUS_FREQ_MIN_HZ..=WMBUS_FREQ_MAX_HZ).contains(&freq_hz) {
+            self.freq_hz = freq_hz;
+        } else {
+            warn!("CC1101: Frequency {freq_hz} Hz out of range, using default {WMBUS_FREQ_HZ} Hz");
+            self.freq_hz = WMBUS_FREQ_HZ;
+        }
+    }
+
+    /// Config register values written by the next `init()` after everything else.
+    pub fn set_register_overrides(&mut self, overrides: Vec<(u8, u8)>) {
+        self.register_overrides = overrides;
+    }
+
+    /// Run one SPI access and keep count of failures, every access to the chip goes through here.
+    fn with_spi<T>(
+        &mut self,
+        f: impl FnOnce(&mut SpiDevice<'a>) -> Result<T, Cc1101RadioError>,
+    ) -> Result<T, Cc1101RadioError> {
+        let result = match self.spi.as_mut() {
+            Some(spi) => f(spi),
+            None => Err(Cc1101RadioError::NoSpiDevice),
+        };
+        match &result {
+            Ok(_) => self.spi_error_streak = 0,
+            Err(e) => {
+                self.spi_error_streak += 1;
+                warn!("CC1101: SPI error ({} in a row): {e}", self.spi_error_streak);
+                if let Some(stats) = &self.stats {
+                    stats.spi_errors.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+        result
+    }
+
+    fn write_config(&mut self, reg: CcConfig, value: u8) -> Result<(), Cc1101RadioError> {
+        self.with_spi(|spi| {
+            let mut radio = LowLevelCc1101::new(spi)?;
+            radio.write_register(reg, value)?;
+            Ok(())
+        })
+    }
+
     fn read_config(&mut self, reg: CcConfig) -> Result<u8, Cc1101RadioError> {
-        let mut radio = LowLevelCc1101::new(&mut self.spi)?;
-        Ok(radio.read_register(reg)?)
+        self.with_spi(|spi| {
+            let mut radio = LowLevelCc1101::new(spi)?;
+            Ok(radio.read_register(reg)?)
+        })
     }
 
     fn read_status(&mut self, reg: CcStatus) -> Result<u8, Cc1101RadioError> {
-        let mut radio = LowLevelCc1101::new(&mut self.spi)?;
-        Ok(radio.read_register(reg)?)
+        self.with_spi(|spi| {
+            let mut radio = LowLevelCc1101::new(spi)?;
+            Ok(radio.read_register(reg)?)
+        })
     }
 
     fn strobe(&mut self, cmd: CcCommand) -> Result<(), Cc1101RadioError> {
-        let mut radio = LowLevelCc1101::new(&mut self.spi)?;
-        radio.write_strobe(cmd)?;
-        Ok(())
+        self.with_spi(|spi| {
+            let mut radio = LowLevelCc1101::new(spi)?;
+            radio.write_strobe(cmd)?;
+            Ok(())
+        })
+    }
+
+    fn read_register_raw(&mut self, addr: u8) -> Result<u8, Cc1101RadioError> {
+        // Status registers share addresses with strobes and need the burst bit set
+        let flags = if addr >= STATUS_REG_PARTNUM {
+            READ_BURST
+        } else {
+            READ_SINGLE
+        };
+        let mut buf = [addr | flags, 0];
+        self.with_spi(|spi| Ok(spi.transfer_in_place(&mut buf)?))?;
+        Ok(buf[1])
+    }
+
+    /// Read back all config registers plus PARTNUM, VERSION, MARCSTATE and RXBYTES.
+    pub fn dump_registers(&mut self) -> Result<Vec<(u8, u8)>, Cc1101RadioError> {
+        let mut regs = Vec::with_capacity(CONFIG_REG_LAST as usize + 5);
+        for addr in (0..=CONFIG_REG_LAST).chain([
+            STATUS_REG_PARTNUM,
+            STATUS_REG_VERSION,
+            STATUS_REG_MARCSTATE,
+            STATUS_REG_RXBYTES,
+        ]) {
+            regs.push((addr, self.read_register_raw(addr)?));
+        }
+        Ok(regs)
+    }
+
+    pub fn snapshot(&mut self) -> Result<RadioSnapshot, Cc1101RadioError> {
+        Ok(RadioSnapshot {
+            registers: self.dump_registers()?,
+            rssi_dbm: self.last_rssi.map(rssi_to_dbm),
+            lqi: self.last_lqi,
+        })
+    }
+
+    /// RSSI (dBm) and LQI of the last received packet.
+    pub fn last_signal(&self) -> (Option<f32>, Option<u8>) {
+        (self.last_rssi.map(rssi_to_dbm), self.last_lqi)
+    }
+
+    pub fn handle_command(&mut self, cmd: RadioCommand) {
+        match cmd {
+            RadioCommand::DumpRegisters(reply) => {
+                let snapshot = self.snapshot().map_err(|e| e.to_string());
+                if reply.send(snapshot).is_err() {
+                    warn!("CC1101: Register dump requester went away");
+                }
+            }
+            RadioCommand::Restart(reply) => {
+                let result = self.restart_radio().map_err(|e| e.to_string());
+                if let Err(e) = &result {
+                    error!("CC1101: Restart failed: {e}");
+                }
+                reply.send(result).ok();
+            }
+            RadioCommand::Inject(_, reply) => {
+                // Decoding needs the meter config, only read_meter() can serve this
+                error!("CC1101: Inject command reached the radio driver");
+                reply.send(Err(FrameError::Payload)).ok();
+            }
+            RadioCommand::Rekey(_, _, reply) => {
+                error!("CC1101: Rekey command reached the radio driver");
+                reply.send(Err("Not served by the radio driver".into())).ok();
+            }
+        }
     }
 
     fn read_fifo_burst(&mut self, buf: &mut [u8]) -> Result<(), Cc1101RadioError> {
@@ -123,11 +422,70 @@ impl<'a> Cc1101Radio<'a> {
         let len = buf.len();
         let mut txbuf = vec![0u8; len + 1];
         txbuf[0] = FIFO | READ_BURST;
-        self.spi.transfer_in_place(&mut txbuf)?;
+        self.with_spi(|spi| Ok(spi.transfer_in_place(&mut txbuf)?))?;
         buf.copy_from_slice(&txbuf[1..]);
         Ok(())
     }
 
+    fn write_register_raw(&mut self, addr: u8, value: u8) -> Result<(), Cc1101RadioError> {
+        self.with_spi(|spi| Ok(spi.write(&[addr, value])?))
+    }
+
+    fn write_fifo_burst(&mut self, data: &[u8]) -> Result<(), Cc1101RadioError> {
+        let mut txbuf = Vec::with_capacity(data.len() + 1);
+        txbuf.push(FIFO | WRITE_BURST);
+        txbuf.extend_from_slice(data);
+        self.with_spi(|spi| Ok(spi.write(&txbuf)?))
+    }
+
+    /// Send one frame, e.g. a wakeup for two-way meters, and go back to RX whatever
+    /// the outcome. The frame is sent as is after the preamble and sync word, so it
+    /// starts at the L-field and must fit the 64 byte TX FIFO.
+    ///
+    /// Dropping the future while it waits for the packet to go out leaves the radio
+    /// outside RX, the next `wait_for_packet()` restores it before listening.
+    pub async fn transmit(&mut self, frame: &[u8]) -> Result<(), Cc1101RadioError> {
+        if frame.is_empty() || frame.len() > TX_FIFO_SIZE {
+            return Err(Cc1101RadioError::TxLength(frame.len()));
+        }
+        info!("CC1101: Transmitting {} bytes", frame.len());
+        self.rx_restore_pending = true;
+        let result = self.send_frame(frame).await;
+
+        let restored = self.restore_receiver();
+        if let Err(e) = &result {
+            error!("CC1101: Transmit failed: {e}");
+        }
+        result.and(restored)
+    }
+
+    async fn send_frame(&mut self, frame: &[u8]) -> Result<(), Cc1101RadioError> {
+        self.strobe(CcCommand::SIDLE)?;
+        self.strobe(CcCommand::SFTX)?;
+        self.write_config(CcConfig::PKTCTRL0, PKTCTRL0_FIXED)?;
+        self.write_config(CcConfig::PKTLEN, frame.len() as u8)?;
+        self.write_register_raw(PATABLE, TX_PA_POWER)?;
+        self.write_fifo_burst(frame)?;
+        self.strobe(CcCommand::STX)?;
+
+        // MCSM1.TXOFF_MODE = IDLE, the radio leaves TX once the packet is out
+        let mut marcstate = 0;
+        for _ in 0..TX_POLL_TRIES {
+            sleep(Duration::from_millis(TX_POLL_MS)).await;
+            marcstate = self.read_status(CcStatus::MARCSTATE)? & 0x1F;
+            if marcstate == MARC_IDLE {
+                return Ok(());
+            }
+        }
+        Err(Cc1101RadioError::TxTimeout { marcstate })
+    }
+
+    fn restore_receiver(&mut self) -> Result<(), Cc1101RadioError> {
+        self.start_receiver()?;
+        self.rx_restore_pending = false;
+        Ok(())
+    }
+
     fn start_receiver(&mut self) -> Result<(), Cc1101RadioError> {
         // Go to IDLE
         self.strobe(CcCommand::SIDLE)?;
@@ -139,6 +497,8 @@ impl<'a> Cc1101Radio<'a> {
             }
             FreeRtos::delay_ms(5);
         }
+        // The previous packet or a transmit left fixed length mode behind
+        self.write_config(CcConfig::PKTCTRL0, PKTCTRL0_INFINITE)?;
         // Flush RX FIFO
         self.strobe(CcCommand::SFRX)?;
         // Start RX
@@ -156,12 +516,21 @@ impl<'a> Cc1101Radio<'a> {
 
     pub fn init(&mut self) -> Result<(), Cc1101RadioError> {
         info!("CC1101: Resetting radio...");
-        {
-            let mut radio = Cc1101::new(&mut self.spi)?;
+        self.with_spi(|spi| {
+            let mut radio = Cc1101::new(spi)?;
             radio.reset()?;
-        }
+            Ok(())
+        })?;
         FreeRtos::delay_ms(100);
 
+        // Verify chip
+        let partnum = self.read_status(CcStatus::PARTNUM)?;
+        let version = self.read_status(CcStatus::VERSION)?;
+        info!("CC1101: PARTNUM=0x{:02X} VERSION=0x{:02X}", partnum, version);
+        if partnum != CC1101_PARTNUM || !(CC1101_VERSION_MIN..=CC1101_VERSION_MAX).contains(&version) {
+            return Err(Cc1101RadioError::ChipNotDetected { partnum, version });
+        }
+
         // Force exact legacy profile because some bit patterns are not expressible
         // via crate high-level enums (for example MDMCFG2 sync+carrier variants).
         info!("CC1101: Applying low-level config...");
@@ -169,14 +538,22 @@ impl<'a> Cc1101Radio<'a> {
             self.write_config(*reg, *value)?;
         }
 
-        info!("CC1101: Applying high-level config...");
-        {
-            let mut radio = Cc1101::new(&mut self.spi)?;
+        info!("CC1101: Applying high-level config, frequency {} Hz...", self.freq_hz);
+        let freq_hz = self.freq_hz;
+        self.with_spi(|spi| {
+            let mut radio = Cc1101::new(spi)?;
             radio.set_synthesizer_if(WMBUS_IF_HZ)?;
-            radio.set_frequency(WMBUS_FREQ_HZ)?;
+            radio.set_frequency(freq_hz)?;
             radio.set_chanbw(WMBUS_CHANBW_HZ)?;
             radio.set_data_rate(WMBUS_DATA_RATE_BPS)?;
             radio.set_deviation(WMBUS_DEVIATION_HZ)?;
+            Ok(())
+        })?;
+
+        // Field tuning from radio_registers wins over both of the above
+        for (addr, value) in self.register_overrides.clone() {
+            info!("CC1101: Overriding register 0x{addr:02X} = 0x{value:02X}");
+            self.write_register_raw(addr, value)?;
         }
 
         // This check was only needed to be made once.
@@ -196,14 +573,7 @@ impl<'a> Cc1101Radio<'a> {
         info!("CC1101 register checks done.");
         */
 
-        // Calibrate
-        self.strobe(CcCommand::SCAL)?;
-        FreeRtos::delay_ms(100);
-
-        // Verify chip
-        let partnum = self.read_status(CcStatus::PARTNUM)?;
-        let version = self.read_status(CcStatus::VERSION)?;
-        info!("CC1101: PARTNUM=0x{:02X} VERSION=0x{:02X}", partnum, version);
+        self.calibrate()?;
 
         // Start receiving
         self.start_receiver()?;
@@ -211,14 +581,56 @@ impl<'a> Cc1101Radio<'a> {
         Ok(())
     }
 
+    /// Run SCAL and check the outcome: the radio must be back in IDLE with FSCAL1 below `FSCAL1_CAL_FAILED`.
+    /// A PARTNUM that reads fine says nothing about this, and a failed calibration just receives nothing.
+    fn calibrate(&mut self) -> Result<(), Cc1101RadioError> {
+        let mut failed = true;
+        for attempt in 1..=CAL_ATTEMPTS {
+            self.strobe(CcCommand::SCAL)?;
+            FreeRtos::delay_ms(100);
+            let marcstate = self.read_status(CcStatus::MARCSTATE)? & 0x1F;
+            let fscal1 = self.read_config(CcConfig::FSCAL1)?;
+            if marcstate == MARC_IDLE && fscal1 < FSCAL1_CAL_FAILED {
+                info!("CC1101: Calibrated, FSCAL1=0x{fscal1:02X}");
+                failed = false;
+                break;
+            }
+            warn!(
+                "CC1101: Calibration attempt {attempt}/{CAL_ATTEMPTS} invalid (MARCSTATE=0x{marcstate:02X} FSCAL1=0x{fscal1:02X})"
+            );
+            self.strobe(CcCommand::SIDLE)?;
+        }
+        if failed {
+            error!("CC1101: Calibration failed {CAL_ATTEMPTS} times, reception is unlikely");
+        }
+        if let Some(stats) = &self.stats {
+            stats.radio_cal_failed.store(failed, Ordering::Relaxed);
+        }
+        Ok(())
+    }
+
     pub fn restart_radio(&mut self) -> Result<(), Cc1101RadioError> {
         warn!("CC1101: Restarting radio (watchdog)...");
         self.init()
     }
 
     /// Wait for a wMBus packet. Returns `Ok(None)` on watchdog timeout.
+    ///
+    /// SPI errors while waiting are retried, after `SPI_ERROR_RESET_THRESHOLD` of them in a row
+    /// the SPI device is recreated and the chip reinitialized, see `recover_spi()`. Only a
+    /// failure of that recovery, or an error not caused by SPI, is returned.
+    ///
+    /// Cancel safe, so it can sit in `tokio::select!` next to the command channel: the await
+    /// points are the wait for GDO0 and the pause after an SPI error, and a packet is drained from the FIFO and the
+    /// receiver restarted without awaiting in between. A packet that starts while nobody
+    /// waits is read by the next call if it comes soon enough, otherwise the FIFO overflows
+    /// and is flushed. Dropping the future never loses a packet that was already read.
     pub async fn wait_for_packet(&mut self, timeout_s: u64) -> Result<Option<Vec<u8>>, Cc1101RadioError> {
-        match Box::pin(timeout(Duration::from_secs(timeout_s), self.poll_gdo0())).await {
+        if self.rx_restore_pending {
+            warn!("CC1101: Previous transmit was interrupted, restoring RX");
+            self.restore_receiver()?;
+        }
+        match Box::pin(timeout(Duration::from_secs(timeout_s), self.poll_gdo0_recovering())).await {
             Ok(packet) => Ok(Some(packet?)),
             Err(_) => {
                 warn!("CC1101: Watchdog timeout ({timeout_s} s) with no packets received");
@@ -227,38 +639,204 @@ impl<'a> Cc1101Radio<'a> {
         }
     }
 
+    async fn poll_gdo0_recovering(&mut self) -> Result<Vec<u8>, Cc1101RadioError> {
+        loop {
+            match self.poll_gdo0().await {
+                // A streak means the error came from the failing SPI access
+                Err(e) if self.spi_error_streak > 0 => {
+                    self.recover_spi(e)?;
+                    sleep(Duration::from_millis(SPI_RETRY_MS)).await;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Restart RX after an SPI error, or once they keep coming, drop the SPI device for a
+    /// new one and reinitialize the chip. An error from here ends reception, the caller reboots.
+    fn recover_spi(&mut self, e: Cc1101RadioError) -> Result<(), Cc1101RadioError> {
+        if self.spi_error_streak < SPI_ERROR_RESET_THRESHOLD {
+            warn!("CC1101: Restarting RX after SPI error: {e}");
+            // A failure here adds to the streak, the next round escalates
+            self.start_receiver().ok();
+            return Ok(());
+        }
+        error!(
+            "CC1101: {} SPI errors in a row, recreating the SPI device and reinitializing",
+            self.spi_error_streak
+        );
+        if let Some(stats) = &self.stats {
+            stats.spi_resets.fetch_add(1, Ordering::Relaxed);
+        }
+        // The old device releases the bus and CS pin before the new one claims them
+        self.spi = None;
+        self.spi = Some((self.spi_factory)()?);
+        self.spi_error_streak = 0;
+        self.init()
+    }
+
+    /// An infinite length packet nobody read runs the FIFO over, GDO0 drops and the radio stops.
+    fn rx_overflowed(&mut self) -> Result<bool, Cc1101RadioError> {
+        Ok(self.read_status(CcStatus::MARCSTATE)? & 0x1F == MARC_RXFIFO_OVERFLOW)
+    }
+
+    /// RXBYTES, read until two reads agree as it may be wrong while bytes arrive (CC1101 errata).
+    fn rx_bytes(&mut self) -> Result<u8, Cc1101RadioError> {
+        let mut rx_status = self.read_status(CcStatus::RXBYTES)?;
+        loop {
+            let again = self.read_status(CcStatus::RXBYTES)?;
+            if again == rx_status {
+                return Ok(rx_status);
+            }
+            rx_status = again;
+        }
+    }
+
+    /// Drain one packet from the FIFO while it is still arriving, starting from the sync word.
+    /// The L-field sets PKTLEN and switches to fixed length, so the radio ends the packet
+    /// after it and goes IDLE (MCSM1); frames longer than the 64 byte FIFO are read in chunks.
+    /// Busy-polls without awaiting, a tokio tick is longer than it takes to fill the FIFO.
+    /// `None` for an overflow, a timeout or an implausible L-field.
+    fn read_packet(&mut self) -> Result<Option<Vec<u8>>, Cc1101RadioError> {
+        let deadline = Instant::now() + Duration::from_millis(PACKET_READ_TIMEOUT_MS);
+        let mut data = Vec::with_capacity(WMBUS_HEADER_BYTES);
+        let mut total = WMBUS_HEADER_BYTES;
+        while data.len() < total {
+            if Instant::now() > deadline {
+                warn!(
+                    "CC1101: Packet incomplete after {PACKET_READ_TIMEOUT_MS} ms ({} of {total} bytes)",
+                    data.len()
+                );
+                return Ok(None);
+            }
+            let marcstate = self.read_status(CcStatus::MARCSTATE)? & 0x1F;
+            let rx_status = self.rx_bytes()?;
+            if marcstate == MARC_RXFIFO_OVERFLOW || rx_status & RXBYTES_OVERFLOW != 0 {
+                warn!(
+                    "CC1101: RX FIFO overflow (MARCSTATE=0x{marcstate:02X}) after {} bytes",
+                    data.len()
+                );
+                return Ok(None);
+            }
+
+            // Never empty the FIFO while the packet is still coming in (CC1101 errata)
+            let receiving = marcstate == MARC_RX;
+            let available = (rx_status & RXBYTES_MASK) as usize;
+            let chunk = (total - data.len()).min(available.saturating_sub(receiving as usize));
+            if chunk == 0 {
+                if !receiving && available == 0 {
+                    warn!("CC1101: Packet ended after {} of {total} bytes", data.len());
+                    return Ok(None);
+                }
+                continue;
+            }
+            let start = data.len();
+            data.resize(start + chunk, 0);
+            self.read_fifo_burst(&mut data[start..])?;
+
+            if total == WMBUS_HEADER_BYTES && data.len() == WMBUS_HEADER_BYTES {
+                // Another frame format or noise, the sync check reports it
+                if data[..2] != WMBUS_SYNC_WORD.to_be_bytes() {
+                    return Ok(Some(data));
+                }
+                let l_field = data[2];
+                if !(WMBUS_MIN_L_FIELD..=u8::MAX - WMBUS_HEADER_BYTES as u8).contains(&l_field) {
+                    warn!("CC1101: Implausible L-field {l_field}");
+                    return Ok(None);
+                }
+                // Frame format B: the L-field counts every byte after it, CRCs included
+                total = WMBUS_HEADER_BYTES + l_field as usize;
+                self.write_config(CcConfig::PKTLEN, total as u8)?;
+                self.write_config(CcConfig::PKTCTRL0, PKTCTRL0_FIXED)?;
+            }
+        }
+        Ok(Some(data))
+    }
+
+    /// Sample the RSSI in RX without a packet. A detached antenna or a dead front end leaves it
+    /// at the no-signal floor or frozen at one value, while a working receiver sees the noise
+    /// floor wander. Either condition lasting `ANTENNA_SUSPECT_SECS` flags `antenna_suspect`.
+    fn sample_rssi(&mut self) -> Result<(), Cc1101RadioError> {
+        let dbm = rssi_to_dbm(self.read_status(CcStatus::RSSI)?);
+        let fresh = RssiWindow {
+            since: Instant::now(),
+            min_dbm: dbm,
+            max_dbm: dbm,
+        };
+        let mut window = self.rssi_window.unwrap_or(fresh);
+        window.min_dbm = window.min_dbm.min(dbm);
+        window.max_dbm = window.max_dbm.max(dbm);
+
+        let floor = window.max_dbm <= ANTENNA_FLOOR_DBM;
+        let frozen = window.max_dbm == window.min_dbm;
+        if !floor && !frozen {
+            window = fresh;
+        }
+        self.rssi_window = Some(window);
+
+        let suspect = (floor || frozen) && window.since.elapsed() >= Duration::from_secs(ANTENNA_SUSPECT_SECS);
+        if suspect && !self.antenna_suspect() {
+            warn!(
+                "CC1101: RSSI stuck at {:.1}..{:.1} dBm for {} s, antenna disconnected?",
+                window.min_dbm,
+                window.max_dbm,
+                window.since.elapsed().as_secs()
+            );
+        }
+        self.set_antenna_suspect(suspect);
+        Ok(())
+    }
+
+    fn antenna_suspect(&self) -> bool {
+        self.stats
+            .as_ref()
+            .is_some_and(|s| s.antenna_suspect.load(Ordering::Relaxed))
+    }
+
+    fn set_antenna_suspect(&self, suspect: bool) {
+        if let Some(stats) = &self.stats {
+            stats.antenna_suspect.store(suspect, Ordering::Relaxed);
+        }
+    }
+
     async fn poll_gdo0(&mut self) -> Result<Vec<u8>, Cc1101RadioError> {
-        // IOCFG0=0x01 and FIFOTHR=0x01: GDO0 rises when FIFO has at least 8 bytes
-        // IOCFG0=0x01 and FIFOTHR=0x0E: GDO0 rises when FIFO has at least 60 bytes
+        // IOCFG0=0x06: GDO0 goes high on the sync word and low again at the end of the packet
+        // or on a FIFO overflow. The packet is read as it arrives, see read_packet().
         loop {
-            while self.gdo0.is_low() {
-                sleep(Duration::from_millis(100)).await;
+            // Sample the noise floor while nothing arrives. A packet that started while
+            // nobody was waiting has overflowed the FIFO by now, flush it.
+            while !self.gdo0.is_high() {
+                if self.rx_overflowed()? {
+                    warn!("CC1101: RX FIFO overflow while not reading, flushing");
+                    self.start_receiver()?;
+                }
+                // A level wait, it doubles as the light sleep wakeup, see enable_gdo0_wakeup()
+                match timeout(Duration::from_secs(RSSI_SAMPLE_SECS), self.gdo0.wait_for_high()).await {
+                    Ok(woken) => {
+                        woken?;
+                        break;
+                    }
+                    Err(_) => self.sample_rssi()?,
+                }
             }
-            // wait for the packet to be completely received
-            sleep(Duration::from_millis(10)).await;
-
-            // Packet received, radio should now be in IDLE.
-            // Read RXBYTES to see how much data we got.
-            let rx_bytes = self.read_status(CcStatus::RXBYTES)? & 0x7F;
-            if rx_bytes == 0 {
-                error!("CC1101: GDO0 triggered but FIFO empty?");
+
+            let Some(fifo_data) = self.read_packet()? else {
                 self.start_receiver()?;
                 continue;
-            }
+            };
 
-            info!("CC1101: Packet received, {} bytes", rx_bytes);
+            // Signal quality of the packet just received, LQI bit 7 is CRC_OK
+            self.last_rssi = Some(self.read_status(CcStatus::RSSI)?);
+            self.set_antenna_suspect(false);
+            self.rssi_window = None;
+            self.last_lqi = Some(self.read_status(CcStatus::LQI)? & 0x7F);
 
-            // Read all FIFO bytes
-            let mut fifo_data = vec![0u8; rx_bytes as usize];
-            self.read_fifo_burst(&mut fifo_data)?;
+            info!("CC1101: Packet received, {} bytes", fifo_data.len());
 
             // Restart receiver for next packet
             self.start_receiver()?;
-
-            // Check preamble bytes
-            if fifo_data.len() < 3 {
-                warn!("CC1101: Packet too short ({} bytes)", fifo_data.len());
-                continue;
+            if let Some(stats) = &self.stats {
+                stats.frame_received();
             }
 
             let sync_hi = ((WMBUS_SYNC_WORD >> 8) & 0xFF) as u8;
@@ -268,6 +846,9 @@ impl<'a> Cc1101Radio<'a> {
                     "CC1101: Bad preamble: {:02X} {:02X} (expected {:02X} {:02X})",
                     fifo_data[0], fifo_data[1], sync_hi, sync_lo
                 );
+                if let Some(stats) = &self.stats {
+                    stats.preamble_error();
+                }
                 continue;
             }
 
@@ -278,4 +859,42 @@ impl<'a> Cc1101Radio<'a> {
         }
     }
 }
+
+impl RadioSource for Cc1101Radio<'_> {
+    fn set_stats(&mut self, stats: Arc<RxStats>) {
+        Cc1101Radio::set_stats(self, stats)
+    }
+
+    fn set_freq_hz(&mut self, freq_hz: u64) {
+        Cc1101Radio::set_freq_hz(self, freq_hz)
+    }
+
+    fn set_register_overrides(&mut self, overrides: Vec<(u8, u8)>) {
+        Cc1101Radio::set_register_overrides(self, overrides)
+    }
+
+    fn enable_gdo0_wakeup(&mut self) -> Result<(), Cc1101RadioError> {
+        Cc1101Radio::enable_gdo0_wakeup(self)
+    }
+
+    fn init(&mut self) -> Result<(), Cc1101RadioError> {
+        Cc1101Radio::init(self)
+    }
+
+    fn restart_radio(&mut self) -> Result<(), Cc1101RadioError> {
+        Cc1101Radio::restart_radio(self)
+    }
+
+    fn wait_for_packet(&mut self, timeout_s: u64) -> impl Future<Output = Result<Option<Vec<u8>>, Cc1101RadioError>> {
+        Cc1101Radio::wait_for_packet(self, timeout_s)
+    }
+
+    fn last_signal(&self) -> (Option<f32>, Option<u8>) {
+        Cc1101Radio::last_signal(self)
+    }
+
+    fn handle_command(&mut self, cmd: RadioCommand) {
+        Cc1101Radio::handle_command(self, cmd)
+    }
+}
 // EOF
@@ -1,55 +1,282 @@
 // state.rs
 
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    time::Instant,
+};
+
+use crc::{CRC_32_ISCSI, Crc};
+
 use crate::*;
 
 pub const AP_MODE_NVS_KEY: &str = "boot_ap";
+// Values of the one-shot AP mode flag: asked for with the button, or after the WiFi connection failed
+pub const AP_MODE_BUTTON: u8 = 1;
+pub const AP_MODE_PROVISION: u8 = 2;
+pub const CONFIG_INCOMPLETE_MSG: &str = "configure meter_id/meter_key";
+const RESET_REASON_NVS_KEY: &str = "reset_reason";
+const READING_NVS_KEY: &str = "last_reading";
+const COUNTER_RESET_NVS_KEY: &str = "counter_reset";
+const FRAMES_LIFETIME_NVS_KEY: &str = "frames_total";
+// Flash wear limit: persist the latest reading at most this often
+const READING_PERSIST_SECS: u64 = 1800;
+// Same for the lifetime frame counter, a power cut loses at most this much of it
+const FRAMES_LIFETIME_PERSIST_SECS: u64 = 3600;
+const RADIO_CMD_QUEUE_LEN: usize = 4;
+pub const HISTORY_LEN: usize = 64;
+// A total below this fraction of the previous one means a new or reset meter
+const COUNTER_RESET_DIVISOR: u32 = 2;
+// Timestamps below this (2021-01-01) are seconds since boot, not wall clock time
+pub const NTP_VALID_EPOCH: i64 = 1_609_459_200;
+
+/// Reception health counters since boot. The success ratio covers the last 64
+/// frames that were either decoded or failed on preamble/CRC/decryption; frames of other meters are not counted.
+#[derive(Default)]
+pub struct RxStats {
+    pub frames: AtomicU32,
+    /// Frames over the device's life, restored from nvs at boot, see `MyState::persist_frames_lifetime()`
+    pub frames_lifetime: AtomicU64,
+    pub preamble_errors: AtomicU32,
+    pub crc_errors: AtomicU32,
+    /// Frames of our meter that decrypted to garbage, see `FrameError::KeySuspect`
+    pub decrypt_suspect: AtomicU32,
+    pub meter_id_mismatches: AtomicU32,
+    pub decoded: AtomicU32,
+    /// RSSI stuck at the no-signal floor while in RX, see `Cc1101Radio::sample_rssi()`
+    pub antenna_suspect: AtomicBool,
+    /// No frame of the configured meter decoded for `radio_quiet_alarm_secs`, see `MyState::check_meter_quiet()`
+    pub meter_quiet: AtomicBool,
+    /// The last CC1101 calibration never came out valid, see `Cc1101Radio::calibrate()`
+    pub radio_cal_failed: AtomicBool,
+    /// Failed SPI transactions with the CC1101, see `Cc1101Radio::with_spi()`
+    pub spi_errors: AtomicU32,
+    /// SPI device recreated after repeated failures, see `Cc1101Radio::recover_spi()`
+    pub spi_resets: AtomicU32,
+    recent: AtomicU64,
+    recent_len: AtomicU32,
+}
+
+impl RxStats {
+    pub fn new(frames_lifetime: u64) -> Self {
+        Self {
+            frames_lifetime: frames_lifetime.into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn frame_received(&self) {
+        self.frames.fetch_add(1, Ordering::Relaxed);
+        self.frames_lifetime.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn preamble_error(&self) {
+        self.preamble_errors.fetch_add(1, Ordering::Relaxed);
+        self.push_recent(false);
+    }
+
+    pub fn decode_result<T>(&self, result: &Result<T, FrameError>) {
+        match result {
+            Ok(_) => {
+                self.decoded.fetch_add(1, Ordering::Relaxed);
+                self.meter_quiet.store(false, Ordering::Relaxed);
+                self.push_recent(true);
+            }
+            Err(FrameError::Crc) => {
+                self.crc_errors.fetch_add(1, Ordering::Relaxed);
+                self.push_recent(false);
+            }
+            Err(FrameError::KeySuspect) => {
+                self.decrypt_suspect.fetch_add(1, Ordering::Relaxed);
+                self.push_recent(false);
+            }
+            Err(FrameError::MeterId) => {
+                self.meter_id_mismatches.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(_) => {}
+        }
+    }
+
+    /// Percentage of good frames in the rolling window, `None` before the first one.
+    pub fn success_ratio(&self) -> Option<f32> {
+        let len = self.recent_len.load(Ordering::Relaxed).min(64);
+        if len == 0 {
+            return None;
+        }
+        let mask = if len == 64 { u64::MAX } else { (1 << len) - 1 };
+        let good = (self.recent.load(Ordering::Relaxed) & mask).count_ones();
+        Some(good as f32 * 100.0 / len as f32)
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "frames": self.frames.load(Ordering::Relaxed),
+            "frames_lifetime": self.frames_lifetime.load(Ordering::Relaxed),
+            "preamble_errors": self.preamble_errors.load(Ordering::Relaxed),
+            "crc_errors": self.crc_errors.load(Ordering::Relaxed),
+            "decrypt_suspect": self.decrypt_suspect.load(Ordering::Relaxed),
+            "meter_id_mismatches": self.meter_id_mismatches.load(Ordering::Relaxed),
+            "decoded": self.decoded.load(Ordering::Relaxed),
+            "success_ratio": self.success_ratio(),
+            "antenna_suspect": self.antenna_suspect.load(Ordering::Relaxed),
+            "meter_quiet": self.meter_quiet.load(Ordering::Relaxed),
+            "radio_cal_failed": self.radio_cal_failed.load(Ordering::Relaxed),
+            "spi_errors": self.spi_errors.load(Ordering::Relaxed),
+            "spi_resets": self.spi_resets.load(Ordering::Relaxed),
+        })
+    }
+
+    fn push_recent(&self, ok: bool) {
+        self.recent
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |v| Some((v << 1) | ok as u64))
+            .ok();
+        self.recent_len.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Human readable `esp_reset_reason()` code.
+pub fn reset_reason_str(reason: esp_idf_sys::esp_reset_reason_t) -> &'static str {
+    match reason {
+        esp_idf_sys::esp_reset_reason_t_ESP_RST_POWERON => "power-on",
+        esp_idf_sys::esp_reset_reason_t_ESP_RST_EXT => "external pin",
+        esp_idf_sys::esp_reset_reason_t_ESP_RST_SW => "software restart",
+        esp_idf_sys::esp_reset_reason_t_ESP_RST_PANIC => "panic",
+        esp_idf_sys::esp_reset_reason_t_ESP_RST_INT_WDT => "interrupt watchdog",
+        esp_idf_sys::esp_reset_reason_t_ESP_RST_TASK_WDT => "task watchdog",
+        esp_idf_sys::esp_reset_reason_t_ESP_RST_WDT => "other watchdog",
+        esp_idf_sys::esp_reset_reason_t_ESP_RST_DEEPSLEEP => "deep sleep wakeup",
+        esp_idf_sys::esp_reset_reason_t_ESP_RST_BROWNOUT => "brownout",
+        esp_idf_sys::esp_reset_reason_t_ESP_RST_SDIO => "SDIO",
+        esp_idf_sys::esp_reset_reason_t_ESP_RST_USB => "USB peripheral",
+        esp_idf_sys::esp_reset_reason_t_ESP_RST_JTAG => "JTAG",
+        esp_idf_sys::esp_reset_reason_t_ESP_RST_EFUSE => "efuse error",
+        esp_idf_sys::esp_reset_reason_t_ESP_RST_PWR_GLITCH => "power glitch",
+        esp_idf_sys::esp_reset_reason_t_ESP_RST_CPU_LOCKUP => "CPU lockup",
+        _ => "unknown",
+    }
+}
 
 pub struct MyState {
     pub ap_mode: bool,
     pub ota_slot: String,
+    pub reset_reason: &'static str,
+    /// Reset reason of the previous boot, kept in nvs
+    pub prev_reset_reason: Option<&'static str>,
+    /// No valid meter_id or meter_key, the radio task idles until both are set
+    pub config_incomplete: AtomicBool,
     pub config: RwLock<MyConfig>,
     pub uptime: RwLock<usize>,
     pub api_cnt: AtomicU32,
     pub net_up: RwLock<bool>,
+    pub ntp_synced: RwLock<bool>,
     pub if_index: RwLock<u32>,
     pub ip_addr: RwLock<net::Ipv4Addr>,
     pub ping_ip: RwLock<Option<net::Ipv4Addr>>,
+    /// Outcome of the last ping watchdog round, `None` before the first one
+    pub ping_ok: RwLock<Option<bool>>,
     pub my_id: RwLock<String>,
+    pub hostname: RwLock<String>,
     pub my_mac: RwLock<[u8; 6]>,
     pub my_mac_s: RwLock<String>,
-    pub latest_data: RwLock<Option<MeterReading>>,
-    pub data_updated: RwLock<bool>,
+    /// Latest reading. Every consumer holds its own receiver, so each one sees every new reading.
+    pub latest_data: watch::Sender<Option<MeterReading>>,
+    pub history: RwLock<VecDeque<HistoryEntry>>,
+    pub consumption: RwLock<Consumption>,
+    pub last_frame: RwLock<Option<LastFrame>>,
+    /// The last `capture_depth` raw frames, oldest first
+    pub capture: RwLock<VecDeque<LastFrame>>,
+    pub last_decoded: RwLock<Option<Instant>>,
+    /// Header of the last frame from the configured meter, decoded or not
+    pub last_header: RwLock<Option<FrameHeader>>,
+    /// Security mode of the last frame from the configured meter, decoded or not
+    pub last_security: RwLock<Option<SecurityMode>>,
+    pub rx_stats: Arc<RxStats>,
+    pub radio_ok: RwLock<Option<bool>>,
+    pub last_rssi: RwLock<Option<f32>>,
+    pub last_lqi: RwLock<Option<u8>>,
+    pub last_acc: RwLock<BTreeMap<[u8; 4], (u8, Instant)>>,
+    pub last_persist: RwLock<Option<(u32, Instant)>>,
+    pub total_baseline: RwLock<Option<u32>>,
+    /// When the meter total last dropped, see `check_counter_reset()`. Kept in nvs.
+    pub last_reset: RwLock<Option<i64>>,
+    /// Wall clock boot time, fixed once NTP is synced
+    pub boot_time: RwLock<Option<i64>>,
+    /// Lifetime frame count last written to nvs and when
+    pub frames_lifetime_saved: RwLock<(u64, Instant)>,
     pub nvs: RwLock<nvs::EspNvs<nvs::NvsDefault>>,
     pub led: RwLock<PinDriver<'static, Output>>,
     pub reset: RwLock<bool>,
+    pub ota: RwLock<OtaProgress>,
+    pub radio_cmd: mpsc::Sender<RadioCommand>,
+    pub radio_cmd_rx: RwLock<Option<mpsc::Receiver<RadioCommand>>>,
 }
 
 impl MyState {
     pub fn new(
         ap_mode: bool,
         config: MyConfig,
-        nvs: nvs::EspNvs<nvs::NvsDefault>,
+        mut nvs: nvs::EspNvs<nvs::NvsDefault>,
         ota_slot: String,
         led: PinDriver<'static, Output>,
     ) -> Self {
+        let (radio_cmd, radio_cmd_rx) = mpsc::channel(RADIO_CMD_QUEUE_LEN);
+        let latest_data = MeterReading::from_nvs(&mut nvs);
+
+        let reset_code = unsafe { esp_idf_sys::esp_reset_reason() };
+        let prev_reset_code = nvs.get_u32(RESET_REASON_NVS_KEY).ok().flatten();
+        if let Err(e) = nvs.set_u32(RESET_REASON_NVS_KEY, reset_code) {
+            error!("Cannot save reset reason to nvs: {e:?}");
+        }
+        let reset_reason = reset_reason_str(reset_code);
+        let prev_reset_reason = prev_reset_code.map(reset_reason_str);
+        info!("Reset reason: {reset_reason}, previous boot: {prev_reset_reason:?}");
+        let config_incomplete = config.meter_id_bytes().is_none() || meter_key_bytes(&nvs).is_none();
+        let last_reset = nvs.get_i64(COUNTER_RESET_NVS_KEY).ok().flatten();
+        let frames_lifetime = nvs.get_u64(FRAMES_LIFETIME_NVS_KEY).ok().flatten().unwrap_or(0);
+
         MyState {
             ap_mode,
             ota_slot,
+            reset_reason,
+            prev_reset_reason,
+            config_incomplete: AtomicBool::new(config_incomplete),
             config: RwLock::new(config),
             uptime: RwLock::new(0),
             api_cnt: 0.into(),
             net_up: RwLock::new(false),
+            ntp_synced: RwLock::new(false),
             if_index: RwLock::new(0),
             ip_addr: RwLock::new(net::Ipv4Addr::new(0, 0, 0, 0)),
             ping_ip: RwLock::new(None),
+            ping_ok: RwLock::new(None),
             my_id: RwLock::new("esp32multical_000000000000".into()),
+            hostname: RwLock::new("esp32multical21".into()),
             my_mac: RwLock::new([0, 0, 0, 0, 0, 0]),
             my_mac_s: RwLock::new("00:00:00:00:00:00".into()),
-            latest_data: RwLock::new(None),
-            data_updated: RwLock::new(false),
+            latest_data: watch::Sender::new(latest_data.clone()),
+            history: RwLock::new(VecDeque::with_capacity(HISTORY_LEN)),
+            consumption: RwLock::new(Consumption::default()),
+            last_frame: RwLock::new(None),
+            capture: RwLock::new(VecDeque::new()),
+            last_decoded: RwLock::new(None),
+            last_header: RwLock::new(None),
+            last_security: RwLock::new(None),
+            rx_stats: Arc::new(RxStats::new(frames_lifetime)),
+            radio_ok: RwLock::new(None),
+            last_rssi: RwLock::new(None),
+            last_lqi: RwLock::new(None),
+            last_acc: RwLock::new(BTreeMap::new()),
+            last_persist: RwLock::new(latest_data.as_ref().map(|r| (r.total_l, Instant::now()))),
+            total_baseline: RwLock::new(latest_data.as_ref().map(|r| r.total_l)),
+            last_reset: RwLock::new(last_reset),
+            boot_time: RwLock::new(None),
+            frames_lifetime_saved: RwLock::new((frames_lifetime, Instant::now())),
             nvs: RwLock::new(nvs),
             led: RwLock::new(led),
             reset: RwLock::new(false),
+            ota: RwLock::new(OtaProgress::default()),
+            radio_cmd,
+            radio_cmd_rx: RwLock::new(Some(radio_cmd_rx)),
         }
     }
 
@@ -70,8 +297,221 @@ impl MyState {
         self.set_led(false).await
     }
 
-    pub async fn request_ap_mode_on_next_boot(&self) -> AppResult<()> {
-        self.nvs.write().await.set_u8(AP_MODE_NVS_KEY, 1)?;
+    /// Record the ACC byte of a frame and report whether it repeats the previous frame
+    /// of the same meter within `window`. Only an identical ACC counts, so the
+    /// 0xFF -> 0x00 wrap is never a duplicate. Meters not heard within `window` are
+    /// forgotten, so every meter in range does not stay in memory for good.
+    pub async fn is_duplicate_frame(&self, meter_id: [u8; 4], acc: u8, window: Duration) -> bool {
+        let now = Instant::now();
+        let mut last_acc = self.last_acc.write().await;
+        last_acc.retain(|_, (_, seen)| now.duration_since(*seen) < window);
+        let duplicate = matches!(
+            last_acc.get(&meter_id),
+            Some((last, seen)) if *last == acc && now.duration_since(*seen) < window
+        );
+        last_acc.insert(meter_id, (acc, now));
+        duplicate
+    }
+
+    /// Timestamp for a new reading: wall clock time once NTP has synced,
+    /// seconds since boot before that. The flag tells which one it is.
+    pub async fn reading_timestamp(&self) -> (i64, bool) {
+        if *self.ntp_synced.read().await {
+            (Utc::now().timestamp(), true)
+        } else {
+            (*self.uptime.read().await as i64, false)
+        }
+    }
+
+    /// Mark the clock as NTP synced and move the readings taken before that
+    /// from uptime-relative to wall clock timestamps.
+    pub async fn set_ntp_synced(&self) {
+        let mut synced = self.ntp_synced.write().await;
+        let boot_epoch = Utc::now().timestamp() - *self.uptime.read().await as i64;
+        let tz_offset_minutes = self.config.read().await.tz_offset_minutes;
+        self.boot_time.write().await.get_or_insert(boot_epoch);
+
+        // Not a new reading, so receivers are not notified. Consumers that wait for
+        // `time_synced` still hold the reading as unseen and pick up the corrected one.
+        self.latest_data.send_if_modified(|latest| {
+            if let Some(reading) = latest.as_mut()
+                && !reading.time_synced
+            {
+                reading.timestamp += boot_epoch;
+                reading.timestamp_s = format_timestamp(reading.timestamp, tz_offset_minutes);
+                reading.time_synced = true;
+            }
+            false
+        });
+        for entry in self.history.write().await.iter_mut() {
+            if entry.timestamp < NTP_VALID_EPOCH {
+                entry.timestamp += boot_epoch;
+            }
+        }
+        if let Some(frame) = self.last_frame.write().await.as_mut()
+            && frame.timestamp < NTP_VALID_EPOCH
+        {
+            frame.timestamp += boot_epoch;
+        }
+        for frame in self.capture.write().await.iter_mut() {
+            if frame.timestamp < NTP_VALID_EPOCH {
+                frame.timestamp += boot_epoch;
+            }
+        }
+        let mut last_reset = self.last_reset.write().await;
+        if let Some(timestamp) = last_reset.as_mut()
+            && *timestamp < NTP_VALID_EPOCH
+        {
+            *timestamp += boot_epoch;
+            self.save_last_reset(*timestamp).await;
+        }
+        *synced = true;
+    }
+
+    /// Append a reading to the history ring, dropping the oldest entry when full.
+    pub async fn push_history(&self, reading: &MeterReading) {
+        let mut history = self.history.write().await;
+        if history.len() >= HISTORY_LEN {
+            history.pop_front();
+        }
+        history.push_back(reading.into());
+    }
+
+    /// Append a raw frame to the capture ring, which is off with `capture_depth` 0.
+    pub async fn push_capture(&self, frame: &LastFrame) {
+        let depth = self.config.read().await.capture_depth.min(CAPTURE_DEPTH_MAX) as usize;
+        let mut capture = self.capture.write().await;
+        while capture.len() >= depth.max(1) {
+            capture.pop_front();
+        }
+        if depth > 0 {
+            capture.push_back(frame.clone());
+        }
+    }
+
+    /// Track the total volume baseline. Returns the previous total if the counter was reset,
+    /// i.e. the meter was replaced and the new total is far below the previous one.
+    /// The baseline follows the new total in either case, and a reset is recorded in `last_reset`.
+    pub async fn check_counter_reset(&self, reading: &MeterReading) -> Option<u32> {
+        let mut baseline = self.total_baseline.write().await;
+        let reset = baseline.filter(|prev| reading.total_l < prev / COUNTER_RESET_DIVISOR);
+        if reset.is_some() {
+            // Save the new total right away instead of waiting for the persist interval
+            *self.last_persist.write().await = None;
+            *self.last_reset.write().await = Some(reading.timestamp);
+            self.save_last_reset(reading.timestamp).await;
+        }
+        *baseline = Some(reading.total_l);
+        reset
+    }
+
+    /// Raise `meter_quiet` once the configured meter has not been decoded for `alarm`, counted from
+    /// `since` until the first frame. Only a diagnostic, the next decoded frame clears it.
+    pub async fn check_meter_quiet(&self, alarm: Duration, since: Instant) {
+        let quiet_for = self.last_decoded.read().await.unwrap_or(since).elapsed();
+        if quiet_for >= alarm && !self.rx_stats.meter_quiet.swap(true, Ordering::Relaxed) {
+            warn!(
+                "Meter silent for {} s, check meter_id and the antenna. Staying up.",
+                quiet_for.as_secs()
+            );
+        }
+    }
+
+    async fn save_last_reset(&self, timestamp: i64) {
+        if let Err(e) = self.nvs.write().await.set_i64(COUNTER_RESET_NVS_KEY, timestamp) {
+            error!("Cannot save counter reset time to nvs: {e:?}");
+        }
+    }
+
+    /// Time of the last counter reset as ISO 8601 UTC, the `last_reset` format Home Assistant expects.
+    pub async fn last_reset_s(&self) -> Option<String> {
+        self.last_reset.read().await.map(|t| format_timestamp(t, 0))
+    }
+
+    /// Boot time as ISO 8601 UTC, `None` before NTP sync.
+    pub async fn boot_time_s(&self) -> Option<String> {
+        self.boot_time.read().await.map(|t| format_timestamp(t, 0))
+    }
+
+    /// Save the reading to nvs if the total has changed and the previous save
+    /// is older than `READING_PERSIST_SECS`.
+    pub async fn persist_reading(&self, reading: &MeterReading) -> AppResult<()> {
+        let mut last_persist = self.last_persist.write().await;
+        if let Some((total_l, saved)) = *last_persist
+            && (total_l == reading.total_l || saved.elapsed() < Duration::from_secs(READING_PERSIST_SECS))
+        {
+            return Ok(());
+        }
+
+        reading.to_nvs(&mut *self.nvs.write().await)?;
+        *last_persist = Some((reading.total_l, Instant::now()));
+        Ok(())
+    }
+
+    /// Save the lifetime frame count if it has changed, at most every `FRAMES_LIFETIME_PERSIST_SECS`
+    /// unless `force`d before a reboot. A failed write is logged and retried at the next interval.
+    pub async fn persist_frames_lifetime(&self, force: bool) {
+        let frames = self.rx_stats.frames_lifetime.load(Ordering::Relaxed);
+        let mut saved = self.frames_lifetime_saved.write().await;
+        let (saved_frames, saved_at) = *saved;
+        if frames == saved_frames || (!force && saved_at.elapsed() < Duration::from_secs(FRAMES_LIFETIME_PERSIST_SECS))
+        {
+            return;
+        }
+
+        match self.nvs.write().await.set_u64(FRAMES_LIFETIME_NVS_KEY, frames) {
+            Ok(()) => *saved = (frames, Instant::now()),
+            Err(e) => {
+                error!("Cannot save lifetime frame count to nvs: {e:?}");
+                *saved = (saved_frames, Instant::now());
+            }
+        }
+    }
+
+    /// `reason` is `AP_MODE_BUTTON` or `AP_MODE_PROVISION`.
+    pub async fn request_ap_mode_on_next_boot(&self, reason: u8) -> AppResult<()> {
+        self.nvs.write().await.set_u8(AP_MODE_NVS_KEY, reason)?;
+        Ok(())
+    }
+}
+
+impl MeterReading {
+    pub fn from_nvs(nvs: &mut nvs::EspNvs<nvs::NvsDefault>) -> Option<Self> {
+        let b = match nvs_get_blob_vec(nvs, READING_NVS_KEY) {
+            Err(e) => {
+                error!("Nvs read error {e:?}");
+                return None;
+            }
+            Ok(Some(b)) => b,
+            _ => {
+                info!("No saved meter reading in nvs");
+                return None;
+            }
+        };
+
+        let crc = Crc::<u32>::new(&CRC_32_ISCSI);
+        let digest = crc.digest();
+        match postcard::from_bytes_crc32::<MeterReading>(&b, digest) {
+            Ok(r) => {
+                info!("Restored meter reading from nvs: {r:?}");
+                Some(r)
+            }
+            Err(e) => {
+                error!("Cannot parse meter reading from nvs: {e:?}");
+                None
+            }
+        }
+    }
+
+    pub fn to_nvs(&self, nvs: &mut nvs::EspNvs<nvs::NvsDefault>) -> AppResult<()> {
+        let crc = Crc::<u32>::new(&CRC_32_ISCSI);
+        let digest = crc.digest();
+        let nvsdata = postcard::to_allocvec_crc32(self, digest)
+            .map_err(|e| AppError::Message(format!("Cannot encode meter reading to buffer {e:?}")))?;
+
+        nvs.set_blob(READING_NVS_KEY, &nvsdata)
+            .map_err(|e| AppError::Message(format!("Cannot save to nvs: {e:?}")))?;
+        info!("Meter reading saved to nvs.");
         Ok(())
     }
 }
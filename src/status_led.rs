@@ -0,0 +1,62 @@
+// status_led.rs — optional reception indicator LED on a configurable GPIO
+
+use std::sync::atomic::Ordering;
+
+use crate::*;
+
+const STATUS_LED_POLL_MS: u64 = 250;
+const STATUS_LED_BLINK_MS: u64 = 100;
+const STATUS_LED_BLINKS: u32 = 3;
+
+/// Solid on while a frame of the configured meter was decoded within the radio watchdog
+/// window, off otherwise. Each decoded frame is shown as a short burst of blinks.
+pub async fn run_status_led(state: Arc<Pin<Box<MyState>>>) -> AppResult<()> {
+    let (gpio, watchdog_secs, tz_offset_minutes) = {
+        let config = state.config.read().await;
+        (
+            config.status_led_gpio,
+            config.radio_watchdog_secs.max(RADIO_WATCHDOG_MIN_SECS) as u64,
+            config.tz_offset_minutes,
+        )
+    };
+    if gpio < 0 {
+        info!("Status LED is disabled by configuration.");
+        // we cannot return, otherwise tokio::select in main() will exit
+        loop {
+            sleep(Duration::from_secs(3600)).await;
+        }
+    }
+
+    let pin = gpio as esp_idf_sys::gpio_num_t;
+    esp_idf_sys::esp!(unsafe { esp_idf_sys::gpio_reset_pin(pin) })?;
+    esp_idf_sys::esp!(unsafe { esp_idf_sys::gpio_set_direction(pin, esp_idf_sys::gpio_mode_t_GPIO_MODE_OUTPUT) })?;
+    set_status_led(pin, false)?;
+    info!("Status LED on GPIO{gpio}");
+
+    let mut decoded = state.rx_stats.decoded.load(Ordering::Relaxed);
+    loop {
+        sleep(Duration::from_millis(STATUS_LED_POLL_MS)).await;
+
+        let now_decoded = state.rx_stats.decoded.load(Ordering::Relaxed);
+        if now_decoded != decoded {
+            decoded = now_decoded;
+            for _ in 0..STATUS_LED_BLINKS {
+                set_status_led(pin, false)?;
+                sleep(Duration::from_millis(STATUS_LED_BLINK_MS)).await;
+                set_status_led(pin, true)?;
+                sleep(Duration::from_millis(STATUS_LED_BLINK_MS)).await;
+            }
+        }
+
+        let window = Duration::from_secs(watchdog_timeout(watchdog_secs, tz_offset_minutes));
+        let healthy = state.last_decoded.read().await.is_some_and(|t| t.elapsed() < window);
+        set_status_led(pin, healthy)?;
+    }
+}
+
+// Same polarity as the board LED
+fn set_status_led(pin: esp_idf_sys::gpio_num_t, on: bool) -> AppResult<()> {
+    esp_idf_sys::esp!(unsafe { esp_idf_sys::gpio_set_level(pin, (on != LED_ACTIVE_LOW) as u32) })?;
+    Ok(())
+}
+// EOF
@@ -0,0 +1,113 @@
+// syslog.rs — ESP logger wrapper with an optional RFC 5424 UDP syslog drain
+
+use std::{
+    net::{ToSocketAddrs, UdpSocket},
+    sync::OnceLock,
+};
+
+use esp_idf_svc::log::EspLogger;
+
+use crate::*;
+
+const SYSLOG_APP_NAME: &str = "esp32multical21";
+// RFC 5424 facility "user-level messages"
+const SYSLOG_FACILITY_USER: u8 = 1;
+
+static LOGGER: SyslogLogger = SyslogLogger {
+    esp: EspLogger::new(),
+    sink: OnceLock::new(),
+};
+
+struct SyslogSink {
+    socket: UdpSocket,
+    hostname: String,
+}
+
+/// Logs everything to the ESP console, and to syslog once the drain is installed.
+struct SyslogLogger {
+    esp: EspLogger,
+    sink: OnceLock<SyslogSink>,
+}
+
+impl Log for SyslogLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.esp.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        self.esp.log(record);
+
+        if let Some(sink) = self.sink.get()
+            && self.enabled(record.metadata())
+        {
+            let pri = SYSLOG_FACILITY_USER * 8 + syslog_severity(record.level());
+            let msg = format!(
+                "<{pri}>1 {} {} {SYSLOG_APP_NAME} - - - {}: {}",
+                Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+                sink.hostname,
+                record.target(),
+                record.args()
+            );
+            // Non-blocking socket: if the send buffer is full the message is dropped
+            sink.socket.send(msg.as_bytes()).ok();
+        }
+    }
+
+    fn flush(&self) {
+        self.esp.flush();
+    }
+}
+
+fn syslog_severity(level: Level) -> u8 {
+    match level {
+        Level::Error => 3,
+        Level::Warn => 4,
+        Level::Info => 6,
+        Level::Debug | Level::Trace => 7,
+    }
+}
+
+/// Install the logger. Use instead of `EspLogger::initialize_default()`.
+pub fn init_logger() {
+    set_logger(&LOGGER).map(|()| LOGGER.esp.initialize()).unwrap();
+}
+
+pub async fn run_syslog(state: Arc<Pin<Box<MyState>>>) -> AppResult<()> {
+    let (host, port) = {
+        let config = state.config.read().await;
+        (config.syslog_host.clone(), config.syslog_port)
+    };
+    if host.is_empty() {
+        info!("Syslog is disabled by configuration.");
+        // we cannot return, otherwise tokio::select in main() will exit
+        loop {
+            sleep(Duration::from_secs(3600)).await;
+        }
+    }
+
+    loop {
+        if *state.net_up.read().await {
+            break;
+        }
+        sleep(Duration::from_secs(5)).await;
+    }
+
+    let addr = (host.as_str(), port)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| AppError::Message(format!("Cannot resolve syslog host {host}")))?;
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.set_nonblocking(true)?;
+    socket.connect(addr)?;
+
+    let hostname = state.hostname.read().await.clone();
+    if LOGGER.sink.set(SyslogSink { socket, hostname }).is_err() {
+        warn!("Syslog drain already installed.");
+    }
+    info!("Syslog enabled: {addr}");
+
+    loop {
+        sleep(Duration::from_secs(3600)).await;
+    }
+}
+// EOF
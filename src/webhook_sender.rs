@@ -0,0 +1,101 @@
+// webhook_sender.rs — HTTP POST of each fresh meter reading as JSON
+
+use embedded_svc::http::client::Client as HttpClient;
+use esp_idf_svc::{http::client::Configuration as HttpConfiguration, io::Write};
+
+use crate::*;
+
+const WEBHOOK_HTTP_TIMEOUT_SECS: u64 = 5;
+
+pub async fn run_webhook(state: Arc<Pin<Box<MyState>>>) -> AppResult<()> {
+    let (url, header, meter_id, m3_decimals) = {
+        let config = state.config.read().await;
+        (
+            config.webhook_url.clone(),
+            config.webhook_header.clone(),
+            config.meter_id.clone(),
+            config.m3_decimals,
+        )
+    };
+
+    if url.is_empty() {
+        info!("Webhook is disabled by configuration.");
+        // we cannot return, otherwise tokio::select in main() will exit
+        loop {
+            sleep(Duration::from_secs(3600)).await;
+        }
+    }
+
+    loop {
+        if *state.net_up.read().await {
+            break;
+        }
+        sleep(Duration::from_secs(5)).await;
+    }
+
+    // Optional extra header as "Name: value", e.g. for an auth token
+    let extra_header = header
+        .split_once(':')
+        .map(|(name, value)| (name.trim().to_lowercase(), value.trim().to_string()));
+    info!("Webhook enabled: {url}");
+
+    let mut reading_rx = state.latest_data.subscribe();
+    loop {
+        sleep(Duration::from_secs(10)).await;
+        if !*state.net_up.read().await {
+            continue;
+        }
+
+        if !reading_rx.has_changed().unwrap_or(false) {
+            continue;
+        }
+        let Some(reading) = reading_rx.borrow_and_update().clone() else {
+            continue;
+        };
+
+        let mut body = reading_value(&reading, m3_decimals);
+        body["meter_id"] = meter_id.clone().into();
+        let body = body.to_string();
+        info!("Webhook sending: {body}");
+        // Failures are only logged, the next fresh reading will be sent normally.
+        match webhook_post(&url, extra_header.as_ref(), &meter_id, &body) {
+            Ok(status) if (200..300).contains(&status) => {}
+            Ok(status) => error!("Webhook POST failed: HTTP {status}"),
+            Err(e) => error!("Webhook POST failed: {e}"),
+        }
+    }
+}
+
+fn webhook_post(url: &str, extra_header: Option<&(String, String)>, meter_id: &str, body: &str) -> AppResult<u16> {
+    let conn = EspHttpConnection::new(&HttpConfiguration {
+        timeout: Some(Duration::from_secs(WEBHOOK_HTTP_TIMEOUT_SECS)),
+        crt_bundle_attach: Some(esp_idf_sys::esp_crt_bundle_attach),
+        ..Default::default()
+    })?;
+    let mut client = HttpClient::wrap(conn);
+
+    let content_length = body.len().to_string();
+    let user_agent = format!("esp32multical21/{FW_VERSION}");
+    let mut headers = vec![
+        ("content-type", "application/json"),
+        ("content-length", content_length.as_str()),
+        ("user-agent", user_agent.as_str()),
+        ("x-meter-id", meter_id),
+    ];
+    if let Some((name, value)) = extra_header {
+        headers.push((name.as_str(), value.as_str()));
+    }
+
+    let mut req = client
+        .post(url, &headers)
+        .map_err(|e| AppError::Message(format!("HTTP request error: {e:?}")))?;
+    req.write_all(body.as_bytes())
+        .map_err(|e| AppError::Message(format!("HTTP write error: {e:?}")))?;
+    req.flush()
+        .map_err(|e| AppError::Message(format!("HTTP write error: {e:?}")))?;
+    let resp = req
+        .submit()
+        .map_err(|e| AppError::Message(format!("HTTP submit error: {e:?}")))?;
+    Ok(resp.status())
+}
+// EOF
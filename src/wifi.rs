@@ -1,10 +1,19 @@
 // wifi.rs
 
+use std::time::Instant;
+
 use embedded_svc::wifi::{AccessPointConfiguration, AuthMethod, ClientConfiguration, Configuration};
 use esp_idf_svc::wifi::WifiEvent;
 
 use crate::*;
 
+const WIFI_CONNECT_TIMEOUT_SECS: u64 = 30;
+const WIFI_BACKOFF_MIN_SECS: u64 = 1;
+const WIFI_BACKOFF_MAX_SECS: u64 = 60;
+// Failed connects after boot before falling back to AP provisioning, regardless of wifi_max_retries
+const WIFI_INITIAL_FAILURES_MAX: u8 = 3;
+const NTP_CHECK_SECS: u64 = 60;
+
 pub struct WifiLoop<'a> {
     pub state: Arc<std::pin::Pin<Box<MyState>>>,
     pub wifi: Option<AsyncWifi<EspWifi<'a>>>,
@@ -30,15 +39,24 @@ impl<'a> WifiLoop<'a> {
             }
         })?;
 
-        let net_if = if self.state.ap_mode {
+        let mut net_if = if self.state.ap_mode {
             EspNetif::new_with_conf(&netif::NetifConfiguration::wifi_default_client())?
         } else {
             let config = self.state.config.read().await.clone();
-            let ipv4_config = if config.v4dhcp {
-                ipv4::ClientConfiguration::DHCP(ipv4::DHCPClientSettings {
-                    hostname: Some("esp32multical21".try_into().unwrap()),
-                })
+            if !config.v4dhcp && !config.static_ipv4_valid() {
+                warn!(
+                    "Invalid static IPv4 config {}/{}, falling back to DHCP",
+                    config.v4addr, config.v4mask
+                );
+            }
+            let ipv4_config = if config.v4dhcp || !config.static_ipv4_valid() {
+                // hostname is set on the netif below, once the MAC is known
+                ipv4::ClientConfiguration::DHCP(ipv4::DHCPClientSettings { hostname: None })
             } else {
+                info!(
+                    "Static IPv4: {}/{} gw {} dns {} {}",
+                    config.v4addr, config.v4mask, config.v4gw, config.dns1, config.dns2
+                );
                 ipv4::ClientConfiguration::Fixed(ipv4::ClientSettings {
                     ip: config.v4addr,
                     subnet: ipv4::Subnet {
@@ -57,10 +75,20 @@ impl<'a> WifiLoop<'a> {
         };
 
         let mac = net_if.get_mac()?;
-        *self.state.my_id.write().await = format!(
+        let my_id = format!(
             "esp32multical21_{:02X}{:02X}{:02X}{:02X}{:02X}{:02X}",
             mac[0], mac[1], mac[2], mac[3], mac[4], mac[5],
         );
+
+        // Default hostname is my_id made DNS-safe
+        let hostname = match self.state.config.read().await.hostname.clone() {
+            h if h.is_empty() => my_id.to_lowercase().replace('_', "-"),
+            h => h,
+        };
+        info!("Hostname: {hostname}");
+        net_if.set_hostname(&hostname)?;
+        *self.state.hostname.write().await = hostname;
+        *self.state.my_id.write().await = my_id;
         *self.state.my_mac.write().await = mac;
         *self.state.my_mac_s.write().await = format!(
             "{:02X}:{:02X}:{:02X}:{:02X}:{:02X}:{:02X}",
@@ -91,7 +119,9 @@ impl<'a> WifiLoop<'a> {
 
         if let Err(e) = Box::pin(self.initial_connect()).await {
             error!("WiFi connection failed: {e:?}");
-            error!("Resetting...");
+            // Wrong or stale credentials: offer the setup page, the next boot after it tries again
+            error!("Resetting into AP mode for WiFi setup...");
+            self.state.request_ap_mode_on_next_boot(AP_MODE_PROVISION).await?;
             sleep(Duration::from_secs(5)).await;
             esp_idf_hal::reset::restart();
         }
@@ -104,25 +134,49 @@ impl<'a> WifiLoop<'a> {
         *self.state.ip_addr.write().await = ip_info.ip;
         *self.state.ping_ip.write().await = Some(ip_info.subnet.gateway);
 
-        // wait for NTP synchronization to complete
-        let ntp = sntp::EspSntp::new_default()?;
+        let (ntp_server, require_ntp, ntp_timeout_secs) = {
+            let config = self.state.config.read().await;
+            (config.ntp_server.clone(), config.require_ntp, config.ntp_timeout_secs)
+        };
+        if !require_ntp {
+            // Air-gapped installs: readings keep uptime-relative timestamps for good
+            info!("NTP disabled, using uptime-relative timestamps.");
+            *self.state.net_up.write().await = true;
+            return Box::pin(self.stay_connected()).await;
+        }
+
+        // wait for NTP synchronization to complete, but do not insist on it
+        let ntp = if ntp_server.is_empty() {
+            sntp::EspSntp::new_default()?
+        } else {
+            info!("NTP server: {ntp_server}");
+            let mut conf = sntp::SntpConf::default();
+            conf.servers[0] = ntp_server.as_str();
+            sntp::EspSntp::new(&conf)?
+        };
         sleep(Duration::from_secs(5)).await;
         let mut cnt = 0;
         loop {
-            if Utc::now().year() > 2020 && ntp.get_sync_status() == sntp::SyncStatus::Completed {
+            if Utc::now().timestamp() > NTP_VALID_EPOCH && ntp.get_sync_status() == sntp::SyncStatus::Completed {
+                info!("NTP ok.");
+                self.state.set_ntp_synced().await;
                 break;
             }
 
-            if cnt > 120 {
-                esp_idf_hal::reset::restart();
+            if cnt >= ntp_timeout_secs {
+                warn!("NTP not synced yet, using uptime-relative timestamps until it is.");
+                break;
             }
             cnt += 1;
             sleep(Duration::from_millis(1000)).await;
         }
-        info!("NTP ok.");
 
         *self.state.net_up.write().await = true;
-        Box::pin(self.stay_connected()).await
+        let state = self.state.clone();
+        tokio::select! {
+            res = Box::pin(self.stay_connected()) => res,
+            res = Box::pin(ntp_monitor(state, &ntp)) => res,
+        }
     }
 
     async fn run_ap_mode(&mut self) -> AppResult<()> {
@@ -231,8 +285,14 @@ impl<'a> WifiLoop<'a> {
         info!("WiFi driver starting...");
         Box::pin(wifi.start()).await?;
 
-        info!("WiFi disabling modem power save...");
-        esp_idf_sys::esp!(unsafe { esp_idf_sys::esp_wifi_set_ps(esp_idf_sys::wifi_ps_type_t_WIFI_PS_NONE) })?;
+        if config.low_power {
+            // The radio sleeps between DTIM beacons, the AP buffers traffic meanwhile
+            info!("WiFi enabling max modem power save...");
+            esp_idf_sys::esp!(unsafe { esp_idf_sys::esp_wifi_set_ps(esp_idf_sys::wifi_ps_type_t_WIFI_PS_MAX_MODEM) })?;
+        } else {
+            info!("WiFi disabling modem power save...");
+            esp_idf_sys::esp!(unsafe { esp_idf_sys::esp_wifi_set_ps(esp_idf_sys::wifi_ps_type_t_WIFI_PS_NONE) })?;
+        }
 
         Ok(())
     }
@@ -246,6 +306,10 @@ impl<'a> WifiLoop<'a> {
     }
 
     async fn do_connect_loop(&mut self, initial: bool) -> AppResult<()> {
+        let (max_retries, reboot_secs) = {
+            let config = self.state.config.read().await;
+            (config.wifi_max_retries, config.wifi_reboot_mins as u64 * 60)
+        };
         let wifi = self.wifi.as_mut().unwrap();
         loop {
             // Wait for disconnect before trying to connect again.  This loop ensures
@@ -254,36 +318,84 @@ impl<'a> WifiLoop<'a> {
             // a proper Wi-Fi event loop without a robust async runtime.  Fortunately, we can do it
             // now!
             Box::pin(wifi.wifi_wait(|w| w.is_up(), None)).await.ok();
+            if !initial {
+                warn!("WiFi connection lost.");
+                *self.state.net_up.write().await = false;
+            }
 
-            info!("WiFi connecting...");
-            Box::pin(wifi.connect()).await.ok();
-
-            // Apply the 30s timeout here — this is the call that actually waits for the
-            // connection. The previous wifi_wait returns immediately at boot since WiFi
-            // isn't up yet, so the timeout there was effectively useless.
-            let timeout = if initial { Some(Duration::from_secs(30)) } else { None };
-            info!("WiFi waiting for association...");
-            match Box::pin(wifi.ip_wait_while(|w| w.is_up().map(|s| !s), timeout)).await {
-                Ok(_) => {}
-                Err(e) => {
-                    error!("WiFi error: {e:?}");
-
-                    // only exit here if this is initial connection
-                    // otherwise, keep trying
-                    if initial {
-                        return Err(e.into());
+            let down_since = Instant::now();
+            let mut failures: u8 = 0;
+            let mut backoff = WIFI_BACKOFF_MIN_SECS;
+            loop {
+                info!("WiFi connecting...");
+                Box::pin(wifi.connect()).await.ok();
+
+                // Apply the timeout here — this is the call that actually waits for the
+                // connection. The previous wifi_wait returns immediately at boot since WiFi
+                // isn't up yet, so a timeout there would be useless.
+                info!("WiFi waiting for association...");
+                let timeout = Some(Duration::from_secs(WIFI_CONNECT_TIMEOUT_SECS));
+                match Box::pin(wifi.ip_wait_while(|w| w.is_up().map(|s| !s), timeout)).await {
+                    Ok(_) => break,
+                    Err(e) => {
+                        failures = failures.saturating_add(1);
+                        let down_secs = down_since.elapsed().as_secs();
+                        error!("WiFi error: {e:?} ({failures} consecutive failures, down for {down_secs} s)");
+
+                        // Credentials that never worked since boot are likely wrong, so the setup
+                        // page comes up soon instead of after the reconnect allowance below
+                        if initial && failures >= WIFI_INITIAL_FAILURES_MAX {
+                            return Err(e.into());
+                        }
+                        // 0 retries means keep trying forever. A slow or congested AP gets
+                        // the full downtime allowance before we give up and reboot.
+                        if !initial && max_retries > 0 && failures >= max_retries && down_secs >= reboot_secs {
+                            error!("WiFi reconnect failed {failures} times in {down_secs} s, rebooting.");
+                            sleep(Duration::from_secs(2)).await;
+                            esp_idf_hal::reset::restart();
+                        }
                     }
                 }
+
+                info!("WiFi retrying in {backoff} s...");
+                Box::pin(wifi.disconnect()).await.ok();
+                sleep(Duration::from_secs(backoff)).await;
+                backoff = (backoff * 2).min(WIFI_BACKOFF_MAX_SECS);
             }
 
             info!("WiFi connected.");
             if initial {
                 return Ok(());
             }
+            // net_up is set by run() after NTP on the initial connection
+            *self.state.net_up.write().await = true;
             sleep(Duration::from_secs(5)).await;
         }
     }
 }
+
+/// Keep an eye on SNTP: catch a late first sync, and log the clock drift corrected by each resync.
+async fn ntp_monitor(state: Arc<Pin<Box<MyState>>>, ntp: &sntp::EspSntp<'_>) -> AppResult<()> {
+    let mut last_check = Instant::now();
+    let mut last_check_ms = Utc::now().timestamp_millis();
+    loop {
+        sleep(Duration::from_secs(NTP_CHECK_SECS)).await;
+        // The status reads as completed once per sync, then goes back to reset
+        if ntp.get_sync_status() == sntp::SyncStatus::Completed {
+            let now_ms = Utc::now().timestamp_millis();
+            if *state.ntp_synced.read().await {
+                let expected_ms = last_check_ms + last_check.elapsed().as_millis() as i64;
+                info!("NTP resync, clock corrected by {} ms.", now_ms - expected_ms);
+            } else {
+                info!("NTP ok, correcting earlier timestamps.");
+                state.set_ntp_synced().await;
+            }
+        }
+        last_check = Instant::now();
+        last_check_ms = Utc::now().timestamp_millis();
+    }
+}
+
 fn wifi_disconnect_reason(r: u16) -> &'static str {
     match r {
         1 => "UNSPECIFIED",
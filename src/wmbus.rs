@@ -8,6 +8,22 @@ use ctr::{
 
 use crate::*;
 
+// CI values at raw[10]
+/// ELL-I: [10] CI  [11] CC  [12] ACC, then the plaintext application layer
+const CI_ELL_SHORT: u8 = 0x8C;
+/// ELL-II: [10] CI  [11] CC  [12] ACC  [13..17] SN, then the payload CRC and the application layer,
+/// AES-128-CTR encrypted as selected by the SN
+const CI_ELL_SN: u8 = 0x8D;
+/// Application layer straight after the link layer, no ELL and no encryption
+const CI_PLAIN: [u8; 2] = [0x78, 0x79];
+const ELL_SHORT_HEADER_LEN: usize = 13;
+const ELL_SN_HEADER_LEN: usize = 17;
+const PLAIN_HEADER_LEN: usize = 10;
+/// ELL-II session number bits 29..31: encryption mode, 0 = none, 1 = AES-128-CTR
+const SN_ENC_SHIFT: u32 = 29;
+const SN_ENC_NONE: u32 = 0;
+const SN_ENC_AES_CTR: u32 = 1;
+
 /// CRC-16 EN 13757 (polynomial 0x3D65, init 0x0000, final XOR 0xFFFF, no reflection)
 pub fn crc16_en13757(data: &[u8]) -> u16 {
     let mut crc: u16 = 0x0000;
@@ -24,6 +40,65 @@ pub fn crc16_en13757(data: &[u8]) -> u16 {
     crc ^ 0xFFFF
 }
 
+/// Reason a received frame did not yield a meter reading.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameError {
+    TooShort,
+    Truncated,
+    NotData,
+    MeterId,
+    UnsupportedCi,
+    Decrypt,
+    Crc,
+    /// CRC failed and the decrypted CI is garbage too, most likely a wrong `meter_key`
+    KeySuspect,
+    Payload,
+}
+
+/// How a frame's application layer is protected, as told by its CI and ELL-II session number.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SecurityMode {
+    /// CI 0x78/0x79, no ELL
+    Plain,
+    /// CI 0x8C, ELL-I never encrypts
+    EllShortPlain,
+    /// CI 0x8D with encryption mode 0 in the SN
+    EllSnPlain,
+    /// CI 0x8D with encryption mode 1 in the SN, the Multical 21 default
+    EllSnAesCtr,
+    /// CI 0x8D with an encryption mode we cannot decrypt
+    EllSnUnsupported(u8),
+}
+
+impl SecurityMode {
+    pub fn label(&self) -> String {
+        match self {
+            Self::Plain => "plaintext".into(),
+            Self::EllShortPlain => "ELL-I, plaintext".into(),
+            Self::EllSnPlain => "ELL-II, plaintext".into(),
+            Self::EllSnAesCtr => "ELL-II, AES-128-CTR".into(),
+            Self::EllSnUnsupported(mode) => format!("ELL-II, unsupported encryption mode {mode}"),
+        }
+    }
+}
+
+/// Security mode of the frame from its CI (raw[10]) and ELL-II SN (raw[13..17]), `None` for an unknown CI.
+pub fn frame_security(raw: &[u8]) -> Option<SecurityMode> {
+    match frame_ci(raw)? {
+        CI_ELL_SN => {
+            let sn = u32::from_le_bytes(raw.get(13..17)?.try_into().ok()?);
+            Some(match sn >> SN_ENC_SHIFT {
+                SN_ENC_AES_CTR => SecurityMode::EllSnAesCtr,
+                SN_ENC_NONE => SecurityMode::EllSnPlain,
+                mode => SecurityMode::EllSnUnsupported(mode as u8),
+            })
+        }
+        CI_ELL_SHORT => Some(SecurityMode::EllShortPlain),
+        ci if CI_PLAIN.contains(&ci) => Some(SecurityMode::Plain),
+        _ => None,
+    }
+}
+
 /// Check if payload meter ID matches expected meter ID.
 /// Meter serial is at payload[4..8] in little-endian BCD, reversed vs printed serial.
 pub fn check_meter_id(payload: &[u8], meter_id: &[u8; 4]) -> bool {
@@ -34,6 +109,25 @@ pub fn check_meter_id(payload: &[u8], meter_id: &[u8; 4]) -> bool {
     payload[4] == meter_id[0] && payload[5] == meter_id[1] && payload[6] == meter_id[2] && payload[7] == meter_id[3]
 }
 
+/// Extract the wire-order meter serial from the A-field (raw[4..8]).
+pub fn frame_meter_id(raw: &[u8]) -> Option<[u8; 4]> {
+    raw.get(4..8)?.try_into().ok()
+}
+
+/// Extract the CI (control information) field of the frame, raw[10].
+pub fn frame_ci(raw: &[u8]) -> Option<u8> {
+    raw.get(10).copied()
+}
+
+/// Extract the ACC (access number) byte of an ELL header, incremented by the meter per transmission.
+/// Frames without an ELL header carry no ACC here.
+pub fn frame_acc(raw: &[u8]) -> Option<u8> {
+    match frame_ci(raw)? {
+        CI_ELL_SHORT | CI_ELL_SN => raw.get(12).copied(),
+        _ => None,
+    }
+}
+
 /// Construct AES-128-CTR IV for ELL-II (CI=0x8D) from wMBus frame header.
 /// IV layout (16 bytes):
 ///   [0..2]   = manufacturer (M-field, raw[2..4])
@@ -50,44 +144,100 @@ fn build_iv(raw: &[u8]) -> [u8; 16] {
     iv
 }
 
-/// Decrypt ELL-II wMBus payload using AES-128-CTR.
-/// For CI=0x8D: encrypted data starts at raw[17], length = L - 2 - 16 bytes.
-fn decrypt_payload(raw: &[u8], key: &[u8; 16]) -> Option<Vec<u8>> {
-    let l_field = raw[0] as usize;
-    // Encrypted data: raw[17..L-1] (skip 17-byte header, exclude 2 trailing bytes)
-    // Matches reference: cipherLength = length - 2 - 16
-    let encrypted_start = 17;
-    let encrypted_end = l_field.checked_sub(1)?;
-
-    if encrypted_start >= encrypted_end || encrypted_end > raw.len() {
+/// Payload after a `header_len` byte header: raw[header_len..L-1], excluding the 2 trailing bytes.
+/// Matches reference for CI=0x8D: cipherLength = length - 2 - 16
+fn frame_payload(raw: &[u8], header_len: usize) -> Option<&[u8]> {
+    let end = (raw[0] as usize).checked_sub(1)?;
+    if header_len >= end || end > raw.len() {
         warn!(
-            "wMBus: No encrypted data (start={}, end={}, len={})",
-            encrypted_start,
-            encrypted_end,
+            "wMBus: No payload data (start={}, end={}, len={})",
+            header_len,
+            end,
             raw.len()
         );
         return None;
     }
+    Some(&raw[header_len..end])
+}
 
+/// Decrypt ELL-II wMBus payload using AES-128-CTR.
+/// For CI=0x8D: encrypted data starts at raw[17], length = L - 2 - 16 bytes.
+fn decrypt_payload(raw: &[u8], key: &[u8; 16]) -> Option<Vec<u8>> {
+    let mut decrypted = frame_payload(raw, ELL_SN_HEADER_LEN)?.to_vec();
     let iv = build_iv(raw);
-    let mut decrypted = raw[encrypted_start..encrypted_end].to_vec();
-
     let mut cipher = Ctr128BE::<Aes128>::new(key.into(), &iv.into());
     cipher.apply_keystream(&mut decrypted);
 
     Some(decrypted)
 }
 
-/// Full wMBus frame parsing pipeline: check meter ID → decrypt → parse.
-pub fn parse_frame(raw: &[u8], meter_id: &[u8; 4], key: &[u8; 16]) -> Option<MeterReading> {
+/// Application layer that came without a payload CRC, laid out like a decrypted ELL-II payload
+/// (`[CRC-16] [CI] ...`) so decoders see one format. The CRC is computed here, so it cannot catch bit errors.
+fn with_payload_crc(app: &[u8]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(2 + app.len());
+    data.extend_from_slice(&crc16_en13757(app).to_le_bytes());
+    data.extend_from_slice(app);
+    data
+}
+
+/// Decoder input for the frame's CI: `[CRC-16] [CI] ...`, decrypted when the frame is encrypted.
+fn frame_data(raw: &[u8], key: &[u8; 16]) -> Result<Vec<u8>, FrameError> {
+    let ci = raw[10];
+    let Some(mode) = frame_security(raw) else {
+        warn!("wMBus: Unsupported CI field: 0x{ci:02X}");
+        return Err(FrameError::UnsupportedCi);
+    };
+    match mode {
+        SecurityMode::EllSnAesCtr => {
+            info!("wMBus: CI=0x{ci:02X} {}", mode.label());
+            decrypt_payload(raw, key).ok_or(FrameError::Decrypt)
+        }
+        SecurityMode::EllSnPlain => {
+            info!("wMBus: CI=0x{ci:02X} {}", mode.label());
+            frame_payload(raw, ELL_SN_HEADER_LEN)
+                .map(<[u8]>::to_vec)
+                .ok_or(FrameError::Decrypt)
+        }
+        SecurityMode::EllSnUnsupported(_) => {
+            warn!("wMBus: CI=0x{ci:02X} {}", mode.label());
+            Err(FrameError::UnsupportedCi)
+        }
+        SecurityMode::EllShortPlain => {
+            info!("wMBus: CI=0x{ci:02X} {}", mode.label());
+            frame_payload(raw, ELL_SHORT_HEADER_LEN)
+                .map(with_payload_crc)
+                .ok_or(FrameError::Decrypt)
+        }
+        SecurityMode::Plain => {
+            info!("wMBus: CI=0x{ci:02X} {}", mode.label());
+            frame_payload(raw, PLAIN_HEADER_LEN)
+                .map(with_payload_crc)
+                .ok_or(FrameError::Decrypt)
+        }
+    }
+}
+
+/// Full wMBus frame parsing pipeline: check meter ID → pick decoder → decrypt by CI → parse.
+pub fn parse_frame(raw: &[u8], meter_id: &[u8; 4], key: &[u8; 16]) -> Result<MeterReading, FrameError> {
     if raw.len() < 18 {
         warn!("wMBus: Frame too short ({} bytes)", raw.len());
-        return None;
+        return Err(FrameError::TooShort);
+    }
+
+    // The L-field counts every byte after itself, the trailing CRC included
+    let l_field = raw[0] as usize;
+    if raw.len() < l_field + 1 {
+        warn!(
+            "wMBus: Truncated frame, L-field {l_field} needs {} bytes, got {}",
+            l_field + 1,
+            raw.len()
+        );
+        return Err(FrameError::Truncated);
     }
 
     let c_field = raw[1];
     if c_field != 0x44 {
-        return None;
+        return Err(FrameError::NotData);
     }
 
     if !check_meter_id(raw, meter_id) {
@@ -95,17 +245,102 @@ pub fn parse_frame(raw: &[u8], meter_id: &[u8; 4], key: &[u8; 16]) -> Option<Met
             "wMBus: Ignoring meter {:02X}{:02X}{:02X}{:02X}",
             raw[7], raw[6], raw[5], raw[4]
         );
-        return None;
+        return Err(FrameError::MeterId);
     }
 
-    // CI=0x8D: ELL-II (encrypted)
-    //   [10] CI  [11] CC  [12] ACC  [13..17] SN (4 bytes)  [17+] encrypted
-    if raw[10] != 0x8D {
-        warn!("wMBus: Unsupported CI field: 0x{:02X}", raw[10]);
-        return None;
+    let data = frame_data(raw, key)?;
+    let header = FrameHeader::from_raw(raw).ok_or(FrameError::TooShort)?;
+    let decoder = find_decoder(&header).unwrap_or_else(|| {
+        warn!(
+            "wMBus: No decoder for {} {}, trying {}",
+            header.manufacturer_code(),
+            header.device_type_s(),
+            FALLBACK_DECODER.name()
+        );
+        FALLBACK_DECODER
+    });
+    info!("wMBus: Decoding as {}", decoder.name());
+    decoder.decode(&data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Kamstrup Multical 21 long frame from the wmbusmeters test suite, meter 76348799, total 6.408 m³,
+    /// with the L-field and the trailing CRC of frame format B added as the CC1101 receives it
+    const CAPTURED_FRAME: &str =
+        "2C442D2C998734761B168D2091D37CAC21E1D68CDAFFCD3DC452BD802913FF7B1706CA9E355D6C2701CC2427BD";
+    const CAPTURED_KEY: &str = "28F64A24988064A079AA2C807D6102AE";
+    const CAPTURED_METER_ID: [u8; 4] = [0x99, 0x87, 0x34, 0x76];
+
+    fn captured() -> (Vec<u8>, [u8; 16]) {
+        (
+            parse_hex(CAPTURED_FRAME).unwrap(),
+            parse_meter_key(CAPTURED_KEY).unwrap(),
+        )
     }
 
-    let decrypted = decrypt_payload(raw, key)?;
-    parse_multical21(&decrypted)
+    #[test]
+    fn crc16_check_value() {
+        assert_eq!(crc16_en13757(b"123456789"), 0xC2B7);
+        assert_eq!(crc16_en13757(&[]), 0xFFFF);
+    }
+
+    #[test]
+    fn iv_from_header() {
+        let (raw, _) = captured();
+        let iv = build_iv(&raw);
+        assert_eq!(
+            iv,
+            [
+                0x2D, 0x2C, 0x99, 0x87, 0x34, 0x76, 0x1B, 0x16, 0x20, 0xD3, 0x7C, 0xAC, 0x21, 0x00, 0x00, 0x00
+            ]
+        );
+    }
+
+    #[test]
+    fn decrypt_captured_payload() {
+        let (raw, key) = captured();
+        assert_eq!(frame_security(&raw), Some(SecurityMode::EllSnAesCtr));
+        let data = decrypt_payload(&raw, &key).unwrap();
+        assert_eq!(
+            data,
+            parse_hex("576C7802FF207100041308190000441308190000615B7F616713").unwrap()
+        );
+        assert_eq!(crc16_en13757(&data[2..]).to_le_bytes(), [data[0], data[1]]);
+    }
+
+    #[test]
+    fn parse_captured_frame() {
+        let (raw, key) = captured();
+        let reading = parse_frame(&raw, &CAPTURED_METER_ID, &key).unwrap();
+        assert_eq!(reading.total_l, 6408);
+        assert_eq!(reading.month_start_l, 6408);
+        assert_eq!(reading.flow_temp, 127);
+        assert_eq!(reading.ambient_temp, 19);
+        assert_eq!(reading.info_codes, 0x71);
+    }
+
+    #[test]
+    fn parse_frame_rejects() {
+        let (raw, key) = captured();
+        assert_eq!(
+            parse_frame(&raw[..17], &CAPTURED_METER_ID, &key).unwrap_err(),
+            FrameError::TooShort
+        );
+        assert_eq!(
+            parse_frame(&raw[..40], &CAPTURED_METER_ID, &key).unwrap_err(),
+            FrameError::Truncated
+        );
+        assert_eq!(
+            parse_frame(&raw, &[0x11, 0x22, 0x33, 0x44], &key).unwrap_err(),
+            FrameError::MeterId
+        );
+        assert_eq!(
+            parse_frame(&raw, &CAPTURED_METER_ID, &[0; 16]).unwrap_err(),
+            FrameError::KeySuspect
+        );
+    }
 }
 // EOF
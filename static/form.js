@@ -4,14 +4,53 @@ var postCfgDataAsJson =
            }) => {
         const formObj = Object.fromEntries(formData.entries());
         formObj.wifi_wpa2ent = (formObj.wifi_wpa2ent === "on");
+        formObj.wifi_max_retries = parseInt(formObj.wifi_max_retries);
+        formObj.wifi_reboot_mins = parseInt(formObj.wifi_reboot_mins);
         formObj.v4dhcp = (formObj.v4dhcp === "on");
         formObj.v4mask = parseInt(formObj.v4mask);
+        formObj.require_ntp = (formObj.require_ntp === "on");
+        formObj.ntp_timeout_secs = parseInt(formObj.ntp_timeout_secs);
+        formObj.tz_offset_minutes = parseInt(formObj.tz_offset_minutes);
+        formObj.syslog_port = parseInt(formObj.syslog_port);
+        formObj.esphome_port = parseInt(formObj.esphome_port);
+        formObj.ping_enable = (formObj.ping_enable === "on");
+        formObj.ping_reboot = (formObj.ping_reboot === "on");
+        formObj.radio_freq_hz = parseInt(formObj.radio_freq_hz);
+        formObj.spi_baud_khz = parseInt(formObj.spi_baud_khz);
+        formObj.dedup_window_secs = parseInt(formObj.dedup_window_secs);
+        formObj.radio_watchdog_secs = parseInt(formObj.radio_watchdog_secs);
+        formObj.reset_hold_secs = parseInt(formObj.reset_hold_secs);
+        formObj.radio_max_restarts = parseInt(formObj.radio_max_restarts);
+        formObj.radio_quiet_alarm_secs = parseInt(formObj.radio_quiet_alarm_secs);
+        formObj.status_led_gpio = parseInt(formObj.status_led_gpio);
+        formObj.capture_depth = parseInt(formObj.capture_depth);
+        formObj.m3_decimals = parseInt(formObj.m3_decimals);
+        formObj.temp_report_delta = parseInt(formObj.temp_report_delta);
         formObj.esphome_enable = (formObj.esphome_enable === "on");
         formObj.mqtt_enable = (formObj.mqtt_enable === "on");
+        formObj.mqtt_tls_insecure = (formObj.mqtt_tls_insecure === "on");
+        formObj.mqtt_retain_meter = (formObj.mqtt_retain_meter === "on");
+        formObj.mqtt_retain_uptime = (formObj.mqtt_retain_uptime === "on");
+        formObj.mqtt_qos = parseInt(formObj.mqtt_qos);
+        formObj.mqtt_clean_session = (formObj.mqtt_clean_session === "on");
+        formObj.low_power = (formObj.low_power === "on");
+        formObj.debug_inject = (formObj.debug_inject === "on");
         if (!formObj.wifi_username) formObj.wifi_username = "";
+        if (!formObj.hostname) formObj.hostname = "";
+        if (!formObj.http_user) formObj.http_user = "";
+        if (!formObj.http_pass) formObj.http_pass = "";
+        if (!formObj.cors_origin) formObj.cors_origin = "";
+        if (!formObj.syslog_host) formObj.syslog_host = "";
+        if (!formObj.ping_host) formObj.ping_host = "";
+        if (!formObj.webhook_url) formObj.webhook_url = "";
+        if (!formObj.webhook_header) formObj.webhook_header = "";
         if (!formObj.wifi_wpa2ent) formObj.wifi_username = "";
+        if (!formObj.esphome_psk) formObj.esphome_psk = "";
+        if (!formObj.esphome_overrides) formObj.esphome_overrides = "";
         if (!formObj.meter_id) formObj.meter_id = "";
+        if (!formObj.radio_registers) formObj.radio_registers = "";
         if (!formObj.meter_key) formObj.meter_key = "";
+        formObj.volume_unit = (formObj.volume_unit || "m3").trim().toLowerCase();
         const formDataJsonString = JSON.stringify(formObj);
 
         const fetchOptions = {
@@ -22,7 +61,7 @@ var postCfgDataAsJson =
         const response = await fetch(url, fetchOptions);
         const payload = await response.json();
         if (!response.ok || payload.ok === false) {
-            throw new Error(payload.message || "Config update failed");
+            throw new Error(payload.error || "Config update failed");
         }
         return payload;
     }
@@ -31,6 +70,7 @@ var handleCfgSubmit = async (event) => {
     event.preventDefault();
     const form = event.currentTarget;
     const url = form.action;
+    const status = document.getElementById("cfg_status");
 
     try {
         const formData = new FormData(form);
@@ -40,14 +80,48 @@ var handleCfgSubmit = async (event) => {
         console.log({
             responseData
         });
+        status.className = "status ok";
+        status.textContent = responseData.message || "Config saved, rebooting";
     } catch (error) {
         console.error(error);
+        status.className = "status error";
+        status.textContent = error.message;
+    }
+}
+
+var handleMeterKeySubmit = async (event) => {
+    event.preventDefault();
+    const form = event.currentTarget;
+    const status = document.getElementById("meterkey_status");
+
+    try {
+        const formObj = Object.fromEntries(new FormData(form).entries());
+        const response = await fetch(form.action, {
+            method: "POST", mode: 'cors', keepalive: false, headers: {
+                'Accept': 'application/json', 'Content-Type': 'application/json',
+            }, body: JSON.stringify({
+                meter_id: formObj.meter_id || "", meter_key: formObj.meter_key || "",
+            }),
+        });
+        const payload = await response.json();
+        if (!response.ok || payload.ok === false) {
+            throw new Error(payload.error || "Meter key update failed");
+        }
+        form.reset();
+        status.className = "status ok";
+        status.textContent = payload.message || "Meter key saved";
+    } catch (error) {
+        console.error(error);
+        status.className = "status error";
+        status.textContent = error.message;
     }
 }
 
 document.addEventListener("DOMContentLoaded", function () {
     document.querySelector("form[name='esp32cfg']")
         .addEventListener("submit", handleCfgSubmit);
+    document.querySelector("form[name='esp32meterkey']")
+        .addEventListener("submit", handleMeterKeySubmit);
 });
 
 async function update_uptime() {
@@ -57,6 +131,9 @@ async function update_uptime() {
     const response = await fetch(url);
     const json = await response.json();
     o.innerHTML = "<p>Uptime: " + json.uptime + " s</p>";
+    document.getElementById("radio_status").textContent = (json.radio_ok === false)
+        ? "CC1101 radio not detected, check the wiring. No meter data will be received."
+        : "";
 }
 
 async function update_meter() {
@@ -68,6 +145,7 @@ async function update_meter() {
             o.innerHTML = "<table>" +
                 "<tr><td>Total:</td><td>" + json.total_m3.toFixed(3) + " m3 (" + json.total_l + " l)</td></tr>" +
                 "<tr><td>Month start:</td><td>" + json.month_start_m3.toFixed(3) + " m3 (" + json.month_start_l + " l)</td></tr>" +
+                "<tr><td>This month:</td><td>" + json.month_consumption_l + " l</td></tr>" +
                 "<tr><td>Flow temp:</td><td>" + json.flow_temp + " &deg;C</td></tr>" +
                 "<tr><td>Ambient temp:</td><td>" + json.ambient_temp + " &deg;C</td></tr>" +
                 "<tr><td>Info codes:</td><td>0x" + json.info_codes.toString(16).padStart(2, '0') + "</td></tr>" +
@@ -85,5 +163,6 @@ async function update_meter() {
 function onLoad() {
     setInterval(update_uptime, 30e3);
     setInterval(update_meter, 30e3);
+    update_uptime();
     update_meter();
 }